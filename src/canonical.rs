@@ -0,0 +1,148 @@
+use std::fmt::Write;
+
+use xml::reader::XmlEvent;
+use xml::ParserConfig;
+
+/// Attributes that carry timestamps rather than content (e.g. XEP-0203 delays).
+const TIMESTAMP_ATTRIBUTES: [&str; 2] = ["stamp", "timestamp"];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CanonicalizeOptions {
+    pub ignore_ids: bool,
+    pub ignore_timestamps: bool,
+}
+
+/// Returns a normalized representation of `xml` that is independent of namespace prefixes,
+/// attribute order and insignificant whitespace.
+pub fn canonicalize(xml: &str, options: CanonicalizeOptions) -> Result<String, xml::reader::Error> {
+    let reader = ParserConfig::new()
+        .trim_whitespace(true)
+        .ignore_comments(true)
+        .create_reader(xml.as_bytes());
+
+    let mut canonical = String::new();
+
+    for event in reader {
+        match event? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let mut attributes = attributes
+                    .into_iter()
+                    .filter(|attr| {
+                        let name = attr.name.local_name.as_str();
+                        !(options.ignore_ids && name == "id"
+                            || options.ignore_timestamps && TIMESTAMP_ATTRIBUTES.contains(&name))
+                    })
+                    .map(|attr| {
+                        (
+                            format!(
+                                "{{{}}}{}",
+                                attr.name.namespace.unwrap_or_default(),
+                                attr.name.local_name
+                            ),
+                            attr.value,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                attributes.sort();
+
+                _ = write!(
+                    canonical,
+                    "<{{{}}}{}",
+                    name.namespace.unwrap_or_default(),
+                    name.local_name
+                );
+                for (name, value) in attributes {
+                    _ = write!(canonical, " {name}={value:?}");
+                }
+                canonical.push('>');
+            }
+            XmlEvent::EndElement { .. } => canonical.push_str("</>"),
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => _ = write!(canonical, "{text:?}"),
+            _ => (),
+        }
+    }
+
+    Ok(canonical)
+}
+
+/// 64-bit FNV-1a hash. Unlike `DefaultHasher` its output is stable across builds, so hashes
+/// can be compared between runs and files.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(xml: &str, options: CanonicalizeOptions) -> u64 {
+        content_hash(canonicalize(xml, options).unwrap().as_bytes())
+    }
+
+    #[test]
+    fn canonical_form_ignores_prefixes_attribute_order_and_whitespace() {
+        let options = CanonicalizeOptions::default();
+        let a =
+            "<iq xmlns='jabber:client' type='get' id='1'><query xmlns='jabber:iq:roster'/></iq>";
+        let b = "<c:iq xmlns:c='jabber:client' id='1' type='get'>\n  \
+                 <r:query xmlns:r='jabber:iq:roster'/>\n</c:iq>";
+        assert_eq!(
+            canonicalize(a, options).unwrap(),
+            canonicalize(b, options).unwrap()
+        );
+    }
+
+    #[test]
+    fn canonical_form_keeps_namespaces_values_and_text() {
+        let options = CanonicalizeOptions::default();
+        let iq = "<iq xmlns='jabber:client' type='get'/>";
+        assert_ne!(
+            hash(iq, options),
+            hash("<iq xmlns='jabber:server' type='get'/>", options)
+        );
+        assert_ne!(
+            hash(iq, options),
+            hash("<iq xmlns='jabber:client' type='set'/>", options)
+        );
+        assert_ne!(
+            hash("<message><body>hi</body></message>", options),
+            hash("<message><body>ho</body></message>", options)
+        );
+    }
+
+    #[test]
+    fn ids_and_timestamps_are_ignored_only_when_asked() {
+        let a = "<message id='a'><delay xmlns='urn:xmpp:delay' stamp='2024-01-01T00:00:00Z'/></message>";
+        let b = "<message id='b'><delay xmlns='urn:xmpp:delay' stamp='2024-01-02T00:00:00Z'/></message>";
+        let ignore_ids = CanonicalizeOptions {
+            ignore_ids: true,
+            ..Default::default()
+        };
+        let ignore_both = CanonicalizeOptions {
+            ignore_ids: true,
+            ignore_timestamps: true,
+        };
+        assert_ne!(hash(a, Default::default()), hash(b, Default::default()));
+        assert_ne!(hash(a, ignore_ids), hash(b, ignore_ids));
+        assert_eq!(hash(a, ignore_both), hash(b, ignore_both));
+    }
+
+    #[test]
+    fn malformed_xml_is_an_error() {
+        assert!(canonicalize("<iq><query></iq>", Default::default()).is_err());
+    }
+
+    #[test]
+    fn content_hash_is_stable() {
+        // FNV-1a test vectors
+        assert_eq!(content_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(content_hash(b"a"), 0xaf63dc4c8601ec8c);
+    }
+}
@@ -0,0 +1,126 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use syntect::highlighting::Theme;
+use syntect::parsing::SyntaxSet;
+
+use crate::html::stanza_header_html;
+use crate::log_message::LogMessage;
+use crate::theme::html_document_header;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ExportFormat {
+    /// Pretty-printed stanzas with direction comments, like `print`.
+    #[default]
+    Xml,
+    /// Highlighted HTML document.
+    Html,
+    /// One JSON object per line, in the same shape as the log file.
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] =
+        [ExportFormat::Xml, ExportFormat::Html, ExportFormat::Ndjson];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            ExportFormat::Xml => "XML",
+            ExportFormat::Html => "HTML",
+            ExportFormat::Ndjson => "NDJSON",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Xml => "xml",
+            ExportFormat::Html => "html",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Writes `messages` to `path` in the given format, optionally redacting them first. `theme`
+/// is used for HTML output.
+pub fn export_messages<'a>(
+    messages: impl IntoIterator<Item = &'a LogMessage>,
+    format: ExportFormat,
+    redact: bool,
+    theme: &Theme,
+    path: impl AsRef<Path>,
+) -> Result<usize> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut count = 0;
+
+    let syntax_set = SyntaxSet::load_defaults_newlines();
+
+    if format == ExportFormat::Html {
+        writeln!(writer, "{}", html_document_header(theme))?;
+    }
+
+    for message in messages {
+        let message = if redact {
+            message.redacted()?
+        } else {
+            message.clone()
+        };
+        count += 1;
+
+        match format {
+            ExportFormat::Xml => writeln!(writer, "{}\n", annotated_xml(&message)?)?,
+            ExportFormat::Html => {
+                if let Some(direction) = &message.fields.direction {
+                    writeln!(writer, "{}", stanza_header_html(direction.as_str()))?;
+                }
+                writeln!(
+                    writer,
+                    "{}",
+                    message.highlighted_stanza_html(&syntax_set, theme)?
+                )?
+            }
+            ExportFormat::Ndjson => writeln!(writer, "{}", serde_json::to_string(&message)?)?,
+        }
+    }
+
+    if format == ExportFormat::Html {
+        writeln!(writer, "</body>\n</html>")?;
+    }
+
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Writes a single message to `path`: the log line as JSON if the file has a `json`
+/// extension, the pretty-printed stanza otherwise.
+pub fn save_message(message: &LogMessage, redact: bool, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let message = if redact {
+        message.redacted()?
+    } else {
+        message.clone()
+    };
+
+    let contents = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::to_string_pretty(&message)?
+    } else {
+        message.pretty_printed_xml()?
+    };
+    std::fs::write(path, contents + "\n")?;
+    Ok(())
+}
+
+/// Returns the pretty-printed stanza preceded by a direction comment, or the message wrapped
+/// in a comment if it isn't a stanza.
+pub fn annotated_xml(message: &LogMessage) -> Result<String> {
+    let Some(direction) = &message.fields.direction else {
+        return Ok(format!("<!--\n{}\n-->", message.fields.message));
+    };
+    Ok(format!(
+        "<!-- {} -->\n{}",
+        direction.as_str(),
+        message.pretty_printed_xml()?
+    ))
+}
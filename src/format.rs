@@ -1,43 +1,234 @@
-use std::path::Path;
-use std::str::FromStr;
+use std::collections::{HashMap, VecDeque};
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
 
-use anyhow::Result;
-use syntect::highlighting::ThemeSet;
+use anyhow::{format_err, Result};
+use chrono::format::{Item, StrftimeItems};
+use clap::{Args, ValueEnum};
+use syntect::highlighting::Theme;
 use syntect::parsing::SyntaxSet;
-use tokio::fs::File;
-use tokio::io::{AsyncBufReadExt, BufReader};
 
-use crate::log_message::{LogMessage, StanzaDirection};
+use crate::canonical::CanonicalizeOptions;
+use crate::html::print_html;
+use crate::log_message::LogMessage;
+use crate::log_source::LogSource;
+use crate::template::print_template;
+use crate::theme::{load_theme, DEFAULT_THEME};
 
-pub async fn read_and_parse_json_lines(path: impl AsRef<Path>, color: bool) -> Result<()> {
-    let file = File::open(path).await?;
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Html,
+}
+
+/// When to syntax highlight stanzas.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorMode {
+    /// Highlight text output written to a terminal.
+    #[default]
+    Auto,
+    /// Always highlight, e.g. for CI log viewers that render ANSI colors.
+    Always,
+    Never,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct PrintOptions {
+    /// Syntax highlight stanzas. `--color` without a value means `always`.
+    #[arg(
+        long,
+        alias = "force-color",
+        value_enum,
+        default_value_t,
+        num_args = 0..=1,
+        default_missing_value = "always"
+    )]
+    pub color: ColorMode,
+    /// Syntax highlighting theme used by `--color` and HTML output.
+    #[arg(long, default_value = DEFAULT_THEME)]
+    pub theme: String,
+    /// Include each stanza's timestamp in its comment header.
+    #[arg(long)]
+    pub timestamps: bool,
+    /// strftime-style format used for `--timestamps`.
+    #[arg(long, default_value = "%Y-%m-%d %H:%M:%S%.3f", value_parser = parse_timestamp_format)]
+    pub timestamp_format: String,
+    /// Emit only the stanzas, without comments or blank-line separators.
+    #[arg(long)]
+    pub quiet_xml: bool,
+    /// Separate stanzas with NUL bytes instead of newlines (requires `--quiet-xml`).
+    #[arg(long, requires = "quiet_xml")]
+    pub null_separated: bool,
+    #[arg(long, value_enum, default_value_t)]
+    pub output: OutputFormat,
+    /// Render the messages with a Tera template instead, for custom report formats. It gets
+    /// `messages`, each with `number`, `line`, `timestamp`, `level`, `target`, `direction`,
+    /// `kind`, `hash`, `spans`, `message`, `xml`, `body`, `is_error`, `keepalive`,
+    /// `lint_warnings` and `related_stanza`.
+    #[arg(long, conflicts_with_all = ["output", "quiet_xml", "color"])]
+    pub template: Option<PathBuf>,
+    /// Languages to pick the `body` of `<message/>` stanzas in for `--template`, most
+    /// preferred first, e.g. `fr,en`. The first body is used if none is in these languages.
+    #[arg(long, value_delimiter = ',', requires = "template")]
+    pub body_lang: Vec<String>,
+    /// Annotate stanzas whose canonical content was already emitted before.
+    #[arg(long)]
+    pub mark_duplicates: bool,
+    /// Ignore `id` attributes when detecting duplicates.
+    #[arg(long, requires = "mark_duplicates")]
+    pub ignore_ids: bool,
+    /// Ignore timestamp attributes (e.g. `stamp`) when detecting duplicates.
+    #[arg(long, requires = "mark_duplicates")]
+    pub ignore_timestamps: bool,
+    /// Keep printing messages as lines are appended to the file, like `tail -f`.
+    #[arg(long, conflicts_with_all = ["template", "output"])]
+    pub follow: bool,
+    /// Print only the last N messages already in the file before following it.
+    #[arg(long, value_name = "N", requires = "follow")]
+    pub backfill: Option<usize>,
+}
+
+impl PrintOptions {
+    /// Whether stanzas are highlighted. `auto` only highlights text output to a terminal, since
+    /// HTML output is rarely viewed where it's written.
+    pub fn use_color(&self) -> bool {
+        match self.color {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => self.output == OutputFormat::Text && std::io::stdout().is_terminal(),
+        }
+    }
+
+    pub fn canonicalize_options(&self) -> CanonicalizeOptions {
+        CanonicalizeOptions {
+            ignore_ids: self.ignore_ids,
+            ignore_timestamps: self.ignore_timestamps,
+        }
+    }
+}
+
+/// Checks that `format` is a valid strftime-style format when parsing the arguments, since
+/// formatting a timestamp with an invalid one panics.
+pub fn parse_timestamp_format(format: &str) -> Result<String> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(format_err!("Invalid timestamp format '{format}'"));
+    }
+    Ok(format.to_string())
+}
+
+pub async fn read_and_parse_json_lines(source: &LogSource, options: &PrintOptions) -> Result<()> {
+    if let Some(template) = &options.template {
+        return print_template(source, template, &options.body_lang).await;
+    }
+    if options.output == OutputFormat::Html {
+        return print_html(source, options).await;
+    }
+
+    let mut reader = source.open().await?;
+    if options.follow {
+        reader.follow();
+    }
 
     let syntax_set = SyntaxSet::load_defaults_newlines();
-    let theme_set = ThemeSet::load_defaults();
-    let theme = &theme_set.themes["base16-ocean.dark"];
+    let mut printer = TextPrinter {
+        options,
+        syntax_set: &syntax_set,
+        theme: &load_theme(&options.theme)?,
+        color: options.use_color(),
+        stanza_number: 0,
+        seen_stanzas: HashMap::new(),
+    };
 
-    while let Some(line) = lines.next_line().await? {
-        let message = LogMessage::from_str(&line)?;
+    // The last `--backfill` messages already in the file, printed once they're all read
+    let mut backfill = VecDeque::new();
+    let mut caught_up = !options.follow;
+    loop {
+        match reader.next_message().await? {
+            Some(message) if !caught_up && options.backfill.is_some() => {
+                backfill.push_back(message);
+                if options.backfill.is_some_and(|n| backfill.len() > n) {
+                    backfill.pop_front();
+                }
+            }
+            Some((line_number, message)) => printer.print(line_number, &message)?,
+            None if caught_up => break,
+            None => {
+                caught_up = true;
+                for (line_number, message) in backfill.drain(..) {
+                    printer.print(line_number, &message)?;
+                }
+            }
+        }
+    }
 
-        let direction = match message.fields.direction {
-            Some(StanzaDirection::In) => "in",
-            Some(StanzaDirection::Out) => "out",
+    Ok(())
+}
+
+/// Prints messages as annotated stanzas, numbering them as it goes.
+struct TextPrinter<'a> {
+    options: &'a PrintOptions,
+    syntax_set: &'a SyntaxSet,
+    theme: &'a Theme,
+    color: bool,
+    stanza_number: usize,
+    /// Number of the first stanza printed with each canonical content, for
+    /// `--mark-duplicates`.
+    seen_stanzas: HashMap<u64, usize>,
+}
+
+impl TextPrinter<'_> {
+    fn print(&mut self, line_number: usize, message: &LogMessage) -> Result<()> {
+        let options = self.options;
+        let direction = match &message.fields.direction {
+            Some(direction) => direction.as_str(),
+            None if options.quiet_xml => return Ok(()),
             None => {
                 println!("<!--\n{}\n-->\n", message.fields.message);
-                continue;
+                return Ok(());
             }
         };
 
-        let formatted_message = if color {
-            message.highlighted_stanza_xml(&syntax_set, &theme)?
+        self.stanza_number += 1;
+        let stanza_number = self.stanza_number;
+
+        let formatted_message = if self.color {
+            message.highlighted_stanza_xml(self.syntax_set, self.theme)?
         } else {
             message.pretty_printed_xml()?
         };
 
-        println!("<!-- {direction} -->\n{formatted_message}\n");
-    }
+        if options.quiet_xml {
+            let separator = if options.null_separated { '\0' } else { '\n' };
+            let mut stdout = std::io::stdout().lock();
+            write!(stdout, "{formatted_message}{separator}")?;
+            return Ok(());
+        }
 
-    Ok(())
+        let timestamp = if options.timestamps {
+            format!(" {}", message.timestamp.format(&options.timestamp_format))
+        } else {
+            String::new()
+        };
+
+        let duplicate = if options.mark_duplicates {
+            message
+                .stanza_hash(options.canonicalize_options())
+                .and_then(|hash| match self.seen_stanzas.get(&hash) {
+                    Some(original) => Some(format!(" duplicate of #{original}")),
+                    None => {
+                        self.seen_stanzas.insert(hash, stanza_number);
+                        None
+                    }
+                })
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        println!(
+            "<!-- #{stanza_number} {direction} line {line_number}{timestamp}{duplicate} -->\n{formatted_message}\n"
+        );
+        Ok(())
+    }
 }
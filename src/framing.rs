@@ -0,0 +1,134 @@
+use crate::import::{element_len, find_tag, inherit_namespaces, namespace_declarations};
+use crate::log_message::LogMessage;
+
+/// Namespace of XEP-0124 BOSH `<body/>` wrappers.
+const BOSH_NAMESPACE: &str = "http://jabber.org/protocol/httpbind";
+
+/// Namespace of the stanzas inside BOSH wrappers (XEP-0206).
+const CLIENT_NAMESPACE: &str = "jabber:client";
+
+/// Unwraps stanzas logged with their transport framing, so that each inner stanza is
+/// classified, paired and pretty-printed like stanzas logged on their own:
+///
+/// - WebSocket framing metadata before the XML, e.g. `[ws] text frame (312 bytes): <iq/>`, is
+///   dropped.
+/// - BOSH `<body/>` wrappers are replaced by the stanzas they carry. Empty bodies, which are
+///   polls or session management requests, are kept as they are.
+pub fn unwrap_framing(mut message: LogMessage) -> Vec<LogMessage> {
+    if message.fields.direction.is_none() {
+        return vec![message];
+    }
+
+    let xml = message.fields.message.trim();
+    if let Some(start) = find_tag(xml).filter(|start| *start > 0) {
+        message.fields.message = xml[start..].to_string();
+    }
+
+    match bosh_payload(&message.fields.message) {
+        Some(stanzas) if !stanzas.is_empty() => stanzas
+            .into_iter()
+            .map(|stanza| {
+                let mut message = message.clone();
+                message.fields.message = stanza;
+                message
+            })
+            .collect(),
+        _ => vec![message],
+    }
+}
+
+/// Returns the stanzas inside `xml` if it's a BOSH `<body/>` wrapper, with the namespaces they
+/// inherit from it declared on them.
+fn bosh_payload(xml: &str) -> Option<Vec<String>> {
+    let start_len = element_len(xml).filter(|_| xml.starts_with("<body"))?;
+    let start_tag = &xml[..xml.find('>')? + 1];
+    let declarations = namespace_declarations(start_tag);
+    if !declarations
+        .iter()
+        .any(|(name, value)| name == "xmlns" && value == BOSH_NAMESPACE)
+    {
+        return None;
+    }
+    if start_tag.ends_with("/>") {
+        return Some(vec![]);
+    }
+
+    // Stanzas must not end up in the BOSH namespace, which only applies to the wrapper
+    let mut namespaces = declarations
+        .into_iter()
+        .filter(|(name, _)| name != "xmlns")
+        .collect::<Vec<_>>();
+    namespaces.push(("xmlns".to_string(), CLIENT_NAMESPACE.to_string()));
+
+    let mut content = &xml[start_tag.len()..start_len];
+    let mut stanzas = vec![];
+    while let Some(start) = find_tag(content) {
+        content = &content[start..];
+        if content.starts_with("</") {
+            break;
+        }
+        let len = element_len(content).unwrap_or(content.len());
+        if !content.starts_with("<!--") && !content.starts_with("<?") {
+            stanzas.push(inherit_namespaces(&content[..len], &namespaces));
+        }
+        content = &content[len..];
+    }
+    Some(stanzas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_message::{Level, StanzaDirection};
+
+    fn unwrap(direction: Option<StanzaDirection>, message: &str) -> Vec<String> {
+        let message = LogMessage::for_test("2024-01-01T00:00:00Z", Level::Info, direction, message);
+        unwrap_framing(message)
+            .into_iter()
+            .map(|m| m.fields.message)
+            .collect()
+    }
+
+    #[test]
+    fn websocket_frame_metadata_is_dropped() {
+        let stanzas = unwrap(
+            Some(StanzaDirection::In),
+            "[ws] text frame (22 bytes): <iq type='result'/>",
+        );
+        assert_eq!(stanzas, ["<iq type='result'/>"]);
+    }
+
+    #[test]
+    fn bosh_bodies_are_replaced_by_their_stanzas() {
+        let body = "<body rid='1' xmlns='http://jabber.org/protocol/httpbind' \
+                    xmlns:stream='http://etherx.jabber.org/streams'>\
+                    <iq type='get' id='a'/><!-- poll --><message to='b@c'><body>hi</body></message>\
+                    </body>";
+        let stanzas = unwrap(Some(StanzaDirection::Out), body);
+        assert_eq!(
+            stanzas,
+            [
+                "<iq xmlns:stream='http://etherx.jabber.org/streams' xmlns='jabber:client' \
+                 type='get' id='a'/>",
+                "<message xmlns:stream='http://etherx.jabber.org/streams' xmlns='jabber:client' \
+                 to='b@c'><body>hi</body></message>",
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_bosh_bodies_are_kept() {
+        let poll = "<body rid='2' sid='s' xmlns='http://jabber.org/protocol/httpbind'/>";
+        assert_eq!(unwrap(Some(StanzaDirection::Out), poll), [poll]);
+        let empty = "<body rid='3' xmlns='http://jabber.org/protocol/httpbind'></body>";
+        assert_eq!(unwrap(Some(StanzaDirection::In), empty), [empty]);
+    }
+
+    #[test]
+    fn other_bodies_and_messages_are_left_alone() {
+        let body = "<body xmlns='urn:example'><iq/></body>";
+        assert_eq!(unwrap(Some(StanzaDirection::In), body), [body]);
+        let text = "[ws] connected: <not a stanza>";
+        assert_eq!(unwrap(None, text), [text]);
+    }
+}
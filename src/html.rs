@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::{stream, StreamExt, TryStreamExt};
+use syntect::highlighting::Theme;
+use syntect::parsing::SyntaxSet;
+
+use crate::format::PrintOptions;
+use crate::log_message::{escape_html, LogMessage, StanzaDirection};
+use crate::log_source::LogSource;
+use crate::theme::{html_document_header, load_theme};
+
+/// Number of messages handed to a worker task at once.
+const BATCH_SIZE: usize = 512;
+
+struct Fragment {
+    line_number: usize,
+    direction: Option<&'static str>,
+    timestamp: String,
+    /// Hash of the canonical stanza, for `--mark-duplicates`.
+    hash: Option<u64>,
+    html: String,
+}
+
+/// Returns the line introducing a stanza, e.g. `#3 out line 12`, as a paragraph shown above
+/// it.
+pub fn stanza_header_html(header: &str) -> String {
+    format!(
+        "<p class=\"stanza-header\" style=\"opacity:0.6\"><code>{}</code></p>",
+        escape_html(header)
+    )
+}
+
+/// Renders the log file as a highlighted HTML document.
+///
+/// Pretty-printing and highlighting happen on blocking worker tasks in batches of
+/// `BATCH_SIZE` messages. Batches are reassembled in file order before being written, so that
+/// stanza numbers stay identical to the plain text output.
+pub async fn print_html(source: &LogSource, options: &PrintOptions) -> Result<()> {
+    let reader = source.open().await?;
+
+    let syntax_set = Arc::new(SyntaxSet::load_defaults_newlines());
+    let theme = Arc::new(load_theme(&options.theme)?);
+    let parallelism = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+
+    let batches = stream::unfold(reader, |mut reader| async move {
+        reader
+            .next_message()
+            .await
+            .transpose()
+            .map(|message| (message, reader))
+    })
+    .chunks(BATCH_SIZE);
+
+    let mut fragments = batches
+        .map(|batch| {
+            let syntax_set = syntax_set.clone();
+            let theme = theme.clone();
+            let options = options.clone();
+            tokio::task::spawn_blocking(move || render_batch(batch, &syntax_set, &theme, &options))
+        })
+        .buffered(parallelism)
+        .boxed();
+
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", html_document_header(&theme))?;
+
+    let mut stanza_number = 0;
+    // Number of the first stanza printed with each canonical content
+    let mut seen_stanzas = HashMap::new();
+
+    while let Some(batch) = fragments.try_next().await? {
+        for fragment in batch? {
+            let Some(direction) = fragment.direction else {
+                if !options.quiet_xml {
+                    writeln!(stdout, "{}", fragment.html)?;
+                }
+                continue;
+            };
+
+            stanza_number += 1;
+
+            if !options.quiet_xml {
+                let duplicate = fragment
+                    .hash
+                    .and_then(|hash| match seen_stanzas.get(&hash) {
+                        Some(original) => Some(format!(" duplicate of #{original}")),
+                        None => {
+                            seen_stanzas.insert(hash, stanza_number);
+                            None
+                        }
+                    })
+                    .unwrap_or_default();
+                let header = format!(
+                    "#{stanza_number} {direction} line {}{}{duplicate}",
+                    fragment.line_number, fragment.timestamp
+                );
+                writeln!(stdout, "{}", stanza_header_html(&header))?;
+            }
+            writeln!(stdout, "{}", fragment.html)?;
+        }
+    }
+
+    writeln!(stdout, "</body>\n</html>")?;
+
+    Ok(())
+}
+
+fn render_batch(
+    batch: Vec<Result<(usize, LogMessage)>>,
+    syntax_set: &SyntaxSet,
+    theme: &Theme,
+    options: &PrintOptions,
+) -> Result<Vec<Fragment>> {
+    batch
+        .into_iter()
+        .map(|message| {
+            let (line_number, message) = message?;
+
+            let direction = message
+                .fields
+                .direction
+                .as_ref()
+                .map(StanzaDirection::as_str);
+
+            let timestamp = if options.timestamps {
+                format!(" {}", message.timestamp.format(&options.timestamp_format))
+            } else {
+                String::new()
+            };
+
+            let hash = match options.mark_duplicates {
+                true => message.stanza_hash(options.canonicalize_options()),
+                false => None,
+            };
+
+            let html = if options.use_color() {
+                message.highlighted_stanza_html(syntax_set, theme)?
+            } else {
+                format!("<pre>{}</pre>", escape_html(&message.pretty_printed_xml()?))
+            };
+
+            Ok(Fragment {
+                line_number,
+                direction,
+                timestamp,
+                hash,
+                html,
+            })
+        })
+        .collect()
+}
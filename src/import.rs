@@ -0,0 +1,441 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use regex::Regex;
+
+use crate::log_message::{Fields, Level, LogMessage, StanzaDirection};
+
+/// Target of messages imported from raw stream dumps, which have no notion of modules.
+const RAW_TARGET: &str = "raw";
+
+/// Markers preceding stanzas in the debug logs of other clients, e.g. Gajim's `SENT:` /
+/// `RECV:` and the `-->` / `<--` arrows of Conversations-style dumps.
+const MARKERS: [(&str, StanzaDirection); 6] = [
+    ("SENT:", StanzaDirection::Out),
+    ("SEND:", StanzaDirection::Out),
+    ("-->", StanzaDirection::Out),
+    ("RECV:", StanzaDirection::In),
+    ("RECEIVED:", StanzaDirection::In),
+    ("<--", StanzaDirection::In),
+];
+
+/// Maps a line of a raw stream dump to a message. Lines with a direction marker followed by
+/// XML become stanzas, anything else becomes a plain message. Timestamps and levels are
+/// picked up from the line when present; lines without a timestamp are dated to the epoch.
+pub fn parse_raw_line(line: &str) -> LogMessage {
+    let stanza = find_marker(line)
+        .map(|(position, marker, direction)| {
+            let xml = line[position + marker.len()..].trim();
+            (&line[..position], xml, direction)
+        })
+        .filter(|(_, xml, _)| xml.starts_with('<'));
+
+    // Only look for timestamps and levels before the stanza, which may contain either
+    let (prefix, message, direction, default_level) = match stanza {
+        Some((prefix, xml, direction)) => (prefix, xml, Some(direction), Level::Debug),
+        None => (line, line.trim(), None, Level::Info),
+    };
+
+    raw_message(
+        find_timestamp(prefix).unwrap_or_default(),
+        find_level(prefix).unwrap_or(default_level),
+        message,
+        direction,
+    )
+}
+
+/// Splits a raw XML stream capture into its top-level stanzas. The capture may hold a single
+/// stream, in which case stanzas get `default_direction`, or both streams interleaved with
+/// direction markers. Stanzas are dated after the last timestamp found between them, a
+/// microsecond apart to keep them in order.
+///
+/// Returns each message with the line it starts on.
+pub fn parse_stream_capture(
+    capture: &str,
+    default_direction: StanzaDirection,
+) -> Vec<(usize, LogMessage)> {
+    let mut messages = vec![];
+    let mut direction = default_direction;
+    let mut timestamp = DateTime::<Utc>::default();
+    // Namespaces declared by the stream header, which stanzas inherit
+    let mut namespaces = vec![];
+
+    let mut pos = 0;
+    let mut line_number = 1;
+    while pos < capture.len() {
+        let rest = &capture[pos..];
+        let text_len = find_tag(rest).unwrap_or(rest.len());
+
+        let text = &rest[..text_len];
+        for (offset, line) in text.split('\n').enumerate() {
+            if let Some(t) = find_timestamp(line) {
+                timestamp = t;
+            }
+            match find_marker(line) {
+                Some((_, _, marker_direction)) => direction = marker_direction,
+                None if !line.trim().is_empty() => {
+                    let level = find_level(line).unwrap_or(Level::Info);
+                    let message = raw_message(timestamp, level, line.trim(), None);
+                    messages.push((line_number + offset, message));
+                }
+                None => (),
+            }
+        }
+        line_number += text.matches('\n').count();
+        let rest = &rest[text_len..];
+        if rest.is_empty() {
+            break;
+        }
+
+        let len = element_len(rest).unwrap_or(rest.len());
+        let element = &rest[..len];
+        pos += text_len + len;
+        let start_line = line_number;
+        line_number += element.matches('\n').count();
+
+        if element.starts_with("<?") || element.starts_with("<!--") {
+            continue;
+        }
+        if is_stream_tag(element) {
+            if !element.starts_with("</") {
+                namespaces = namespace_declarations(element);
+            }
+            let message = raw_message(timestamp, Level::Info, element, None);
+            messages.push((start_line, message));
+            continue;
+        }
+
+        timestamp += Duration::microseconds(1);
+        let stanza = inherit_namespaces(element, &namespaces);
+        let message = raw_message(timestamp, Level::Debug, &stanza, Some(direction.clone()));
+        messages.push((start_line, message));
+    }
+
+    messages
+}
+
+/// Whether the start of a file looks like a raw XML stream capture rather than a line-based
+/// log.
+pub fn is_stream_capture(start: &str) -> bool {
+    let start = start.trim_start();
+    if start.starts_with('{') {
+        return false;
+    }
+    start.starts_with("<?xml") || start.contains("<stream:stream")
+}
+
+/// Guesses the direction of a capture holding a single stream from its file name, e.g.
+/// `client-out.xml`.
+pub fn capture_direction(path: &Path) -> StanzaDirection {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if ["out", "sent", "send", "tx"]
+        .iter()
+        .any(|hint| name.contains(hint))
+    {
+        StanzaDirection::Out
+    } else {
+        StanzaDirection::In
+    }
+}
+
+/// Builds a message without spans, as read from a source other than a Prose JSON log.
+pub fn raw_message(
+    timestamp: DateTime<Utc>,
+    level: Level,
+    message: &str,
+    direction: Option<StanzaDirection>,
+) -> LogMessage {
+    LogMessage {
+        timestamp,
+        level,
+        fields: Fields {
+            message: message.to_string(),
+            direction,
+        },
+        target: RAW_TARGET.to_string(),
+        span: None,
+        spans: None,
+        source: 0,
+        sequence: 0,
+        lint_warnings: vec![],
+        related_stanza: None,
+        keepalive: false,
+        preferred_body: None,
+    }
+}
+
+/// Returns the position, text and direction of the first direction marker in `line`.
+fn find_marker(line: &str) -> Option<(usize, &'static str, StanzaDirection)> {
+    MARKERS
+        .iter()
+        .filter_map(|(marker, direction)| Some((line.find(marker)?, *marker, direction.clone())))
+        .min_by_key(|(position, ..)| *position)
+}
+
+/// Returns the position of the next tag in `s`, skipping `<--` markers.
+pub fn find_tag(s: &str) -> Option<usize> {
+    s.match_indices('<')
+        .map(|(position, _)| position)
+        .find(|position| !s[*position..].starts_with("<--"))
+}
+
+/// Returns the length of the element, comment or processing instruction at the start of
+/// `s`, or `None` if it's truncated. Stream headers are returned on their own, since the
+/// stream element only closes at the end of the session.
+pub fn element_len(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut pos = 0;
+
+    loop {
+        pos += s[pos..].find('<')?;
+        let rest = &s[pos..];
+        let terminator = [("<!--", "-->"), ("<![CDATA[", "]]>"), ("<?", "?>")]
+            .into_iter()
+            .find(|(start, _)| rest.starts_with(start));
+
+        let end = match terminator {
+            Some((_, terminator)) => pos + rest.find(terminator)? + terminator.len(),
+            None => pos + tag_len(rest)?,
+        };
+        let tag = &s[pos..end];
+        pos = end;
+
+        if terminator.is_some() {
+            if depth == 0 {
+                return Some(end);
+            }
+        } else if tag.starts_with("</") {
+            depth -= 1;
+        } else if !tag.ends_with("/>") && !is_stream_tag(tag) {
+            depth += 1;
+        }
+        if depth <= 0 {
+            return Some(end);
+        }
+    }
+}
+
+/// Returns the length of the tag at the start of `s`, skipping `>` in quoted attributes.
+fn tag_len(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (position, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some(position + 1),
+            _ => (),
+        }
+    }
+    None
+}
+
+fn is_stream_tag(tag: &str) -> bool {
+    let name = tag
+        .trim_start_matches(['<', '/'])
+        .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .next()
+        .unwrap_or_default();
+    name == "stream" || name.ends_with(":stream")
+}
+
+/// Returns the `xmlns` and `xmlns:*` declarations of a start tag as (attribute, namespace).
+pub fn namespace_declarations(tag: &str) -> Vec<(String, String)> {
+    static NAMESPACE: OnceLock<Regex> = OnceLock::new();
+    let namespace = NAMESPACE.get_or_init(|| {
+        Regex::new(r#"(xmlns(?::[\w.-]+)?)\s*=\s*(?:'([^']*)'|"([^"]*)")"#).unwrap()
+    });
+
+    namespace
+        .captures_iter(tag)
+        .map(|captures| {
+            let value = captures
+                .get(2)
+                .or(captures.get(3))
+                .map_or("", |m| m.as_str());
+            (captures[1].to_string(), value.to_string())
+        })
+        .collect()
+}
+
+/// Adds the namespace declarations `element` inherits from the stream header to its start
+/// tag, so that it can be parsed on its own.
+pub fn inherit_namespaces(element: &str, namespaces: &[(String, String)]) -> String {
+    let Some(name_end) = element.find(|c: char| c.is_whitespace() || c == '>' || c == '/') else {
+        return element.to_string();
+    };
+    let start_tag = &element[..tag_len(element).unwrap_or(element.len())];
+    let declared = namespace_declarations(start_tag);
+
+    let missing = namespaces
+        .iter()
+        .filter(|(name, _)| !declared.iter().any(|(declared, _)| declared == name))
+        .map(|(name, value)| format!(" {name}='{value}'"))
+        .collect::<String>();
+    format!("{}{missing}{}", &element[..name_end], &element[name_end..])
+}
+
+/// Returns the first ISO 8601-like timestamp in `line`. Timestamps without an offset are
+/// taken to be UTC.
+fn find_timestamp(line: &str) -> Option<DateTime<Utc>> {
+    static TIMESTAMP: OnceLock<Regex> = OnceLock::new();
+    let timestamp = TIMESTAMP.get_or_init(|| {
+        Regex::new(r"(\d{4}-\d{2}-\d{2})[T ](\d{2}:\d{2}:\d{2})(?:[.,](\d+))?(Z|[+-]\d{2}:?\d{2})?")
+            .unwrap()
+    });
+
+    let captures = timestamp.captures(line)?;
+    // Beyond nanoseconds, which is all `DateTime` holds, the digits are dropped rather than
+    // failing to parse
+    let fraction = captures
+        .get(3)
+        .map_or("0", |m| &m.as_str()[..m.len().min(9)]);
+    let naive = NaiveDateTime::parse_from_str(
+        &format!("{} {}.{fraction}", &captures[1], &captures[2]),
+        "%Y-%m-%d %H:%M:%S%.f",
+    )
+    .ok()?;
+
+    match captures.get(4).map(|m| m.as_str()) {
+        None | Some("Z") => Some(Utc.from_utc_datetime(&naive)),
+        Some(offset) => {
+            let offset = offset.replace(':', "");
+            let with_offset = format!("{} {offset}", naive.format("%Y-%m-%d %H:%M:%S%.f"));
+            DateTime::parse_from_str(&with_offset, "%Y-%m-%d %H:%M:%S%.f %z")
+                .ok()
+                .map(|t| t.with_timezone(&Utc))
+        }
+    }
+}
+
+fn find_level(line: &str) -> Option<Level> {
+    static LEVEL: OnceLock<Regex> = OnceLock::new();
+    let level =
+        LEVEL.get_or_init(|| Regex::new(r"\b(TRACE|DEBUG|INFO|WARN|WARNING|ERROR)\b").unwrap());
+
+    match &level.captures(line)?[1] {
+        "TRACE" => Some(Level::Trace),
+        "DEBUG" => Some(Level::Debug),
+        "INFO" => Some(Level::Info),
+        "WARN" | "WARNING" => Some(Level::Warn),
+        _ => Some(Level::Error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_lines_with_markers_become_stanzas() {
+        let message = parse_raw_line("2024-03-01 12:00:01,250 SENT: <iq type='get' id='1'/>");
+        assert_eq!(message.fields.direction, Some(StanzaDirection::Out));
+        assert_eq!(message.fields.message, "<iq type='get' id='1'/>");
+        assert_eq!(message.level, Level::Debug);
+        assert_eq!(
+            message.timestamp.to_rfc3339(),
+            "2024-03-01T12:00:01.250+00:00"
+        );
+
+        let message = parse_raw_line("<-- <message from='a@prose.org'><body>hi</body></message>");
+        assert_eq!(message.fields.direction, Some(StanzaDirection::In));
+        assert_eq!(message.target, RAW_TARGET);
+    }
+
+    #[test]
+    fn raw_lines_without_stanzas_are_plain_messages() {
+        let message = parse_raw_line("2024-03-01T12:00:01+01:00 WARNING Connection lost");
+        assert_eq!(message.fields.direction, None);
+        assert_eq!(message.level, Level::Warn);
+        assert_eq!(message.timestamp.to_rfc3339(), "2024-03-01T11:00:01+00:00");
+
+        // A marker not followed by XML doesn't make a stanza
+        let message = parse_raw_line("RECV: nothing");
+        assert_eq!(message.fields.direction, None);
+        assert_eq!(message.level, Level::Info);
+        assert_eq!(message.timestamp, DateTime::<Utc>::default());
+    }
+
+    #[test]
+    fn levels_in_stanzas_are_ignored() {
+        let message = parse_raw_line("RECV: <message><body>ERROR</body></message>");
+        assert_eq!(message.level, Level::Debug);
+    }
+
+    #[test]
+    fn stream_captures_are_split_into_stanzas() {
+        let capture = "<?xml version='1.0'?>\n\
+            <stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>\n\
+            <iq type='get' id='1'><ping xmlns='urn:xmpp:ping'/></iq>\n\
+            <message to='a@prose.org'>\n  <body>a > b</body>\n</message>\n\
+            </stream:stream>\n";
+        let messages = parse_stream_capture(capture, StanzaDirection::In);
+        let lines = messages.iter().map(|(line, _)| *line).collect::<Vec<_>>();
+        assert_eq!(lines, [2, 3, 4, 7]);
+
+        let (_, iq) = &messages[1];
+        assert_eq!(iq.fields.direction, Some(StanzaDirection::In));
+        assert_eq!(
+            iq.fields.message,
+            "<iq xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams' \
+             type='get' id='1'><ping xmlns='urn:xmpp:ping'/></iq>"
+        );
+        // Stanzas stay in order although the capture has no timestamps
+        assert!(messages[1].1.timestamp < messages[2].1.timestamp);
+        assert_eq!(messages[3].1.fields.message, "</stream:stream>");
+    }
+
+    #[test]
+    fn stream_captures_follow_direction_markers() {
+        let capture = "SEND:\n<presence/>\nRECV:\n<presence from='a@prose.org'/>\n";
+        let directions = parse_stream_capture(capture, StanzaDirection::In)
+            .into_iter()
+            .map(|(_, message)| message.fields.direction)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            directions,
+            [Some(StanzaDirection::Out), Some(StanzaDirection::In)]
+        );
+    }
+
+    #[test]
+    fn element_len_handles_nesting_quotes_and_truncation() {
+        assert_eq!(element_len("<a><b/></a>tail"), Some(11));
+        assert_eq!(element_len("<a x='>'><!-- </a> --></a>"), Some(26));
+        assert_eq!(element_len("<stream:stream to='x'><iq/>"), Some(22));
+        assert_eq!(element_len("<a><b>"), None);
+    }
+
+    #[test]
+    fn inherited_namespaces_dont_override_declared_ones() {
+        let namespaces =
+            namespace_declarations("<stream:stream xmlns='jabber:client' xmlns:stream='s'>");
+        assert_eq!(
+            inherit_namespaces("<iq xmlns='jabber:server'/>", &namespaces),
+            "<iq xmlns:stream='s' xmlns='jabber:server'/>"
+        );
+    }
+
+    #[test]
+    fn stream_captures_are_told_from_line_logs() {
+        assert!(is_stream_capture("<?xml version='1.0'?><stream:stream>"));
+        assert!(is_stream_capture("  <stream:stream xmlns='jabber:client'>"));
+        assert!(!is_stream_capture("{\"message\": \"<stream:stream>\"}"));
+        assert!(!is_stream_capture("2024-03-01 SENT: <iq/>"));
+    }
+
+    #[test]
+    fn capture_direction_is_guessed_from_the_file_name() {
+        assert_eq!(
+            capture_direction(Path::new("dumps/client-out.xml")),
+            StanzaDirection::Out
+        );
+        assert_eq!(
+            capture_direction(Path::new("dumps/capture.xml")),
+            StanzaDirection::In
+        );
+    }
+}
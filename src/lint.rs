@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use anyhow::{format_err, Result};
+use chrono::{DateTime, Duration, Utc};
+use clap::Args;
+
+use crate::log_message::{LogMessage, StanzaDirection, StanzaKind};
+use crate::log_source::LogSource;
+use crate::round_trip::{check_round_trip, RoundTripDiscrepancy};
+use crate::throttle::{ThrottleDetector, ThrottleOptions};
+
+const IQ_TYPES: [&str; 4] = ["get", "set", "result", "error"];
+const MESSAGE_TYPES: [&str; 5] = ["chat", "error", "groupchat", "headline", "normal"];
+const PRESENCE_TYPES: [&str; 7] = [
+    "error",
+    "probe",
+    "subscribe",
+    "subscribed",
+    "unavailable",
+    "unsubscribe",
+    "unsubscribed",
+];
+
+/// Default for `--duplicate-window`, also used when browsing.
+pub const DEFAULT_DUPLICATE_WINDOW_SECS: u64 = 10;
+
+#[derive(Args, Debug, Clone)]
+pub struct LintOptions {
+    /// Stanzas sent in the same direction with the same id within this many seconds are
+    /// reported as duplicates.
+    #[arg(long, default_value_t = DEFAULT_DUPLICATE_WINDOW_SECS)]
+    pub duplicate_window: u64,
+    /// Also parse and re-serialize each stanza, reporting where the result differs from the
+    /// logged text.
+    #[arg(long)]
+    pub round_trip: bool,
+    #[command(flatten)]
+    pub throttle: ThrottleOptions,
+}
+
+impl LintOptions {
+    pub fn duplicate_window(&self) -> Duration {
+        Duration::seconds(self.duplicate_window as i64)
+    }
+}
+
+/// A violation of basic XMPP structural rules.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LintWarning {
+    MissingNamespace,
+    MissingType,
+    InvalidType(String),
+    DuplicateId(String),
+    RoundTrip(RoundTripDiscrepancy),
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintWarning::MissingNamespace => f.write_str("missing xmlns"),
+            LintWarning::MissingType => f.write_str("missing type attribute"),
+            LintWarning::InvalidType(value) => write!(f, "invalid type '{value}'"),
+            LintWarning::DuplicateId(id) => write!(f, "duplicate id '{id}'"),
+            LintWarning::RoundTrip(discrepancy) => write!(f, "round trip: {discrepancy}"),
+        }
+    }
+}
+
+/// Checks stanzas one at a time, remembering recent ids to detect duplicates. Messages must be
+/// fed in chronological order.
+pub struct Linter {
+    window: Duration,
+    /// Ids seen within the last `window`, keyed by source file and direction.
+    recent_ids: VecDeque<(DateTime<Utc>, usize, bool, String)>,
+}
+
+impl Linter {
+    pub fn new(window: Duration) -> Self {
+        Linter {
+            window,
+            recent_ids: VecDeque::new(),
+        }
+    }
+
+    pub fn check(&mut self, message: &LogMessage) -> Vec<LintWarning> {
+        let Some(kind) = message.stanza_kind() else {
+            return vec![];
+        };
+        let mut warnings = vec![];
+
+        if message.stanza_attribute("xmlns").is_none() && !message.stanza_has_prefix() {
+            warnings.push(LintWarning::MissingNamespace);
+        }
+
+        let valid_types: &[&str] = match kind {
+            StanzaKind::Iq => &IQ_TYPES,
+            StanzaKind::Message => &MESSAGE_TYPES,
+            StanzaKind::Presence => &PRESENCE_TYPES,
+            StanzaKind::Other => &[],
+        };
+        match message.stanza_attribute("type") {
+            Some(value) if kind != StanzaKind::Other && !valid_types.contains(&value) => {
+                warnings.push(LintWarning::InvalidType(value.to_string()))
+            }
+            None if kind == StanzaKind::Iq => warnings.push(LintWarning::MissingType),
+            _ => (),
+        }
+
+        if let Some(id) = message.stanza_attribute("id") {
+            let cutoff = message.timestamp - self.window;
+            while self
+                .recent_ids
+                .front()
+                .is_some_and(|(timestamp, ..)| *timestamp < cutoff)
+            {
+                self.recent_ids.pop_front();
+            }
+
+            let incoming = message.fields.direction == Some(StanzaDirection::In);
+            if self.recent_ids.iter().any(|(_, source, i, seen)| {
+                *source == message.source && *i == incoming && seen == id
+            }) {
+                warnings.push(LintWarning::DuplicateId(id.to_string()));
+            }
+            self.recent_ids.push_back((
+                message.timestamp,
+                message.source,
+                incoming,
+                id.to_string(),
+            ));
+        }
+
+        warnings
+    }
+}
+
+/// Prints the lint warnings of every stanza in the log file, and outbound bursts that likely
+/// got the client throttled, failing if there are any.
+pub async fn lint_log_file(source: &LogSource, options: &LintOptions) -> Result<()> {
+    let mut reader = source.open().await?;
+
+    let mut linter = Linter::new(options.duplicate_window());
+    let mut throttle_detector = ThrottleDetector::new(&options.throttle);
+    let mut stanza_number = 0;
+    let mut warning_count = 0;
+
+    while let Some((line_number, message)) = reader.next_message().await? {
+        throttle_detector.feed(line_number, &message);
+        if message.fields.direction.is_none() {
+            continue;
+        }
+        stanza_number += 1;
+
+        let mut warnings = linter.check(&message);
+        if options.round_trip {
+            warnings.extend(
+                check_round_trip(&message.fields.message)
+                    .into_iter()
+                    .map(LintWarning::RoundTrip),
+            );
+        }
+        for warning in warnings {
+            warning_count += 1;
+            println!("line {line_number}: stanza #{stanza_number}: {warning}");
+        }
+    }
+
+    let (_, throttled) = throttle_detector.finish();
+    for burst in throttled {
+        warning_count += 1;
+        println!("line {}: likely throttled: {burst}", burst.line_number);
+    }
+
+    if warning_count > 0 {
+        return Err(format_err!(
+            "{warning_count} warnings in {stanza_number} stanzas"
+        ));
+    }
+    println!("{stanza_number} stanzas, no warnings");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_message::Level;
+
+    fn stanza(seconds: u32, direction: StanzaDirection, xml: &str) -> LogMessage {
+        let timestamp = format!("2024-01-01T00:00:{seconds:02}Z");
+        LogMessage::for_test(&timestamp, Level::Info, Some(direction), xml)
+    }
+
+    fn check(xml: &str) -> Vec<LintWarning> {
+        Linter::new(Duration::seconds(10)).check(&stanza(0, StanzaDirection::Out, xml))
+    }
+
+    #[test]
+    fn well_formed_stanzas_have_no_warnings() {
+        assert!(check("<iq xmlns='jabber:client' type='get' id='1'/>").is_empty());
+        assert!(check("<message xmlns='jabber:client'/>").is_empty());
+        assert!(check("<stream:features/>").is_empty());
+    }
+
+    #[test]
+    fn stanzas_without_namespace_or_with_invalid_types_are_flagged() {
+        assert_eq!(
+            check("<presence type='away'/>"),
+            [
+                LintWarning::MissingNamespace,
+                LintWarning::InvalidType("away".to_string())
+            ]
+        );
+        assert_eq!(
+            check("<iq xmlns='jabber:client'/>"),
+            [LintWarning::MissingType]
+        );
+    }
+
+    #[test]
+    fn ids_are_duplicates_in_the_same_direction_and_window() {
+        let mut linter = Linter::new(Duration::seconds(10));
+        let iq = "<iq xmlns='jabber:client' type='get' id='a'/>";
+        assert!(linter
+            .check(&stanza(0, StanzaDirection::Out, iq))
+            .is_empty());
+        assert!(linter.check(&stanza(1, StanzaDirection::In, iq)).is_empty());
+        assert_eq!(
+            linter.check(&stanza(5, StanzaDirection::Out, iq)),
+            [LintWarning::DuplicateId("a".to_string())]
+        );
+        // Only the stanza at 5s is still within the window at 16s
+        assert!(linter
+            .check(&stanza(16, StanzaDirection::Out, iq))
+            .is_empty());
+    }
+
+    #[test]
+    fn ids_are_only_duplicates_within_the_same_file() {
+        let mut linter = Linter::new(Duration::seconds(10));
+        let iq = "<iq xmlns='jabber:client' type='get' id='a'/>";
+        let mut other_file = stanza(1, StanzaDirection::Out, iq);
+        other_file.source = 1;
+        assert!(linter
+            .check(&stanza(0, StanzaDirection::Out, iq))
+            .is_empty());
+        assert!(linter.check(&other_file).is_empty());
+    }
+}
@@ -5,52 +5,206 @@ use anyhow::Result;
 use chrono::{DateTime, Utc};
 use ratatui::style::Color;
 use ratatui::text::Line;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use syntect::easy::HighlightLines;
 use syntect::highlighting::{Style, Theme};
+use syntect::html::highlighted_html_for_string;
 use syntect::parsing::SyntaxSet;
 use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
 use syntect_tui::into_span;
+use xml::reader::XmlEvent;
+use xml::ParserConfig;
 
+use crate::canonical::{canonicalize, content_hash, CanonicalizeOptions};
+use crate::lint::LintWarning;
 use crate::pretty_print::to_writer_pretty;
+use crate::redact::redact_xml;
 
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Hash)]
+const TLS_NAMESPACE: &str = "urn:ietf:params:xml:ns:xmpp-tls";
+const COMPRESSION_NAMESPACE: &str = "http://jabber.org/protocol/compress";
+/// Namespace of the RFC 7395 WebSocket stream headers.
+const FRAMING_NAMESPACE: &str = "urn:ietf:params:xml:ns:xmpp-framing";
+const SASL_NAMESPACE: &str = "urn:ietf:params:xml:ns:xmpp-sasl";
+const PING_NAMESPACE: &str = "urn:xmpp:ping";
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
 pub struct Span {
     pub name: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Fields {
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub direction: Option<StanzaDirection>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum StanzaDirection {
     In,
     Out,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl StanzaDirection {
+    /// `in` or `out`, as shown in headers, filters and templates.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StanzaDirection::In => "in",
+            StanzaDirection::Out => "out",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StanzaKind {
+    Iq,
+    Message,
+    Presence,
+    Other,
+}
+
+impl std::fmt::Display for StanzaKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            StanzaKind::Iq => "iq",
+            StanzaKind::Message => "message",
+            StanzaKind::Presence => "presence",
+            StanzaKind::Other => "other",
+        })
+    }
+}
+
+/// A point where the properties of the connection change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelBoundary {
+    /// A stream header, which opens the stream or restarts it after TLS, compression or
+    /// authentication. The new stream starts with this message.
+    StreamStart,
+    /// The server's STARTTLS `<proceed/>`. TLS applies to what follows.
+    Tls,
+    /// The server's XEP-0138 `<compressed/>`. Compression applies to what follows.
+    Compression,
+}
+
+impl ChannelBoundary {
+    /// Whether the change applies to the message itself, rather than to the messages after it.
+    pub fn includes_message(self) -> bool {
+        self == ChannelBoundary::StreamStart
+    }
+}
+
+impl std::fmt::Display for ChannelBoundary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ChannelBoundary::StreamStart => "stream start",
+            ChannelBoundary::Tls => "TLS established",
+            ChannelBoundary::Compression => "compression enabled",
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    pub const ALL: [Level; 5] = [
+        Level::Trace,
+        Level::Debug,
+        Level::Info,
+        Level::Warn,
+        Level::Error,
+    ];
+}
+
+impl std::fmt::Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        })
+    }
+}
+
+/// Identifies a message among those of the files read together: the index of its file and its
+/// position in it. Unlike timestamps and texts, which repeat, it's unique and stays the same
+/// as lines are appended.
+pub type MessageId = (usize, usize);
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct LogMessage {
     pub timestamp: DateTime<Utc>,
-    pub level: String,
+    pub level: Level,
     pub fields: Fields,
     pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub span: Option<Span>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub spans: Option<Vec<Span>>,
+    /// Index of the file this message was read from when browsing several files at once.
+    #[serde(skip)]
+    pub source: usize,
+    /// Position of the message in its file, counting from 1.
+    #[serde(skip)]
+    pub sequence: usize,
+    /// Structural problems found in the stanza when it was loaded.
+    #[serde(skip)]
+    pub lint_warnings: Vec<LintWarning>,
+    /// For errors, the stanza most likely involved: the last one sent or received before them
+    /// in the same span.
+    #[serde(skip)]
+    pub related_stanza: Option<Box<LogMessage>>,
+    /// Whether the stanza is a keepalive ping or the reply to one.
+    #[serde(skip)]
+    pub keepalive: bool,
+    /// For `<message/>` stanzas, the text of the body in the most preferred language, shown
+    /// in place of the stanza in `browse`'s message list.
+    #[serde(skip)]
+    pub preferred_body: Option<String>,
 }
 
 impl FromStr for LogMessage {
-    type Err = serde_json::Error;
+    type Err = anyhow::Error;
 
+    /// Parses a line of a Prose JSON log. Lines of other clients' raw stream dumps are parsed
+    /// with [`crate::import::parse_raw_line`] instead.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        serde_json::from_str(&s)
+        Ok(serde_json::from_str(s)?)
     }
 }
 
 impl LogMessage {
+    /// Orders messages by time, then messages with equal timestamps as they were logged: by
+    /// file, then by position in the file. Sorting by it is deterministic whatever order the
+    /// messages were in.
+    pub fn chronological_key(&self) -> (DateTime<Utc>, usize, usize) {
+        (self.timestamp, self.source, self.sequence)
+    }
+
+    pub fn id(&self) -> MessageId {
+        (self.source, self.sequence)
+    }
+
+    /// Returns a copy of the message with the stanza's text content and JID local parts
+    /// replaced by placeholders.
+    pub fn redacted(&self) -> Result<LogMessage> {
+        let mut message = self.clone();
+        if self.fields.direction.is_some() {
+            message.fields.message = redact_xml(&self.fields.message)?;
+        }
+        Ok(message)
+    }
+
     pub fn pretty_printed_xml(&self) -> Result<String> {
         if self.fields.direction.is_none() {
             return Ok(self.fields.message.to_string());
@@ -60,6 +214,199 @@ impl LogMessage {
         Ok(String::from_utf8(buf)?)
     }
 
+    /// Returns the message pretty-printed if it isn't a stanza but a JSON object or array, like
+    /// an API response logged as is. Keys keep their logged order.
+    pub fn pretty_printed_json(&self) -> Option<String> {
+        if self.fields.direction.is_some() {
+            return None;
+        }
+        let text = self.fields.message.trim();
+        if !text.starts_with('{') && !text.starts_with('[') {
+            return None;
+        }
+        let value = serde_json::from_str::<serde_json::Value>(text).ok()?;
+        serde_json::to_string_pretty(&value).ok()
+    }
+
+    /// Classifies the stanza by the local name of its root element without parsing the whole
+    /// document. Returns `None` for messages that aren't stanzas.
+    pub fn stanza_kind(&self) -> Option<StanzaKind> {
+        self.fields.direction.as_ref()?;
+        Some(match root_element_name(&self.fields.message) {
+            Some("iq") => StanzaKind::Iq,
+            Some("message") => StanzaKind::Message,
+            Some("presence") => StanzaKind::Presence,
+            _ => StanzaKind::Other,
+        })
+    }
+
+    /// Returns the change of connection properties this message marks, if any. Stream headers
+    /// are often logged without a direction, so they're recognized in any message.
+    pub fn channel_boundary(&self) -> Option<ChannelBoundary> {
+        let xml = &self.fields.message;
+        let namespace = root_attribute(xml, "xmlns");
+        match (root_element_name(xml)?, namespace) {
+            ("stream", _) if xml.trim_start().starts_with('<') && !is_end_tag(xml) => {
+                Some(ChannelBoundary::StreamStart)
+            }
+            ("open", Some(FRAMING_NAMESPACE)) => Some(ChannelBoundary::StreamStart),
+            _ if self.fields.direction != Some(StanzaDirection::In) => None,
+            ("proceed", Some(TLS_NAMESPACE)) => Some(ChannelBoundary::Tls),
+            ("compressed", Some(COMPRESSION_NAMESPACE)) => Some(ChannelBoundary::Compression),
+            _ => None,
+        }
+    }
+
+    /// Whether the message ends the stream: a stream error, a closing stream tag or an RFC 7395
+    /// `<close/>`.
+    pub fn is_stream_end(&self) -> bool {
+        let xml = &self.fields.message;
+        match root_element_name(xml) {
+            Some("stream") => is_end_tag(xml),
+            Some("error") => xml.trim_start().starts_with("<stream:"),
+            Some("close") => root_attribute(xml, "xmlns") == Some(FRAMING_NAMESPACE),
+            _ => false,
+        }
+    }
+
+    /// Whether the stream is restarted after this message: once TLS or compression is
+    /// established, and after successful SASL authentication.
+    pub fn precedes_stream_restart(&self) -> bool {
+        match self.channel_boundary() {
+            Some(ChannelBoundary::Tls | ChannelBoundary::Compression) => true,
+            Some(ChannelBoundary::StreamStart) => false,
+            None => {
+                self.fields.direction == Some(StanzaDirection::In)
+                    && root_element_name(&self.fields.message) == Some("success")
+                    && root_attribute(&self.fields.message, "xmlns") == Some(SASL_NAMESPACE)
+            }
+        }
+    }
+
+    /// Returns the defined condition of a stream error, e.g. `conflict`.
+    pub fn stream_error_condition(&self) -> Option<&str> {
+        let xml = &self.fields.message;
+        if root_element_name(xml) != Some("error") || !xml.trim_start().starts_with("<stream:") {
+            return None;
+        }
+        root_element_name(xml.split_once('>')?.1)
+    }
+
+    /// Whether the stanza is a XEP-0199 ping or a whitespace keepalive.
+    pub fn is_ping(&self) -> bool {
+        if self.fields.direction.is_none() {
+            return false;
+        }
+        let xml = &self.fields.message;
+        if xml.trim().is_empty() {
+            return true;
+        }
+        root_element_name(xml) == Some("iq")
+            && root_attribute(xml, "type") == Some("get")
+            && xml.contains(PING_NAMESPACE)
+    }
+
+    /// Returns the value of the attribute `name` on the stanza's root element.
+    pub fn stanza_attribute(&self, name: &str) -> Option<&str> {
+        self.fields.direction.as_ref()?;
+        root_attribute(&self.fields.message, name)
+    }
+
+    /// Returns the text of the `<body/>` of a `<message/>` stanza in the first of `languages`
+    /// it has one in, e.g. `["fr", "en"]`. A language also matches its regional variants, so
+    /// `en` matches `en-US`. Bodies without `xml:lang` are in the language of the stanza. Falls
+    /// back to the first body if none matches.
+    pub fn body(&self, languages: &[String]) -> Option<String> {
+        if self.stanza_kind() != Some(StanzaKind::Message) {
+            return None;
+        }
+        let reader = ParserConfig::new().create_reader(self.fields.message.as_bytes());
+
+        // Language and text of each body, in document order
+        let mut bodies: Vec<(Option<String>, String)> = vec![];
+        let mut stanza_lang = None;
+        let mut depth = 0;
+        let mut in_body = false;
+        for event in reader {
+            match event.ok()? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    depth += 1;
+                    let lang = attributes
+                        .into_iter()
+                        .find(|a| {
+                            a.name.prefix.as_deref() == Some("xml") && a.name.local_name == "lang"
+                        })
+                        .map(|a| a.value);
+                    if depth == 1 {
+                        stanza_lang = lang;
+                    } else if depth == 2 && name.local_name == "body" {
+                        in_body = true;
+                        bodies.push((lang.or_else(|| stanza_lang.clone()), String::new()));
+                    }
+                }
+                XmlEvent::EndElement { .. } => {
+                    depth -= 1;
+                    in_body = false;
+                }
+                XmlEvent::Characters(text) | XmlEvent::CData(text) if in_body => {
+                    if let Some((_, body)) = bodies.last_mut() {
+                        body.push_str(&text);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let matches = |lang: &str, preferred: &str| {
+            lang.eq_ignore_ascii_case(preferred)
+                || lang
+                    .split_once('-')
+                    .is_some_and(|(primary, _)| primary.eq_ignore_ascii_case(preferred))
+        };
+        let preferred = languages.iter().find_map(|preferred| {
+            bodies
+                .iter()
+                .position(|(lang, _)| lang.as_deref().is_some_and(|lang| matches(lang, preferred)))
+        });
+        bodies
+            .into_iter()
+            .nth(preferred.unwrap_or(0))
+            .map(|(_, body)| body)
+    }
+
+    /// Returns true if the stanza's root element has a namespace prefix, like `stream:features`.
+    pub fn stanza_has_prefix(&self) -> bool {
+        root_start_tag(&self.fields.message)
+            .and_then(|tag| tag.split(|c: char| c.is_whitespace()).next())
+            .is_some_and(|name| name.contains(':'))
+    }
+
+    /// Returns true for ERROR-level messages and for stanzas of type `error`.
+    pub fn is_error(&self) -> bool {
+        if self.level == Level::Error {
+            return true;
+        }
+        self.fields.direction.is_some()
+            && root_attribute(&self.fields.message, "type") == Some("error")
+    }
+
+    /// Content-addressed hash of the canonicalized stanza. Returns `None` for messages that
+    /// aren't stanzas or can't be parsed.
+    pub fn stanza_hash(&self, options: CanonicalizeOptions) -> Option<u64> {
+        self.fields.direction.as_ref()?;
+        let canonical = canonicalize(&self.fields.message, options).ok()?;
+        Some(content_hash(canonical.as_bytes()))
+    }
+
+    /// `stanza_hash` with the default options as 16 hex digits, as matched by the `hash`
+    /// filter and given to templates.
+    pub fn stanza_hash_hex(&self) -> Option<String> {
+        self.stanza_hash(CanonicalizeOptions::default())
+            .map(|hash| format!("{hash:016x}"))
+    }
+
     pub fn highlighted_stanza_xml(&self, syntax_set: &SyntaxSet, theme: &Theme) -> Result<String> {
         if self.fields.direction.is_none() {
             return Ok(self.fields.message.to_string());
@@ -78,16 +425,40 @@ impl LogMessage {
             let escaped = as_24_bit_terminal_escaped(&ranges[..], true);
             buf.push_str(&escaped);
         }
+        // Reset the colors so they don't bleed into what follows when the output isn't
+        // going to a terminal that resets them itself, e.g. CI logs
+        buf.push_str("\x1b[0m");
 
         Ok(buf)
     }
 
+    pub fn highlighted_stanza_html(&self, syntax_set: &SyntaxSet, theme: &Theme) -> Result<String> {
+        if self.fields.direction.is_none() {
+            return Ok(format!(
+                "<pre class=\"log\">{}</pre>",
+                escape_html(&self.fields.message)
+            ));
+        }
+
+        let xml = self.pretty_printed_xml()?;
+        let syntax = syntax_set
+            .find_syntax_by_extension("xml")
+            .ok_or(anyhow::format_err!("Missing syntax reference for XML."))?;
+
+        Ok(highlighted_html_for_string(
+            &xml, syntax_set, syntax, theme,
+        )?)
+    }
+
     pub fn highlighted_stanza_xml_text(
         &self,
         syntax_set: &SyntaxSet,
         theme: &Theme,
     ) -> Result<Vec<Line<'static>>> {
         if self.fields.direction.is_none() {
+            if let Some(json) = self.pretty_printed_json() {
+                return highlighted_text(&json, "json", syntax_set, theme);
+            }
             let mut lines = vec![];
             for line in LinesWithEndings::from(&self.fields.message) {
                 lines.push(Line::styled(
@@ -98,34 +469,218 @@ impl LogMessage {
             return Ok(lines);
         }
 
-        let xml = self.pretty_printed_xml()?;
+        highlighted_text(&self.pretty_printed_xml()?, "xml", syntax_set, theme)
+    }
+}
 
-        let mut lines = Vec::<Line>::new();
-        let syntax = syntax_set
-            .find_syntax_by_extension("xml")
-            .ok_or(anyhow::format_err!("Missing syntax reference for XML."))?;
-        let mut highlighter = HighlightLines::new(syntax, theme);
+#[cfg(test)]
+impl LogMessage {
+    /// Builds a message logged at `timestamp`, an RFC 3339 date, by the Prose client.
+    pub fn for_test(
+        timestamp: &str,
+        level: Level,
+        direction: Option<StanzaDirection>,
+        message: &str,
+    ) -> Self {
+        LogMessage {
+            timestamp: timestamp.parse().expect("valid timestamp"),
+            level,
+            fields: Fields {
+                message: message.to_string(),
+                direction,
+            },
+            target: "prose_xmpp::client".to_string(),
+            span: None,
+            spans: None,
+            source: 0,
+            sequence: 0,
+            lint_warnings: vec![],
+            related_stanza: None,
+            keepalive: false,
+            preferred_body: None,
+        }
+    }
+}
 
-        for line in LinesWithEndings::from(&xml) {
-            let line_spans = highlighter
-                .highlight_line(line, &syntax_set)?
-                .into_iter()
-                .map(|segment| {
-                    into_span(segment).map(|span| {
-                        let mut style = ratatui::style::Style::default();
-                        if let Some(fg) = span.style.fg {
-                            style = style.fg(fg);
-                        }
-                        ratatui::text::Span {
-                            content: Cow::Owned(span.content.into_owned()),
-                            style,
-                        }
-                    })
+/// Highlights `text` with the syntax of files with the `extension` into terminal lines.
+fn highlighted_text(
+    text: &str,
+    extension: &str,
+    syntax_set: &SyntaxSet,
+    theme: &Theme,
+) -> Result<Vec<Line<'static>>> {
+    let mut lines = Vec::<Line>::new();
+    let syntax = syntax_set
+        .find_syntax_by_extension(extension)
+        .ok_or(anyhow::format_err!(
+            "Missing syntax reference for {extension}."
+        ))?;
+    let mut highlighter = HighlightLines::new(syntax, theme);
+
+    for line in LinesWithEndings::from(text) {
+        let line_spans = highlighter
+            .highlight_line(line, syntax_set)?
+            .into_iter()
+            .map(|segment| {
+                into_span(segment).map(|span| {
+                    let mut style = ratatui::style::Style::default();
+                    if let Some(fg) = span.style.fg {
+                        style = style.fg(fg);
+                    }
+                    ratatui::text::Span {
+                        content: Cow::Owned(span.content.into_owned()),
+                        style,
+                    }
                 })
-                .collect::<Result<Vec<ratatui::text::Span<'static>>, _>>()?;
-            lines.push(line_spans.into());
+            })
+            .collect::<Result<Vec<ratatui::text::Span<'static>>, _>>()?;
+        lines.push(line_spans.into());
+    }
+
+    Ok(lines)
+}
+
+/// Returns the contents of the first start tag in `xml` (e.g. `iq type='get'`), skipping
+/// declarations and comments.
+fn root_start_tag(xml: &str) -> Option<&str> {
+    let mut rest = xml;
+    loop {
+        let start = rest.find('<')?;
+        rest = &rest[start + 1..];
+        if !rest.starts_with(['?', '!']) {
+            break;
         }
+    }
+    let end = rest.find('>').unwrap_or(rest.len());
+    Some(rest[..end].trim_end_matches('/'))
+}
+
+fn is_end_tag(xml: &str) -> bool {
+    root_start_tag(xml).is_some_and(|tag| tag.starts_with('/'))
+}
+
+/// Returns the local name of the first element in `xml`, skipping declarations and comments.
+/// For an end tag, e.g. a closing `</stream:stream>`, it's the name of the element it closes.
+fn root_element_name(xml: &str) -> Option<&str> {
+    let tag = root_start_tag(xml)?;
+    let rest = tag.strip_prefix('/').unwrap_or(tag);
+    let end = rest
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .unwrap_or(rest.len());
+    let name = &rest[..end];
+    Some(name.rsplit(':').next().unwrap_or(name))
+}
+
+/// Returns the value of the attribute `name` on the first element in `xml`.
+fn root_attribute<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = root_start_tag(xml)?;
+    while let Some(eq) = rest.find('=') {
+        let attribute = rest[..eq].trim_end();
+        let attribute = attribute
+            .rsplit(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or(attribute);
+        let value = rest[eq + 1..].trim_start();
+        let quote = value.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        let end = value[1..].find(quote)? + 1;
+        if attribute == name {
+            return Some(&value[1..end]);
+        }
+        rest = &value[end + 1..];
+    }
+    None
+}
+
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(direction: Option<StanzaDirection>, xml: &str) -> LogMessage {
+        LogMessage::for_test("2024-01-01T00:00:00Z", Level::Info, direction, xml)
+    }
+
+    #[test]
+    fn root_element_name_skips_prefixes_declarations_and_end_tags() {
+        assert_eq!(root_element_name("<iq type='get'/>"), Some("iq"));
+        assert_eq!(
+            root_element_name("<?xml version='1.0'?><stream:stream>"),
+            Some("stream")
+        );
+        assert_eq!(root_element_name("</stream:stream>"), Some("stream"));
+        assert_eq!(root_element_name("no xml"), None);
+    }
+
+    #[test]
+    fn closing_stream_tag_ends_the_stream() {
+        let message = message(Some(StanzaDirection::In), "</stream:stream>");
+        assert!(message.is_stream_end());
+        assert_eq!(message.channel_boundary(), None);
+    }
+
+    #[test]
+    fn stream_error_ends_the_stream() {
+        let xml =
+            "<stream:error><conflict xmlns='urn:ietf:params:xml:ns:xmpp-streams'/></stream:error>";
+        assert!(message(Some(StanzaDirection::In), xml).is_stream_end());
+        // A stanza error isn't a stream error
+        let xml = "<iq type='error'><error type='cancel'/></iq>";
+        assert!(!message(Some(StanzaDirection::In), xml).is_stream_end());
+    }
+
+    #[test]
+    fn stanza_hash_hex_is_only_given_for_stanzas() {
+        let iq = message(Some(StanzaDirection::Out), "<iq type='get' id='1'/>");
+        let reordered = message(Some(StanzaDirection::In), "<iq id='1' type='get'/>");
+        let hash = iq.stanza_hash_hex().unwrap();
+        assert_eq!(hash.len(), 16);
+        assert_eq!(reordered.stanza_hash_hex(), Some(hash));
+        assert_eq!(
+            message(None, "<iq type='get' id='1'/>").stanza_hash_hex(),
+            None
+        );
+        assert_eq!(
+            message(Some(StanzaDirection::Out), "<iq>").stanza_hash_hex(),
+            None
+        );
+    }
+
+    #[test]
+    fn framing_close_ends_the_stream() {
+        let xml = "<close xmlns='urn:ietf:params:xml:ns:xmpp-framing'/>";
+        assert!(message(Some(StanzaDirection::Out), xml).is_stream_end());
+        assert!(!message(Some(StanzaDirection::Out), "<close/>").is_stream_end());
+    }
 
-        Ok(lines)
+    #[test]
+    fn stream_headers_start_a_stream() {
+        let xml = "<stream:stream xmlns='jabber:client' to='prose.org'>";
+        assert_eq!(
+            message(None, xml).channel_boundary(),
+            Some(ChannelBoundary::StreamStart)
+        );
+        let xml = "<open xmlns='urn:ietf:params:xml:ns:xmpp-framing' to='prose.org'/>";
+        assert_eq!(
+            message(Some(StanzaDirection::Out), xml).channel_boundary(),
+            Some(ChannelBoundary::StreamStart)
+        );
+        assert!(!message(None, "<stream:stream>").is_stream_end());
     }
 }
@@ -0,0 +1,878 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::Args;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+use crate::framing::unwrap_framing;
+use crate::import::{
+    capture_direction, is_stream_capture, parse_raw_line, parse_stream_capture, raw_message,
+};
+use crate::log_message::{Level, LogMessage, StanzaDirection};
+
+/// Where log messages are read from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogInput {
+    File(PathBuf),
+    Stdin,
+}
+
+impl std::fmt::Display for LogInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogInput::File(path) => write!(f, "{}", path.display()),
+            LogInput::Stdin => f.write_str("standard input"),
+        }
+    }
+}
+
+/// Text encoding of a log, as detected from its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Detects the encoding from a byte order mark, or for UTF-16 without one, from the zero
+    /// bytes of ASCII characters. Returns the encoding and the length of the byte order mark.
+    fn sniff(start: &[u8]) -> (Encoding, usize) {
+        match start {
+            [0xef, 0xbb, 0xbf, ..] => (Encoding::Utf8, 3),
+            [0xff, 0xfe, ..] => (Encoding::Utf16Le, 2),
+            [0xfe, 0xff, ..] => (Encoding::Utf16Be, 2),
+            [b, 0, ..] if *b != 0 => (Encoding::Utf16Le, 0),
+            [0, b, ..] if *b != 0 => (Encoding::Utf16Be, 0),
+            _ => (Encoding::Utf8, 0),
+        }
+    }
+
+    /// Decodes UTF-16 `bytes` to UTF-8, replacing unpaired surrogates with U+FFFD. Returns the
+    /// text and the number of lines with replacements.
+    fn decode_utf16(self, bytes: &[u8]) -> (Vec<u8>, usize) {
+        let units = bytes.chunks(2).map(|pair| match (self, pair) {
+            (Encoding::Utf16Be, [high, low]) => u16::from_be_bytes([*high, *low]),
+            (_, [low, high]) => u16::from_le_bytes([*low, *high]),
+            // A dangling odd byte can't be decoded
+            _ => 0xfffd,
+        });
+
+        let mut text = String::with_capacity(bytes.len() / 2);
+        let mut invalid_lines = 0;
+        let mut line_is_invalid = false;
+        for c in char::decode_utf16(units) {
+            let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+            line_is_invalid |= c == char::REPLACEMENT_CHARACTER;
+            if c == '\n' {
+                invalid_lines += line_is_invalid as usize;
+                line_is_invalid = false;
+            }
+            text.push(c);
+        }
+        (text.into_bytes(), invalid_lines + line_is_invalid as usize)
+    }
+}
+
+/// Format of the lines of a line-based log. It's detected once per file rather than per line,
+/// so that a corrupt line of a Prose JSON log is reported instead of being read as a line of a
+/// raw stream dump.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LineFormat {
+    /// A Prose JSON log, with a JSON object per line.
+    Json,
+    /// Another client's raw stream dump, read with [`parse_raw_line`].
+    Raw,
+}
+
+impl LineFormat {
+    /// Number of bytes at the start of a log looked at to detect its format.
+    const SNIFF_LEN: usize = 64 * 1024;
+
+    /// Detects the format from the start of a log: JSON if most of its lines are JSON
+    /// objects. Returns `None` if there are no lines yet.
+    fn sniff(start: &[u8]) -> Option<LineFormat> {
+        let start = String::from_utf8_lossy(&start[..start.len().min(Self::SNIFF_LEN)]);
+        let (json, lines) = start
+            .lines()
+            .map(str::trim_start)
+            .filter(|line| !line.is_empty())
+            .fold((0, 0), |(json, lines), line| {
+                (json + line.starts_with('{') as usize, lines + 1)
+            });
+        match lines {
+            0 => None,
+            _ if json * 2 >= lines => Some(LineFormat::Json),
+            _ => Some(LineFormat::Raw),
+        }
+    }
+
+    fn parse(self, line: &str) -> Result<LogMessage> {
+        match self {
+            LineFormat::Json => line.parse(),
+            LineFormat::Raw => Ok(parse_raw_line(line)),
+        }
+    }
+}
+
+/// Default limit on the length of a line, beyond which it's truncated.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Number of bytes of a truncated line quoted in the message flagging it.
+const TRUNCATED_LINE_QUOTE: usize = 200;
+
+/// How often a followed file is checked for appended lines once its end is reached.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Command line options controlling how logs are read, shared by all commands.
+#[derive(Args, Debug, Clone)]
+pub struct ReadOptions {
+    /// Turn lines that fail to parse into error messages instead of stopping.
+    #[arg(long)]
+    pub lenient: bool,
+    /// Longest line to read, in bytes. Longer lines, e.g. in corrupted files without line
+    /// breaks, are truncated and flagged as errors instead of being held in memory.
+    #[arg(long, default_value_t = DEFAULT_MAX_LINE_LENGTH)]
+    pub max_line_length: usize,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            lenient: false,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+        }
+    }
+}
+
+/// Command line arguments selecting the log to read.
+#[derive(Args, Debug, Clone)]
+pub struct SourceArgs {
+    /// Log file, or `-` to read standard input.
+    #[arg(long)]
+    pub path: PathBuf,
+    #[command(flatten)]
+    pub options: ReadOptions,
+}
+
+impl SourceArgs {
+    pub fn source(&self) -> LogSource {
+        LogSource {
+            options: self.options.clone(),
+            ..LogSource::new(&self.path)
+        }
+    }
+}
+
+/// A log to read messages from, either a Prose JSON log, another client's line-based stream
+/// dump or a raw XML stream capture. Every command reads logs through this, so that formats
+/// and error handling are the same everywhere. Messages can be read as they're parsed, or all
+/// at once.
+#[derive(Debug, Clone)]
+pub struct LogSource {
+    pub input: LogInput,
+    pub options: ReadOptions,
+}
+
+impl LogSource {
+    /// Reads from the file at `path`, or from standard input if `path` is `-`.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let input = if path == Path::new("-") {
+            LogInput::Stdin
+        } else {
+            LogInput::File(path.to_path_buf())
+        };
+        LogSource {
+            input,
+            options: ReadOptions::default(),
+        }
+    }
+
+    /// Size of the input in bytes, if known up front.
+    pub fn size(&self) -> Option<u64> {
+        match &self.input {
+            LogInput::File(path) => std::fs::metadata(path).map(|m| m.len()).ok(),
+            LogInput::Stdin => None,
+        }
+    }
+
+    /// Opens the log to read messages one by one. Only UTF-8 line logs are read as they're
+    /// parsed: UTF-16 logs and stream captures are read and decoded whole first.
+    pub async fn open(&self) -> Result<MessageReader> {
+        let mut reader: Box<dyn AsyncBufRead + Unpin + Send> = match &self.input {
+            LogInput::File(path) => {
+                let file = tokio::fs::File::open(path)
+                    .await
+                    .with_context(|| format!("Failed to open {}", path.display()))?;
+                Box::new(tokio::io::BufReader::new(file))
+            }
+            LogInput::Stdin => Box::new(tokio::io::BufReader::new(tokio::io::stdin())),
+        };
+        let mut decoder = Decoder::new(self);
+
+        let (encoding, bom_len) = Encoding::sniff(reader.fill_buf().await?);
+        reader.consume(bom_len);
+        if encoding != Encoding::Utf8 {
+            let mut bytes = vec![];
+            reader.read_to_end(&mut bytes).await?;
+            let (text, invalid_lines) = encoding.decode_utf16(&bytes);
+            decoder.invalid_lines = invalid_lines;
+            reader = Box::new(Cursor::new(text));
+        }
+
+        let start = reader.fill_buf().await?;
+        decoder.format = LineFormat::sniff(start);
+        if is_stream_capture(&String::from_utf8_lossy(start)) {
+            let mut capture = vec![];
+            reader.read_to_end(&mut capture).await?;
+            return Ok(MessageReader {
+                reader: None,
+                pending: decoder.decode_capture(&capture),
+                decoder,
+                line: vec![],
+                line_len: 0,
+                follow: false,
+                caught_up: false,
+            });
+        }
+
+        Ok(MessageReader {
+            reader: Some(reader),
+            pending: VecDeque::new(),
+            decoder,
+            line: vec![],
+            line_len: 0,
+            follow: false,
+            caught_up: false,
+        })
+    }
+
+    /// Reads all messages of the log, calling `on_read` with the number of bytes read as it
+    /// goes. Stops early, returning the messages read so far, once `on_read` returns false.
+    ///
+    /// If lines had to be decoded with replacement characters, a warning message saying how
+    /// many is added at the end. Lines that fail to parse are returned separately instead of
+    /// failing, and also kept as error messages in lenient mode.
+    ///
+    /// Also returns where reading stopped, if lines appended to the file later can be read
+    /// with [`LogSource::read_appended`]. That's only the case for complete UTF-8 line-based
+    /// files ending with a line break.
+    pub fn read_all(
+        &self,
+        mut on_read: impl FnMut(u64) -> bool,
+    ) -> Result<(Vec<LogMessage>, Vec<MalformedLine>, Option<ReadPosition>)> {
+        let mut reader: Box<dyn BufRead> = match &self.input {
+            LogInput::File(path) => {
+                let file = std::fs::File::open(path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?;
+                Box::new(std::io::BufReader::new(file))
+            }
+            LogInput::Stdin => Box::new(std::io::stdin().lock()),
+        };
+        let mut decoder = Decoder::new(self);
+        decoder.malformed = Some(vec![]);
+
+        let (encoding, bom_len) = Encoding::sniff(reader.fill_buf()?);
+        reader.consume(bom_len);
+        if encoding != Encoding::Utf8 {
+            let mut bytes = vec![];
+            reader.read_to_end(&mut bytes)?;
+            let (text, invalid_lines) = encoding.decode_utf16(&bytes);
+            decoder.invalid_lines = invalid_lines;
+            reader = Box::new(Cursor::new(text));
+        }
+
+        let mut messages = vec![];
+        let mut position = None;
+        let start = reader.fill_buf()?;
+        decoder.format = LineFormat::sniff(start);
+        if is_stream_capture(&String::from_utf8_lossy(start)) {
+            let mut capture = vec![];
+            reader.read_to_end(&mut capture)?;
+            on_read(capture.len() as u64);
+            messages.extend(
+                decoder
+                    .decode_capture(&capture)
+                    .into_iter()
+                    .map(|(_, message)| message),
+            );
+        } else {
+            let read = decoder.read_lines(&mut reader, true, &mut on_read, &mut messages)?;
+            let appendable = encoding == Encoding::Utf8 && matches!(self.input, LogInput::File(_));
+            if read.complete && appendable {
+                position = Some(ReadPosition {
+                    offset: bom_len as u64 + read.terminated_len,
+                    line_number: decoder.line_number,
+                    sequence: decoder.sequence,
+                    last_timestamp: decoder.last_timestamp,
+                    format: decoder.format,
+                });
+            }
+        }
+
+        messages.extend(decoder.encoding_warning_message());
+        let malformed = decoder.malformed.take().unwrap_or_default();
+        Ok((messages, malformed, position))
+    }
+
+    /// Reads the complete lines appended to the file since `position`, returning their
+    /// messages, the lines that failed to parse and the new position. Returns `None` if the
+    /// file got shorter, e.g. because it was truncated or replaced, and has to be read again
+    /// from the start.
+    pub fn read_appended(&self, position: &ReadPosition) -> Result<Option<AppendedLines>> {
+        let LogInput::File(path) = &self.input else {
+            return Ok(None);
+        };
+        let mut file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        if file.metadata()?.len() < position.offset {
+            return Ok(None);
+        }
+        file.seek(SeekFrom::Start(position.offset))?;
+
+        let mut decoder = Decoder::new(self);
+        decoder.line_number = position.line_number;
+        decoder.sequence = position.sequence;
+        decoder.last_timestamp = position.last_timestamp;
+        decoder.format = position.format;
+        decoder.malformed = Some(vec![]);
+        let mut messages = vec![];
+        let mut reader = std::io::BufReader::new(file);
+        let read = decoder.read_lines(&mut reader, false, &mut |_| true, &mut messages)?;
+
+        messages.extend(decoder.encoding_warning_message());
+        let position = ReadPosition {
+            offset: position.offset + read.terminated_len,
+            line_number: decoder.line_number,
+            sequence: decoder.sequence,
+            last_timestamp: decoder.last_timestamp,
+            format: decoder.format,
+        };
+        let malformed = decoder.malformed.take().unwrap_or_default();
+        Ok(Some((messages, malformed, position)))
+    }
+}
+
+/// A line that failed to parse, set aside by [`LogSource::read_all`] and
+/// [`LogSource::read_appended`] instead of failing the whole read.
+#[derive(Debug, Clone)]
+pub struct MalformedLine {
+    pub line_number: usize,
+    pub line: String,
+    pub error: String,
+    /// Index of the file the line was read from when browsing several files at once.
+    pub source: usize,
+}
+
+/// The messages of the lines appended to a file, the lines that failed to parse and where
+/// reading stopped, as returned by [`LogSource::read_appended`].
+pub type AppendedLines = (Vec<LogMessage>, Vec<MalformedLine>, ReadPosition);
+
+/// Where reading a line-based log file stopped: after its last complete line.
+#[derive(Debug, Clone)]
+pub struct ReadPosition {
+    offset: u64,
+    line_number: usize,
+    sequence: usize,
+    last_timestamp: DateTime<Utc>,
+    format: Option<LineFormat>,
+}
+
+/// Outcome of [`Decoder::read_lines`].
+struct LinesRead {
+    /// Number of bytes up to the end of the last line ending with a line break.
+    terminated_len: u64,
+    /// Whether the input was read to the end, and ended with a line break.
+    complete: bool,
+}
+
+/// Reads the messages of a log one by one, with the line each one starts on.
+///
+/// Once all messages are read, warns on standard error if lines had to be decoded with
+/// replacement characters.
+pub struct MessageReader {
+    /// Input still to be read line by line, or `None` for captures, which are read whole.
+    reader: Option<Box<dyn AsyncBufRead + Unpin + Send>>,
+    pending: VecDeque<(usize, LogMessage)>,
+    decoder: Decoder,
+    /// The line being read, kept while waiting for the rest of it when following.
+    line: Vec<u8>,
+    /// Length of `line` as read, including dropped bytes.
+    line_len: usize,
+    follow: bool,
+    /// Set once the end of a followed file was first reached.
+    caught_up: bool,
+}
+
+impl MessageReader {
+    /// Waits for lines appended to the file once its end is reached, like `tail -f`, instead
+    /// of stopping. [`MessageReader::next_message`] then returns `None` once, when all the
+    /// lines already in the file were read. Standard input and stream captures are still read
+    /// until they end.
+    pub fn follow(&mut self) {
+        self.follow = matches!(self.decoder.input, LogInput::File(_)) && self.reader.is_some();
+    }
+
+    pub async fn next_message(&mut self) -> Result<Option<(usize, LogMessage)>> {
+        loop {
+            if let Some(message) = self.pending.pop_front() {
+                return Ok(Some(message));
+            }
+            let Some(reader) = &mut self.reader else {
+                self.finish();
+                return Ok(None);
+            };
+            loop {
+                let available = reader.fill_buf().await?;
+                if available.is_empty() && self.follow {
+                    if !std::mem::replace(&mut self.caught_up, true) {
+                        return Ok(None);
+                    }
+                    // Wait for the next line, or for the rest of a line still being written
+                    tokio::time::sleep(FOLLOW_POLL_INTERVAL).await;
+                    continue;
+                }
+                let (consumed, done) = self.decoder.take_line_chunk(available, &mut self.line);
+                reader.consume(consumed);
+                self.line_len += consumed;
+                if done {
+                    break;
+                }
+            }
+            let line = std::mem::take(&mut self.line);
+            let len = std::mem::take(&mut self.line_len);
+            if len == 0 {
+                self.reader = None;
+                continue;
+            }
+            self.pending.extend(self.decoder.decode_line(&line, len)?);
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(warning) = self.decoder.encoding_warning() {
+            eprintln!("Warning: {warning}");
+            self.decoder.invalid_lines = 0;
+        }
+    }
+}
+
+/// Turns lines or captures into messages, the same way for sync and async reads.
+struct Decoder {
+    input: LogInput,
+    lenient: bool,
+    max_line_length: usize,
+    direction: StanzaDirection,
+    line_number: usize,
+    /// Number of messages decoded so far, the last one's [`LogMessage::sequence`].
+    sequence: usize,
+    /// Timestamp of the last message, given to lines that fail to parse in lenient mode so
+    /// that they stay in place when sorting by time.
+    last_timestamp: DateTime<Utc>,
+    /// Number of lines with bytes that aren't valid in the encoding of the log, which are
+    /// replaced with U+FFFD instead of failing.
+    invalid_lines: usize,
+    /// Lines that failed to parse, when they're collected instead of failing. They're still
+    /// turned into error messages in lenient mode.
+    malformed: Option<Vec<MalformedLine>>,
+    /// Format of the lines, detected from the start of the log. `None` until there are lines.
+    format: Option<LineFormat>,
+}
+
+impl Decoder {
+    fn new(source: &LogSource) -> Self {
+        let direction = match &source.input {
+            LogInput::File(path) => capture_direction(path),
+            LogInput::Stdin => StanzaDirection::In,
+        };
+        Decoder {
+            input: source.input.clone(),
+            lenient: source.options.lenient,
+            max_line_length: source.options.max_line_length,
+            direction,
+            line_number: 0,
+            sequence: 0,
+            last_timestamp: DateTime::default(),
+            invalid_lines: 0,
+            malformed: None,
+            format: None,
+        }
+    }
+
+    /// Appends the bytes of the current line at the start of `available` to `line`, up to
+    /// the maximum line length. Returns the number of bytes to consume, including dropped
+    /// ones, and whether the line is complete. An empty `available` means the end of input.
+    fn take_line_chunk(&self, available: &[u8], line: &mut Vec<u8>) -> (usize, bool) {
+        let (chunk, done) = match available.iter().position(|b| *b == b'\n') {
+            Some(end) => (&available[..=end], true),
+            None => (available, available.is_empty()),
+        };
+        // The line ending doesn't count towards the limit, so keep one more byte for the `\r`
+        // of a `\r\n` and always keep the `\n`
+        let content = chunk.strip_suffix(b"\n").unwrap_or(chunk);
+        let room = (self.max_line_length + 1).saturating_sub(line.len());
+        line.extend_from_slice(&content[..content.len().min(room)]);
+        if content.len() < chunk.len() {
+            line.push(b'\n');
+        }
+        (chunk.len(), done)
+    }
+
+    /// Reads `reader` line by line, adding the messages to `messages` and calling `on_read`
+    /// with the length of each line. Stops once `on_read` returns false. The last line is
+    /// skipped if it has no line break and `unterminated` is false, as it may still be being
+    /// written.
+    fn read_lines(
+        &mut self,
+        reader: &mut dyn BufRead,
+        unterminated: bool,
+        on_read: &mut dyn FnMut(u64) -> bool,
+        messages: &mut Vec<LogMessage>,
+    ) -> Result<LinesRead> {
+        let mut line = vec![];
+        let mut terminated_len = 0;
+        loop {
+            let mut len = 0;
+            let mut terminated;
+            loop {
+                let available = reader.fill_buf()?;
+                let (consumed, done) = self.take_line_chunk(available, &mut line);
+                terminated = consumed > 0 && available[consumed - 1] == b'\n';
+                reader.consume(consumed);
+                len += consumed;
+                if done {
+                    break;
+                }
+            }
+            if len == 0 {
+                return Ok(LinesRead {
+                    terminated_len,
+                    complete: true,
+                });
+            }
+            if !terminated && !unterminated {
+                return Ok(LinesRead {
+                    terminated_len,
+                    complete: false,
+                });
+            }
+            if !on_read(len as u64) {
+                return Ok(LinesRead {
+                    terminated_len,
+                    complete: false,
+                });
+            }
+            messages.extend(self.decode_line(&line, len)?.map(|(_, message)| message));
+            line.clear();
+            if terminated {
+                terminated_len += len as u64;
+            } else {
+                // An unterminated line is the last one, and can't be continued by appending
+                return Ok(LinesRead {
+                    terminated_len,
+                    complete: false,
+                });
+            }
+        }
+    }
+
+    /// Decodes a line read with its line ending, if any. `len` is the length of the line as
+    /// read, which is longer than `line` if it was truncated.
+    fn decode_line(
+        &mut self,
+        line: &[u8],
+        len: usize,
+    ) -> Result<impl Iterator<Item = (usize, LogMessage)> + 'static> {
+        self.line_number += 1;
+        let line_number = self.line_number;
+        let dropped = len > line.len();
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let truncated = dropped || line.len() > self.max_line_length;
+        let line = self.decode_text(line);
+
+        let parsed = if truncated {
+            let quote = &line[..line.floor_char_boundary(TRUNCATED_LINE_QUOTE)];
+            Ok(raw_message(
+                self.last_timestamp,
+                Level::Error,
+                &format!(
+                    "Line {line_number} is {len} bytes long, over the limit of {} bytes, and was truncated: {quote}…",
+                    self.max_line_length
+                ),
+                None,
+            ))
+        } else {
+            let format = *self.format.get_or_insert_with(|| {
+                LineFormat::sniff(line.as_bytes()).unwrap_or(LineFormat::Json)
+            });
+            format.parse(&line)
+        };
+        let message = match parsed {
+            Ok(message) => message,
+            Err(err) => {
+                if let Some(malformed) = &mut self.malformed {
+                    malformed.push(MalformedLine {
+                        line_number,
+                        line: line.clone(),
+                        error: format!("{err:#}"),
+                        source: 0,
+                    });
+                    if !self.lenient {
+                        return Ok(vec![].into_iter());
+                    }
+                }
+                if !self.lenient {
+                    return Err(err.context(format!("Failed to parse line {line_number}")));
+                }
+                raw_message(
+                    self.last_timestamp,
+                    Level::Error,
+                    &format!("Line {line_number} could not be parsed: {err:#}"),
+                    None,
+                )
+            }
+        };
+        self.last_timestamp = message.timestamp;
+
+        let messages = unwrap_framing(message)
+            .into_iter()
+            .map(|message| (line_number, self.numbered(message)))
+            .collect::<Vec<_>>();
+        Ok(messages.into_iter())
+    }
+
+    fn decode_capture(&mut self, capture: &[u8]) -> VecDeque<(usize, LogMessage)> {
+        let capture = match std::str::from_utf8(capture) {
+            Ok(capture) => capture.to_string(),
+            Err(_) => capture
+                .split(|b| *b == b'\n')
+                .map(|line| self.decode_text(line))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        parse_stream_capture(&capture, self.direction.clone())
+            .into_iter()
+            .flat_map(|(line_number, message)| {
+                unwrap_framing(message)
+                    .into_iter()
+                    .map(move |message| (line_number, message))
+            })
+            .map(|(line_number, message)| (line_number, self.numbered(message)))
+            .collect()
+    }
+
+    /// Sets the sequence of `message`, the next one decoded.
+    fn numbered(&mut self, mut message: LogMessage) -> LogMessage {
+        self.sequence += 1;
+        message.sequence = self.sequence;
+        message
+    }
+
+    /// Decodes `bytes` as UTF-8, replacing invalid sequences with U+FFFD.
+    fn decode_text(&mut self, bytes: &[u8]) -> String {
+        match String::from_utf8_lossy(bytes) {
+            std::borrow::Cow::Borrowed(text) => text.to_string(),
+            std::borrow::Cow::Owned(text) => {
+                self.invalid_lines += 1;
+                text
+            }
+        }
+    }
+
+    /// The warning of [`Decoder::encoding_warning`] as a message following the last one.
+    fn encoding_warning_message(&mut self) -> Option<LogMessage> {
+        let warning = self.encoding_warning()?;
+        let message = raw_message(self.last_timestamp, Level::Warn, &warning, None);
+        Some(self.numbered(message))
+    }
+
+    fn encoding_warning(&self) -> Option<String> {
+        let lines = match self.invalid_lines {
+            0 => return None,
+            1 => "1 line".to_string(),
+            n => format!("{n} lines"),
+        };
+        Some(format!(
+            "{lines} of {} had invalid text and were decoded with replacement characters",
+            self.input
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON_LINE: &str = r#"{"timestamp":"2024-03-01T12:00:00Z","level":"INFO","fields":{"message":"Connected"},"target":"prose_xmpp::client"}"#;
+
+    /// Writes `contents` to a file named `name` in a directory of its own, returning a source
+    /// reading it.
+    fn source(name: &str, contents: &[u8]) -> LogSource {
+        let dir = std::env::temp_dir().join(format!("log-source-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        LogSource::new(path)
+    }
+
+    fn read(source: &LogSource) -> (Vec<LogMessage>, Vec<MalformedLine>) {
+        let (messages, malformed, _) = source.read_all(|_| true).unwrap();
+        (messages, malformed)
+    }
+
+    #[test]
+    fn corrupt_lines_of_json_logs_are_malformed() {
+        let log = format!("{JSON_LINE}\n{{\"timestamp\": \"2024-03\n{JSON_LINE}\ngarbage\n");
+        let (messages, malformed) = read(&source("corrupt.log", log.as_bytes()));
+        assert_eq!(messages.len(), 2);
+        let lines = malformed.iter().map(|m| m.line_number).collect::<Vec<_>>();
+        assert_eq!(lines, [2, 4]);
+    }
+
+    #[test]
+    fn raw_dumps_are_read_line_by_line() {
+        let log = "2024-03-01 12:00:00 SENT: <presence/>\n{not json}\n2024-03-01 12:00:01 info\n";
+        let (messages, malformed) = read(&source("raw.log", log.as_bytes()));
+        assert!(malformed.is_empty());
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].fields.direction, Some(StanzaDirection::Out));
+        assert_eq!(messages[1].fields.message, "{not json}");
+    }
+
+    /// Reads `contents` with lines limited to `max_line_length` bytes.
+    fn read_limited(name: &str, contents: &str, max_line_length: usize) -> Vec<LogMessage> {
+        let source = LogSource {
+            options: ReadOptions {
+                max_line_length,
+                ..Default::default()
+            },
+            ..source(name, contents.as_bytes())
+        };
+        let (messages, malformed) = read(&source);
+        assert!(malformed.is_empty());
+        messages
+    }
+
+    fn is_truncated(message: &LogMessage) -> bool {
+        message.fields.message.contains("was truncated")
+    }
+
+    #[test]
+    fn lines_at_the_limit_are_not_truncated() {
+        let log = format!("{JSON_LINE}\n{JSON_LINE}\r\n{JSON_LINE}");
+        let messages = read_limited("at-limit.log", &log, JSON_LINE.len());
+        assert_eq!(messages.len(), 3);
+        assert!(!messages.iter().any(is_truncated));
+    }
+
+    #[test]
+    fn lines_over_the_limit_are_truncated() {
+        let log = format!("{JSON_LINE}\n{JSON_LINE}\r\n{JSON_LINE}\n");
+        let messages = read_limited("over-limit.log", &log, JSON_LINE.len() - 1);
+        assert!(messages.iter().all(is_truncated));
+        let len = JSON_LINE.len() + 2;
+        assert!(messages[1]
+            .fields
+            .message
+            .starts_with(&format!("Line 2 is {len} bytes long")));
+    }
+
+    #[test]
+    fn lines_longer_than_the_read_buffer_are_joined() {
+        let long_message = "x".repeat(20_000);
+        let long_line = JSON_LINE.replace("Connected", &long_message);
+        let log = format!("{long_line}\n{JSON_LINE}\n");
+        let messages = read_limited("long.log", &log, DEFAULT_MAX_LINE_LENGTH);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].fields.message, long_message);
+        assert_eq!(messages[1].fields.message, "Connected");
+    }
+
+    #[test]
+    fn reading_resumes_after_a_truncated_line() {
+        let long_line = JSON_LINE.replace("Connected", &"x".repeat(20_000));
+        let log = format!("{long_line}\n{JSON_LINE}\n");
+        let messages = read_limited("resumed.log", &log, 1_000);
+        assert_eq!(messages.len(), 2);
+        assert!(is_truncated(&messages[0]));
+        assert_eq!(messages[1].fields.message, "Connected");
+    }
+
+    #[test]
+    fn format_is_sniffed_from_most_lines() {
+        let json = format!("garbage\n{JSON_LINE}\n{JSON_LINE}\n");
+        assert_eq!(LineFormat::sniff(json.as_bytes()), Some(LineFormat::Json));
+        let raw = format!("SENT: <presence/>\nRECV: <presence/>\n{JSON_LINE}\n");
+        assert_eq!(LineFormat::sniff(raw.as_bytes()), Some(LineFormat::Raw));
+        assert_eq!(LineFormat::sniff(b"\n\n"), None);
+    }
+
+    fn append(source: &LogSource, contents: &str) {
+        let LogInput::File(path) = &source.input else {
+            unreachable!()
+        };
+        let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+        std::io::Write::write_all(&mut file, contents.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn appended_lines_are_read_once_complete() {
+        let source = source("growing.log", format!("{JSON_LINE}\n").as_bytes());
+        let (_, _, position) = source.read_all(|_| true).unwrap();
+
+        // The last line may still be being written
+        append(&source, &format!("{JSON_LINE}\n{}", &JSON_LINE[..20]));
+        let (messages, _, position) = source.read_appended(&position.unwrap()).unwrap().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].sequence, 2);
+
+        append(&source, &format!("{}\n", &JSON_LINE[20..]));
+        let (messages, malformed, position) = source.read_appended(&position).unwrap().unwrap();
+        assert!(malformed.is_empty());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].sequence, 3);
+
+        let (messages, ..) = source.read_appended(&position).unwrap().unwrap();
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn shortened_files_have_to_be_read_again() {
+        let source = source(
+            "rotated.log",
+            format!("{JSON_LINE}\n{JSON_LINE}\n").as_bytes(),
+        );
+        let (_, _, position) = source.read_all(|_| true).unwrap();
+        let LogInput::File(path) = &source.input else {
+            unreachable!()
+        };
+        std::fs::write(path, format!("{JSON_LINE}\n")).unwrap();
+        assert!(source.read_appended(&position.unwrap()).unwrap().is_none());
+    }
+
+    #[test]
+    fn unterminated_files_cant_be_appended_to() {
+        let source = source("unterminated.log", JSON_LINE.as_bytes());
+        let (messages, _, position) = source.read_all(|_| true).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(position.is_none());
+    }
+
+    #[test]
+    fn appended_lines_keep_the_format_of_the_file() {
+        let source = source("appended.log", format!("{JSON_LINE}\n").as_bytes());
+        let (_, _, position) = source.read_all(|_| true).unwrap();
+        append(&source, "SENT: <presence/>\n");
+
+        let (messages, malformed, _) = source.read_appended(&position.unwrap()).unwrap().unwrap();
+        assert!(messages.is_empty());
+        assert_eq!(malformed.len(), 1);
+        assert_eq!(malformed[0].line_number, 2);
+    }
+}
@@ -3,12 +3,31 @@ use std::path::PathBuf;
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 
-use crate::format::read_and_parse_json_lines;
-use crate::tui::browse_log_file;
+use crate::format::{read_and_parse_json_lines, PrintOptions};
+use crate::lint::{lint_log_file, LintOptions};
+use crate::log_source::SourceArgs;
+use crate::stats::print_stats;
+use crate::throttle::ThrottleOptions;
+use crate::tui::{browse_log_files, BrowseOptions};
 
+mod canonical;
+mod export;
 mod format;
+mod framing;
+mod html;
+mod import;
+mod lint;
 mod log_message;
+mod log_source;
+mod pairing;
 mod pretty_print;
+mod reconnect;
+mod redact;
+mod round_trip;
+mod stats;
+mod template;
+mod theme;
+mod throttle;
 mod tui;
 
 #[derive(Parser)]
@@ -20,21 +39,42 @@ struct LogParser {
 #[derive(Subcommand)]
 enum Command {
     Print {
-        #[arg(long)]
-        path: PathBuf,
-        #[arg(long)]
-        color: bool,
+        #[command(flatten)]
+        source: SourceArgs,
+        #[command(flatten)]
+        options: PrintOptions,
+    },
+    /// Checks stanzas against basic XMPP structural rules.
+    Lint {
+        #[command(flatten)]
+        source: SourceArgs,
+        #[command(flatten)]
+        options: LintOptions,
+    },
+    /// Prints summary statistics in a single pass, in constant memory for UTF-8 line logs.
+    Stats {
+        #[command(flatten)]
+        source: SourceArgs,
+        #[command(flatten)]
+        options: ThrottleOptions,
     },
     Browse {
-        #[arg(long)]
-        path: PathBuf,
+        /// Log file, or directory of per-account log files. Repeat to open several tabs.
+        #[arg(long, required = true)]
+        path: Vec<PathBuf>,
+        #[command(flatten)]
+        options: BrowseOptions,
     },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     match LogParser::parse().cmd {
-        Command::Print { path, color } => read_and_parse_json_lines(path, color).await,
-        Command::Browse { path } => browse_log_file(path).await,
+        Command::Print { source, options } => {
+            read_and_parse_json_lines(&source.source(), &options).await
+        }
+        Command::Lint { source, options } => lint_log_file(&source.source(), &options).await,
+        Command::Stats { source, options } => print_stats(&source.source(), &options).await,
+        Command::Browse { path, options } => browse_log_files(&path, &options).await,
     }
 }
@@ -0,0 +1,50 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::log_message::{Level, LogMessage, Span};
+
+/// Links each ERROR message that isn't a stanza itself to the last stanza sent or received
+/// before it in the same file and innermost span, which is often what the error is about.
+/// Messages must be fed in chronological order.
+#[derive(Debug, Default)]
+pub struct ErrorPairing {
+    last_stanzas: HashMap<(usize, Option<Span>), LogMessage>,
+}
+
+impl ErrorPairing {
+    pub fn pair(&mut self, message: &mut LogMessage) {
+        let innermost_span = message
+            .spans
+            .iter()
+            .flatten()
+            .last()
+            .or(message.span.as_ref());
+        let key = (message.source, innermost_span.cloned());
+        if message.fields.direction.is_some() {
+            self.last_stanzas.insert(key, message.clone());
+        } else if message.level == Level::Error {
+            message.related_stanza = self.last_stanzas.get(&key).cloned().map(Box::new);
+        }
+    }
+}
+
+/// Marks keepalive pings and the replies to XEP-0199 pings, matched by file and id. Messages
+/// must be fed in chronological order.
+#[derive(Debug, Default)]
+pub struct PingPairing {
+    /// Files and ids of the pings not answered yet.
+    pending: HashSet<(usize, String)>,
+}
+
+impl PingPairing {
+    pub fn pair(&mut self, message: &mut LogMessage) {
+        if message.is_ping() {
+            if let Some(id) = message.stanza_attribute("id") {
+                self.pending.insert((message.source, id.to_string()));
+            }
+            message.keepalive = true;
+        } else if matches!(message.stanza_attribute("type"), Some("result" | "error")) {
+            let id = message.stanza_attribute("id").unwrap_or_default();
+            message.keepalive = self.pending.remove(&(message.source, id.to_string()));
+        }
+    }
+}
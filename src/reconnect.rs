@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::log_message::{ChannelBoundary, Level, LogMessage};
+
+/// Connections opened within this many seconds of the previous one count as reconnects.
+const RECONNECT_WINDOW_SECS: i64 = 30;
+/// Number of connections in a row, each opened shortly after the previous one, that make a
+/// storm.
+const MIN_STORM_CONNECTS: usize = 3;
+
+/// A run of connections to the same account, each opened shortly after the previous one.
+#[derive(Debug, Clone)]
+pub struct Storm {
+    /// Index of the file the connections were logged in.
+    pub source: usize,
+    /// When the first connection of the storm was opened.
+    pub start: DateTime<Utc>,
+    /// When the last connection of the storm was opened.
+    pub end: DateTime<Utc>,
+    pub connects: usize,
+    /// Time between each connection and the next.
+    intervals: Vec<Duration>,
+    /// What went wrong before the connections ended, with how many ended that way, most
+    /// frequent first.
+    pub errors: Vec<(String, usize)>,
+}
+
+impl fmt::Display for Storm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = |d: Duration| d.num_milliseconds() as f64 / 1000.0;
+        let mut intervals = self.intervals.clone();
+        intervals.sort();
+        write!(
+            f,
+            "{} connects over {:.1}s, every {:.1}s to {:.1}s (median {:.1}s)",
+            self.connects,
+            seconds(self.end - self.start),
+            seconds(intervals[0]),
+            seconds(intervals[intervals.len() - 1]),
+            seconds(intervals[intervals.len() / 2]),
+        )?;
+        let errors = self
+            .errors
+            .iter()
+            .map(|(error, count)| format!("{error} ×{count}"))
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            write!(f, ", after {}", errors.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Storm {
+    /// Whether `message` was logged during the storm.
+    pub fn contains(&self, message: &LogMessage) -> bool {
+        message.source == self.source && (self.start..=self.end).contains(&message.timestamp)
+    }
+
+    fn add_error(&mut self, error: String) {
+        match self.errors.iter_mut().find(|(e, _)| *e == error) {
+            Some((_, count)) => *count += 1,
+            None => self.errors.push((error, 1)),
+        }
+    }
+}
+
+/// Connection state of a single file.
+#[derive(Default)]
+struct Connection {
+    /// Set after a stream header, whose peer's header may follow without opening a new
+    /// connection.
+    awaiting_header: bool,
+    /// Set once TLS, compression or authentication is established, after which the stream
+    /// is restarted without opening a new connection.
+    restart_pending: bool,
+    /// The last error logged since the connection was opened.
+    last_error: Option<String>,
+    /// The run of quick reconnects in progress, which becomes a storm once long enough.
+    run: Option<Storm>,
+}
+
+/// Finds rapid connect/disconnect cycles in each file. Messages must be fed in chronological
+/// order.
+///
+/// A stream header opens a new connection unless it answers the peer's header or restarts
+/// the stream after TLS, compression or authentication. The last error before a connection
+/// ends, or before the next one is opened if the disconnect wasn't logged, is taken as what
+/// triggered the reconnect.
+#[derive(Default)]
+pub struct ReconnectDetector {
+    connections: HashMap<usize, Connection>,
+    storms: Vec<Storm>,
+}
+
+impl ReconnectDetector {
+    pub fn feed(&mut self, message: &LogMessage) {
+        let connection = self.connections.entry(message.source).or_default();
+
+        if message.channel_boundary() == Some(ChannelBoundary::StreamStart) {
+            if std::mem::take(&mut connection.awaiting_header) {
+                return;
+            }
+            connection.awaiting_header = true;
+            if std::mem::take(&mut connection.restart_pending) {
+                return;
+            }
+            self.connect(message);
+            return;
+        }
+
+        if message.fields.direction.is_some() {
+            connection.awaiting_header = false;
+        }
+        if message.is_stream_end() {
+            if let Some(condition) = message.stream_error_condition() {
+                connection.last_error = Some(format!("stream error '{condition}'"));
+            }
+            let error = connection.last_error.take();
+            if let Some((run, error)) = connection.run.as_mut().zip(error) {
+                run.add_error(error);
+            }
+            connection.awaiting_header = false;
+            connection.restart_pending = false;
+        } else if message.precedes_stream_restart() {
+            connection.restart_pending = true;
+        } else if message.level == Level::Error && message.fields.direction.is_none() {
+            let text = message.fields.message.lines().next().unwrap_or_default();
+            let text = text.chars().take(80).collect::<String>();
+            connection.last_error = Some(format!("error '{text}'"));
+        }
+    }
+
+    /// Returns the storms found, in chronological order.
+    pub fn finish(mut self) -> Vec<Storm> {
+        let runs = self.connections.into_values().filter_map(|c| c.run);
+        self.storms.extend(
+            runs.filter(|run| run.connects >= MIN_STORM_CONNECTS)
+                .map(sort_errors),
+        );
+        self.storms.sort_by_key(|s| s.start);
+        self.storms
+    }
+
+    fn connect(&mut self, message: &LogMessage) {
+        let connection = self
+            .connections
+            .get_mut(&message.source)
+            .expect("connection was added when fed");
+        let timestamp = message.timestamp;
+        let error = connection.last_error.take();
+        if let Some((run, error)) = connection.run.as_mut().zip(error) {
+            run.add_error(error);
+        }
+
+        match &mut connection.run {
+            Some(run) if timestamp - run.end <= Duration::seconds(RECONNECT_WINDOW_SECS) => {
+                run.intervals.push(timestamp - run.end);
+                run.end = timestamp;
+                run.connects += 1;
+            }
+            run => {
+                let finished = run.replace(Storm {
+                    source: message.source,
+                    start: timestamp,
+                    end: timestamp,
+                    connects: 1,
+                    intervals: vec![],
+                    errors: vec![],
+                });
+                self.storms.extend(
+                    finished
+                        .filter(|run| run.connects >= MIN_STORM_CONNECTS)
+                        .map(sort_errors),
+                );
+            }
+        }
+    }
+}
+
+fn sort_errors(mut storm: Storm) -> Storm {
+    storm.errors.sort_by(|(_, a), (_, b)| b.cmp(a));
+    storm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_message::StanzaDirection;
+
+    const HEADER: &str = "<stream:stream xmlns='jabber:client' to='prose.org' version='1.0'>";
+    const CLOSE: &str = "</stream:stream>";
+    const PROCEED: &str = "<proceed xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>";
+
+    fn at(seconds: u32, direction: Option<StanzaDirection>, xml: &str) -> LogMessage {
+        let timestamp = format!("2024-01-01T00:{:02}:{:02}Z", seconds / 60, seconds % 60);
+        LogMessage::for_test(&timestamp, Level::Info, direction, xml)
+    }
+
+    fn error(seconds: u32, text: &str) -> LogMessage {
+        LogMessage {
+            level: Level::Error,
+            ..at(seconds, None, text)
+        }
+    }
+
+    /// The messages of a connection opened at `seconds`, up to the server's stream features.
+    fn connect(seconds: u32) -> Vec<LogMessage> {
+        vec![
+            at(seconds, Some(StanzaDirection::Out), HEADER),
+            at(seconds, Some(StanzaDirection::In), HEADER),
+            at(seconds, Some(StanzaDirection::In), "<stream:features/>"),
+        ]
+    }
+
+    fn storms(messages: impl IntoIterator<Item = LogMessage>) -> Vec<Storm> {
+        let mut detector = ReconnectDetector::default();
+        for message in messages {
+            detector.feed(&message);
+        }
+        detector.finish()
+    }
+
+    #[test]
+    fn quick_reconnects_make_a_storm() {
+        let messages = [0, 5, 10, 15].into_iter().flat_map(connect);
+        let storms = storms(messages);
+        assert_eq!(storms.len(), 1);
+        assert_eq!(storms[0].connects, 4);
+        assert_eq!(storms[0].end - storms[0].start, Duration::seconds(15));
+    }
+
+    #[test]
+    fn slow_reconnects_are_not_a_storm() {
+        let messages = [0, 60, 120, 180].into_iter().flat_map(connect);
+        assert!(storms(messages).is_empty());
+    }
+
+    #[test]
+    fn stream_restarts_are_not_reconnects() {
+        let mut messages = vec![];
+        for seconds in [0, 5, 10] {
+            messages.extend(connect(seconds));
+            messages.push(at(seconds, Some(StanzaDirection::In), PROCEED));
+            messages.extend(connect(seconds + 1));
+            messages.push(at(seconds + 2, Some(StanzaDirection::In), CLOSE));
+        }
+        let storms = storms(messages);
+        assert_eq!(storms.len(), 1);
+        assert_eq!(storms[0].connects, 3);
+    }
+
+    #[test]
+    fn closing_stream_tag_cancels_a_pending_restart() {
+        let mut messages = vec![];
+        for seconds in [0, 5, 10] {
+            messages.extend(connect(seconds));
+            messages.push(at(seconds, Some(StanzaDirection::In), PROCEED));
+            // The server hangs up before the stream is restarted
+            messages.push(at(seconds + 1, Some(StanzaDirection::In), CLOSE));
+        }
+        let storms = storms(messages);
+        assert_eq!(storms.len(), 1);
+        assert_eq!(storms[0].connects, 3);
+    }
+
+    #[test]
+    fn errors_before_disconnects_are_attributed() {
+        let mut messages = vec![];
+        for seconds in [0, 5, 10] {
+            messages.extend(connect(seconds));
+            messages.push(error(seconds + 1, "Connection reset by peer"));
+            messages.push(at(seconds + 1, Some(StanzaDirection::In), CLOSE));
+        }
+        let conflict = "<stream:error><conflict xmlns='urn:ietf:params:xml:ns:xmpp-streams'/>\
+            </stream:error>";
+        messages.extend(connect(15));
+        messages.push(at(16, Some(StanzaDirection::In), conflict));
+        messages.extend(connect(20));
+
+        let storms = storms(messages);
+        assert_eq!(storms.len(), 1);
+        assert_eq!(storms[0].connects, 5);
+        assert_eq!(
+            storms[0].errors,
+            [
+                ("error 'Connection reset by peer'".to_string(), 3),
+                ("stream error 'conflict'".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn files_are_tracked_separately() {
+        let messages = [0, 5, 10].into_iter().flat_map(connect).map(|mut m| {
+            m.source = (m.timestamp.timestamp() % 2) as usize;
+            m
+        });
+        assert!(storms(messages).is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+use xml::attribute::Attribute;
+use xml::reader::XmlEvent;
+use xml::writer;
+use xml::{EmitterConfig, ParserConfig};
+
+const REDACTED: &str = "[redacted]";
+
+/// Attributes whose values identify users.
+const JID_ATTRIBUTES: [&str; 3] = ["to", "from", "jid"];
+
+/// Replaces all text content of `xml` and the local part of JIDs with placeholders so that
+/// stanzas can be shared without leaking message bodies or contacts.
+pub fn redact_xml(xml: &str) -> Result<String, std::io::Error> {
+    let reader = ParserConfig::new()
+        .trim_whitespace(true)
+        .ignore_comments(true)
+        .create_reader(xml.as_bytes());
+
+    let mut buf = Vec::new();
+    let mut writer = EmitterConfig::new()
+        .write_document_declaration(false)
+        .create_writer(&mut buf);
+
+    for event in reader {
+        let event = event.map_err(to_io)?;
+        match event {
+            XmlEvent::StartDocument { .. } => continue,
+            XmlEvent::StartElement {
+                name,
+                attributes,
+                namespace,
+            } => {
+                let values = attributes
+                    .iter()
+                    .map(|attr| {
+                        if JID_ATTRIBUTES.contains(&attr.name.local_name.as_str()) {
+                            redact_jid(&attr.value)
+                        } else {
+                            attr.value.clone()
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let attributes = attributes
+                    .iter()
+                    .zip(values.iter())
+                    .map(|(attr, value)| Attribute::new(attr.name.borrow(), value))
+                    .collect::<Vec<_>>();
+
+                writer
+                    .write(writer::XmlEvent::StartElement {
+                        name: name.borrow(),
+                        attributes: attributes.into(),
+                        namespace: namespace.borrow(),
+                    })
+                    .map_err(to_io)?;
+            }
+            XmlEvent::Characters(_) | XmlEvent::CData(_) => writer
+                .write(writer::XmlEvent::Characters(REDACTED))
+                .map_err(to_io)?,
+            event => {
+                if let Some(event) = event.as_writer_event() {
+                    writer.write(event).map_err(to_io)?;
+                }
+            }
+        }
+    }
+
+    String::from_utf8(buf).map_err(to_io)
+}
+
+/// Keeps the domain and resource of a JID but hides its local part.
+fn redact_jid(jid: &str) -> String {
+    match jid.split_once('@') {
+        Some((_, domain)) => format!("{REDACTED}@{domain}"),
+        None => jid.to_string(),
+    }
+}
+
+fn to_io<E>(e: E) -> std::io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    std::io::Error::new(std::io::ErrorKind::Other, e)
+}
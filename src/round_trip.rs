@@ -0,0 +1,400 @@
+use std::fmt;
+
+use xml::{EmitterConfig, ParserConfig};
+
+/// Named entities that a single round of escaping produces. Finding one of them in decoded
+/// content means the logged text was escaped twice.
+const ESCAPED_ENTITIES: [&str; 5] = ["&amp;", "&lt;", "&gt;", "&quot;", "&apos;"];
+
+/// A difference between the logged text of a stanza and the same stanza parsed and
+/// serialized again, hinting that the logging layer doesn't log the bytes on the wire.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoundTripDiscrepancy {
+    /// The logged text isn't well-formed XML, e.g. because it was truncated.
+    Malformed(String),
+    /// Text or an attribute value changes whitespace when parsed, e.g. literal newlines in
+    /// attributes or carriage returns.
+    Whitespace { element: String },
+    /// Content still contains entity references after decoding.
+    DoubleEscaped { element: String, entity: String },
+    /// Elements or text are added, lost or renamed by the round trip.
+    Structure {
+        logged: String,
+        reserialized: String,
+    },
+}
+
+impl fmt::Display for RoundTripDiscrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoundTripDiscrepancy::Malformed(err) => write!(f, "not well-formed: {err}"),
+            RoundTripDiscrepancy::Whitespace { element } => {
+                write!(f, "whitespace in <{element}> is normalized by parsing")
+            }
+            RoundTripDiscrepancy::DoubleEscaped { element, entity } => {
+                write!(f, "'{entity}' in <{element}> was escaped twice")
+            }
+            RoundTripDiscrepancy::Structure {
+                logged,
+                reserialized,
+            } => write!(f, "logged {logged} but re-serialized {reserialized}"),
+        }
+    }
+}
+
+/// A lexical token of XML, with attribute values and text decoded but not normalized.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Namespace declarations are left out, since serializers may move or elide them freely.
+    Start {
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+    End(String),
+    Text(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Start { name, .. } => write!(f, "<{name}>"),
+            Token::End(name) => write!(f, "</{name}>"),
+            Token::Text(text) => write!(f, "text {text:?}"),
+        }
+    }
+}
+
+/// Parses and re-serializes `xml`, returning how the result differs from the logged text.
+pub fn check_round_trip(xml: &str) -> Vec<RoundTripDiscrepancy> {
+    let reserialized = match reserialize(xml) {
+        Ok(reserialized) => reserialized,
+        Err(err) => return vec![RoundTripDiscrepancy::Malformed(err)],
+    };
+    let (logged, reserialized) = match (tokenize(xml, false), tokenize(&reserialized, false)) {
+        (Ok(logged), Ok(reserialized)) => (logged, reserialized),
+        (Err(err), _) | (_, Err(err)) => return vec![RoundTripDiscrepancy::Malformed(err)],
+    };
+    // The parser keeps the whitespace a conforming one normalizes, so compare with that too
+    let normalized = tokenize(xml, true).unwrap_or_default();
+
+    let mut discrepancies = vec![];
+    let mut elements = vec![];
+    for (i, (logged, reserialized)) in logged.iter().zip(&reserialized).enumerate() {
+        if let Token::Start { name, .. } = logged {
+            elements.push(name.clone());
+        }
+        let element = elements.last().cloned().unwrap_or_default();
+        if let Token::End(_) = logged {
+            elements.pop();
+        }
+        if normalized
+            .get(i)
+            .is_some_and(|normalized| normalized != logged)
+            && !discrepancies.contains(&whitespace(&element))
+        {
+            discrepancies.push(whitespace(&element));
+        }
+
+        let values = match (logged, reserialized) {
+            (Token::Text(a), Token::Text(b)) => vec![(a, b)],
+            (
+                Token::Start {
+                    name: a,
+                    attributes: a_attrs,
+                },
+                Token::Start {
+                    name: b,
+                    attributes: b_attrs,
+                },
+            ) if a == b
+                && a_attrs
+                    .iter()
+                    .map(|(n, _)| n)
+                    .eq(b_attrs.iter().map(|(n, _)| n)) =>
+            {
+                a_attrs
+                    .iter()
+                    .zip(b_attrs)
+                    .map(|((_, a), (_, b))| (a, b))
+                    .collect()
+            }
+            (Token::End(a), Token::End(b)) if a == b => vec![],
+            _ => {
+                discrepancies.push(RoundTripDiscrepancy::Structure {
+                    logged: logged.to_string(),
+                    reserialized: reserialized.to_string(),
+                });
+                return discrepancies;
+            }
+        };
+
+        for (logged, reserialized) in values {
+            if logged != reserialized {
+                let discrepancy = if logged
+                    .split_whitespace()
+                    .eq(reserialized.split_whitespace())
+                {
+                    whitespace(&element)
+                } else {
+                    RoundTripDiscrepancy::Structure {
+                        logged: format!("{logged:?}"),
+                        reserialized: format!("{reserialized:?}"),
+                    }
+                };
+                if !discrepancies.contains(&discrepancy) {
+                    discrepancies.push(discrepancy);
+                }
+            }
+            if let Some(entity) = ESCAPED_ENTITIES.iter().find(|e| reserialized.contains(*e)) {
+                discrepancies.push(RoundTripDiscrepancy::DoubleEscaped {
+                    element: element.clone(),
+                    entity: entity.to_string(),
+                });
+            }
+        }
+    }
+
+    if logged.len() != reserialized.len() {
+        let describe = |tokens: &[Token]| format!("{} tokens", tokens.len());
+        discrepancies.push(RoundTripDiscrepancy::Structure {
+            logged: describe(&logged),
+            reserialized: describe(&reserialized),
+        });
+    }
+
+    discrepancies
+}
+
+fn whitespace(element: &str) -> RoundTripDiscrepancy {
+    RoundTripDiscrepancy::Whitespace {
+        element: element.to_string(),
+    }
+}
+
+fn reserialize(xml: &str) -> Result<String, String> {
+    let reader = ParserConfig::new()
+        .trim_whitespace(false)
+        .whitespace_to_characters(true)
+        .create_reader(xml.as_bytes());
+
+    let mut buf = Vec::new();
+    let mut writer = EmitterConfig::new()
+        .write_document_declaration(false)
+        .create_writer(&mut buf);
+
+    for event in reader {
+        let event = event.map_err(|err| err.to_string())?;
+        if let Some(event) = event.as_writer_event() {
+            writer.write(event).map_err(|err| err.to_string())?;
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&buf).to_string())
+}
+
+/// Splits `xml` into tags and text, skipping the XML declaration, processing instructions and
+/// comments. Whitespace is only normalized like a conforming parser does if `normalize` is
+/// set: line breaks become `\n`, and line breaks and tabs in attribute values spaces.
+fn tokenize(xml: &str, normalize: bool) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = xml;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("<?") {
+            rest = skip_past(after, "?>")?;
+        } else if let Some(after) = rest.strip_prefix("<!--") {
+            rest = skip_past(after, "-->")?;
+        } else if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let end = after.find("]]>").ok_or("unterminated CDATA section")?;
+            push_text(
+                &mut tokens,
+                &normalize_line_breaks(&after[..end], normalize),
+            );
+            rest = &after[end + 3..];
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let end = after.find('>').ok_or("unterminated end tag")?;
+            tokens.push(Token::End(after[..end].trim().to_string()));
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix('<') {
+            rest = tokenize_start_tag(after, normalize, &mut tokens)?;
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = normalize_line_breaks(&rest[..end], normalize);
+            push_text(&mut tokens, &decode_entities(&text));
+            rest = &rest[end..];
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Reads a start tag from just after its `<`, returning the remaining input.
+fn tokenize_start_tag<'a>(
+    tag: &'a str,
+    normalize: bool,
+    tokens: &mut Vec<Token>,
+) -> Result<&'a str, String> {
+    let name_end = tag
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .ok_or("unterminated start tag")?;
+    let name = tag[..name_end].to_string();
+    let mut rest = tag[name_end..].trim_start();
+    let mut attributes = vec![];
+
+    loop {
+        if let Some(after) = rest.strip_prefix("/>") {
+            tokens.push(Token::Start {
+                name: name.clone(),
+                attributes,
+            });
+            tokens.push(Token::End(name));
+            return Ok(after);
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            tokens.push(Token::Start { name, attributes });
+            return Ok(after);
+        }
+
+        let (attribute, after) = rest.split_once('=').ok_or("malformed attribute")?;
+        let after = after.trim_start();
+        let quote = after.chars().next().ok_or("unterminated start tag")?;
+        let value_end = after[1..]
+            .find(quote)
+            .ok_or("unterminated attribute value")?
+            + 1;
+        let mut value = normalize_line_breaks(&after[1..value_end], normalize);
+        if normalize {
+            value = value.replace(['\n', '\t'], " ");
+        }
+        let value = decode_entities(&value);
+
+        let attribute = attribute.trim();
+        if attribute != "xmlns" && !attribute.starts_with("xmlns:") {
+            attributes.push((attribute.to_string(), value));
+        }
+        rest = after[value_end + 1..].trim_start();
+    }
+}
+
+/// Turns `\r\n` and lone `\r` into `\n` if `normalize` is set. Character references like
+/// `&#13;` are decoded later, so they're kept.
+fn normalize_line_breaks(text: &str, normalize: bool) -> String {
+    match normalize {
+        true => text.replace("\r\n", "\n").replace('\r', "\n"),
+        false => text.to_string(),
+    }
+}
+
+/// Appends text, merging it with preceding text so that CDATA sections and escaped text
+/// compare equal.
+fn push_text(tokens: &mut Vec<Token>, text: &str) {
+    match tokens.last_mut() {
+        Some(Token::Text(previous)) => previous.push_str(text),
+        _ => tokens.push(Token::Text(text.to_string())),
+    }
+}
+
+fn skip_past<'a>(s: &'a str, terminator: &str) -> Result<&'a str, String> {
+    let end = s
+        .find(terminator)
+        .ok_or_else(|| format!("missing '{terminator}'"))?;
+    Ok(&s[end + terminator.len()..])
+}
+
+/// Decodes the predefined entities and character references in `s`, leaving anything else
+/// as is.
+fn decode_entities(s: &str) -> String {
+    let mut decoded = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16).ok())
+                .unwrap_or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stanzas_logged_as_sent_round_trip() {
+        let xml = "<iq xmlns='jabber:client' type='set' id='1'>\
+                   <query xmlns='jabber:iq:private'><note>a &amp; b</note></query></iq>";
+        assert_eq!(check_round_trip(xml), []);
+        assert_eq!(
+            check_round_trip("<message><body><![CDATA[<b>hi</b>]]></body></message>"),
+            []
+        );
+    }
+
+    #[test]
+    fn normalized_whitespace_is_reported() {
+        assert_eq!(check_round_trip("<iq id='a\nb'/>"), [whitespace("iq")]);
+        assert_eq!(
+            check_round_trip("<iq><query node='a\tb'/></iq>"),
+            [whitespace("query")]
+        );
+        assert_eq!(
+            check_round_trip("<message><body>a\r\nb</body></message>"),
+            [whitespace("body")]
+        );
+        // Character references aren't normalized
+        assert_eq!(check_round_trip("<iq id='a&#10;b'>a&#13;b</iq>"), []);
+    }
+
+    #[test]
+    fn double_escaping_is_reported() {
+        assert_eq!(
+            check_round_trip("<message><body>&amp;lt;b&amp;gt;</body></message>"),
+            [RoundTripDiscrepancy::DoubleEscaped {
+                element: "body".to_string(),
+                entity: "&lt;".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn truncated_stanzas_are_malformed() {
+        assert!(matches!(
+            check_round_trip("<iq type='get'><query>")[..],
+            [RoundTripDiscrepancy::Malformed(_)]
+        ));
+    }
+
+    #[test]
+    fn entities_and_character_references_are_decoded() {
+        assert_eq!(decode_entities("&lt;&#233;&#x41;&gt;"), "<éA>");
+        assert_eq!(decode_entities("AT&T &unknown; &"), "AT&T &unknown; &");
+    }
+}
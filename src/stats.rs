@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use crate::canonical::content_hash;
+use crate::log_message::{Level, StanzaDirection, StanzaKind};
+use crate::log_source::LogSource;
+use crate::reconnect::ReconnectDetector;
+use crate::throttle::{ThrottleDetector, ThrottleOptions};
+
+/// Number of index bits of the HyperLogLog sketches. 2^12 one-byte registers give a standard
+/// error of about 1.6%.
+const HLL_PRECISION: u32 = 12;
+
+/// Approximate distinct counter using a fixed amount of memory regardless of the input size.
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new() -> Self {
+        HyperLogLog {
+            registers: vec![0; 1 << HLL_PRECISION],
+        }
+    }
+
+    fn insert(&mut self, value: &str) {
+        let hash = mix(content_hash(value.as_bytes()));
+        let index = (hash >> (64 - HLL_PRECISION)) as usize;
+        let rank = ((hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1))).leading_zeros() + 1;
+        self.registers[index] = self.registers[index].max(rank as u8);
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum = self
+            .registers
+            .iter()
+            .map(|r| 2f64.powi(-(*r as i32)))
+            .sum::<f64>();
+        let estimate = alpha * m * m / sum;
+
+        // Small cardinalities are estimated better by counting empty registers
+        let empty = self.registers.iter().filter(|r| **r == 0).count();
+        if estimate <= 2.5 * m && empty > 0 {
+            return (m * (m / empty as f64).ln()).round() as u64;
+        }
+        estimate.round() as u64
+    }
+}
+
+/// Spreads the bits of an FNV hash, whose high bits are poorly distributed for short inputs.
+fn mix(mut hash: u64) -> u64 {
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+/// Prints summary statistics of the log file in a single pass. Memory use doesn't grow with
+/// the number of messages: distinct JIDs and stanza ids are estimated with HyperLogLog, and
+/// only per-target counts are kept exactly, since targets are module paths. UTF-16 logs and
+/// stream captures are still held whole, as [`LogSource::open`] decodes them up front.
+pub async fn print_stats(source: &LogSource, throttle: &ThrottleOptions) -> Result<()> {
+    let mut reader = source.open().await?;
+
+    let mut messages = 0u64;
+    let mut levels = HashMap::<Level, u64>::new();
+    let mut targets = HashMap::<String, u64>::new();
+    let mut directions = [0u64; 2];
+    let mut kinds = HashMap::<StanzaKind, u64>::new();
+    let mut stanza_bytes = 0u64;
+    let mut largest: Option<(usize, usize)> = None;
+    let mut time_range: Option<(DateTime<Utc>, DateTime<Utc>)> = None;
+    let mut jids = HyperLogLog::new();
+    let mut ids = HyperLogLog::new();
+    let mut throttle_detector = ThrottleDetector::new(throttle);
+    let mut reconnect_detector = ReconnectDetector::default();
+
+    while let Some((line_number, message)) = reader.next_message().await? {
+        messages += 1;
+        throttle_detector.feed(line_number, &message);
+        reconnect_detector.feed(&message);
+        *levels.entry(message.level).or_default() += 1;
+        if let Some(count) = targets.get_mut(&message.target) {
+            *count += 1;
+        } else {
+            targets.insert(message.target.clone(), 1);
+        }
+        time_range = Some(match time_range {
+            Some((first, last)) => (first.min(message.timestamp), last.max(message.timestamp)),
+            None => (message.timestamp, message.timestamp),
+        });
+
+        let Some(direction) = &message.fields.direction else {
+            continue;
+        };
+        directions[(*direction == StanzaDirection::Out) as usize] += 1;
+        if let Some(kind) = message.stanza_kind() {
+            *kinds.entry(kind).or_default() += 1;
+        }
+
+        let size = message.fields.message.len();
+        stanza_bytes += size as u64;
+        if largest.is_none_or(|(_, largest)| size > largest) {
+            largest = Some((line_number, size));
+        }
+        for attribute in ["from", "to"] {
+            if let Some(jid) = message.stanza_attribute(attribute) {
+                jids.insert(jid);
+            }
+        }
+        if let Some(id) = message.stanza_attribute("id") {
+            ids.insert(id);
+        }
+    }
+
+    let [received, sent] = directions;
+    println!("messages: {messages}");
+    println!(
+        "stanzas: {} ({sent} sent, {received} received, {stanza_bytes} bytes)",
+        sent + received
+    );
+    if let Some((first, last)) = time_range {
+        println!("time range: {first} to {last}");
+    }
+
+    let levels = Level::ALL
+        .iter()
+        .map(|level| format!("{level} {}", levels.get(level).unwrap_or(&0)))
+        .collect::<Vec<_>>();
+    println!("levels: {}", levels.join(", "));
+
+    let kinds = [
+        StanzaKind::Iq,
+        StanzaKind::Message,
+        StanzaKind::Presence,
+        StanzaKind::Other,
+    ]
+    .iter()
+    .map(|kind| format!("{kind} {}", kinds.get(kind).unwrap_or(&0)))
+    .collect::<Vec<_>>();
+    println!("stanza kinds: {}", kinds.join(", "));
+
+    let mut targets = targets.into_iter().collect::<Vec<_>>();
+    targets.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    println!("targets:");
+    for (target, count) in targets {
+        println!("  {target}: {count}");
+    }
+
+    println!("distinct JIDs: ~{}", jids.estimate());
+    println!("distinct stanza ids: ~{}", ids.estimate());
+    if let Some((line_number, size)) = largest {
+        println!("largest stanza: {size} bytes (line {line_number})");
+    }
+
+    let (bursts, throttled) = throttle_detector.finish();
+    println!(
+        "bursts over {} bytes/s: {bursts} ({} likely throttled)",
+        throttle.rate_limit,
+        throttled.len()
+    );
+    for burst in throttled {
+        println!("  line {}: {burst}", burst.line_number);
+    }
+
+    let storms = reconnect_detector.finish();
+    println!("reconnect storms: {}", storms.len());
+    for storm in storms {
+        println!("  {}: {storm}", storm.start);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hyperloglog_estimate_is_within_error_bounds() {
+        // 1.04 / sqrt(2^HLL_PRECISION), the standard error quoted for HLL_PRECISION
+        let standard_error = 1.04 / ((1u64 << HLL_PRECISION) as f64).sqrt();
+        for n in [10, 100, 1_000, 10_000, 100_000, 1_000_000] {
+            let mut hll = HyperLogLog::new();
+            for i in 0..n {
+                hll.insert(&format!("user{i}@example.org/resource"));
+            }
+            let estimate = hll.estimate() as f64;
+            let error = (estimate - n as f64).abs() / n as f64;
+            assert!(
+                error <= 3.0 * standard_error,
+                "estimated {estimate} distinct values for {n}, off by {:.2}%",
+                error * 100.0
+            );
+        }
+    }
+
+    #[test]
+    fn hyperloglog_ignores_duplicates() {
+        let mut once = HyperLogLog::new();
+        let mut repeated = HyperLogLog::new();
+        for i in 0..50 {
+            once.insert(&format!("id-{i}"));
+            for _ in 0..100 {
+                repeated.insert(&format!("id-{i}"));
+            }
+        }
+        assert_eq!(repeated.estimate(), once.estimate());
+    }
+}
@@ -0,0 +1,127 @@
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use tera::Tera;
+
+use crate::lint::{Linter, DEFAULT_DUPLICATE_WINDOW_SECS};
+use crate::log_message::{LogMessage, StanzaDirection};
+use crate::log_source::LogSource;
+use crate::pairing::{ErrorPairing, PingPairing};
+
+/// A message as seen by templates.
+#[derive(Serialize)]
+struct TemplateMessage {
+    /// Position of the message in the log, counting from 1.
+    number: usize,
+    /// Line the message starts on.
+    line: usize,
+    timestamp: DateTime<Utc>,
+    level: String,
+    target: String,
+    /// `in` or `out` for stanzas, missing otherwise.
+    direction: Option<&'static str>,
+    /// `iq`, `message`, `presence` or `other` for stanzas, missing otherwise.
+    kind: Option<String>,
+    /// Hash of the canonical stanza as 16 hex digits, missing for messages that aren't
+    /// stanzas. Equal stanzas have equal hashes regardless of attribute order or prefixes.
+    hash: Option<String>,
+    /// Names of the spans the message was logged in, outermost first.
+    spans: Vec<String>,
+    /// The message as logged.
+    message: String,
+    /// The pretty-printed stanza, or the message as logged if it isn't one.
+    xml: String,
+    /// For `<message/>` stanzas, the text of the body in the most preferred language.
+    body: Option<String>,
+    is_error: bool,
+    keepalive: bool,
+    lint_warnings: Vec<String>,
+    /// For errors, the pretty-printed stanza most likely involved.
+    related_stanza: Option<String>,
+}
+
+impl TemplateMessage {
+    fn new(
+        number: usize,
+        line: usize,
+        message: &LogMessage,
+        body_languages: &[String],
+    ) -> Result<Self> {
+        let spans = match (&message.spans, &message.span) {
+            (Some(spans), _) => spans.iter().map(|s| s.name.clone()).collect(),
+            (None, Some(span)) => vec![span.name.clone()],
+            (None, None) => vec![],
+        };
+        Ok(TemplateMessage {
+            number,
+            line,
+            timestamp: message.timestamp,
+            level: message.level.to_string(),
+            target: message.target.clone(),
+            direction: message
+                .fields
+                .direction
+                .as_ref()
+                .map(StanzaDirection::as_str),
+            kind: message.stanza_kind().map(|k| k.to_string()),
+            hash: message.stanza_hash_hex(),
+            spans,
+            message: message.fields.message.clone(),
+            xml: message.pretty_printed_xml()?,
+            body: message.body(body_languages),
+            is_error: message.is_error(),
+            keepalive: message.keepalive,
+            lint_warnings: message
+                .lint_warnings
+                .iter()
+                .map(|w| w.to_string())
+                .collect(),
+            related_stanza: message
+                .related_stanza
+                .as_ref()
+                .and_then(|s| s.pretty_printed_xml().ok()),
+        })
+    }
+}
+
+/// Renders the log with the Tera template at `path`, which gets all messages at once as
+/// `messages`, in file order, so that it can add headers, group or count them. Messages are
+/// linted and paired like in `browse` first. Message bodies are picked in the first of
+/// `body_languages` they're available in.
+pub async fn print_template(
+    source: &LogSource,
+    path: &Path,
+    body_languages: &[String],
+) -> Result<()> {
+    let template = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut reader = source.open().await?;
+    let mut linter = Linter::new(Duration::seconds(DEFAULT_DUPLICATE_WINDOW_SECS as i64));
+    let mut pairing = ErrorPairing::default();
+    let mut pings = PingPairing::default();
+    let mut messages = vec![];
+    while let Some((line_number, mut message)) = reader.next_message().await? {
+        message.lint_warnings = linter.check(&message);
+        pairing.pair(&mut message);
+        pings.pair(&mut message);
+        messages.push(TemplateMessage::new(
+            messages.len() + 1,
+            line_number,
+            &message,
+            body_languages,
+        )?);
+    }
+
+    let mut context = tera::Context::new();
+    context.insert("messages", &messages);
+    let output = Tera::one_off(&template, &context, false)
+        .with_context(|| format!("Failed to render {}", path.display()))?;
+
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(output.as_bytes())?;
+    Ok(())
+}
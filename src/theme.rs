@@ -0,0 +1,38 @@
+use anyhow::{format_err, Result};
+use syntect::highlighting::{Color, Theme, ThemeSet};
+
+/// Syntax highlighting theme used unless `--theme` says otherwise.
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// Looks up one of syntect's bundled themes by name.
+pub fn load_theme(name: &str) -> Result<Theme> {
+    let mut theme_set = ThemeSet::load_defaults();
+    theme_set.themes.remove(name).ok_or_else(|| {
+        let names = theme_set.themes.keys().cloned().collect::<Vec<_>>();
+        format_err!(
+            "Unknown theme `{name}`. Available themes: {}",
+            names.join(", ")
+        )
+    })
+}
+
+/// Returns the opening of an HTML document whose colors match `theme`.
+pub fn html_document_header(theme: &Theme) -> String {
+    let background = theme
+        .settings
+        .background
+        .map(css_color)
+        .unwrap_or_else(|| "#ffffff".to_string());
+    let foreground = theme
+        .settings
+        .foreground
+        .map(css_color)
+        .unwrap_or_else(|| "#000000".to_string());
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"></head>\n<body style=\"background-color:{background};color:{foreground}\">"
+    )
+}
+
+fn css_color(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
@@ -0,0 +1,276 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use clap::Args;
+
+use crate::log_message::{Level, LogMessage, StanzaDirection};
+
+/// Default for `--rate-limit`. Prosody limits clients to 10 kB/s by default, and ejabberd's
+/// shapers are in the same range.
+pub const DEFAULT_RATE_LIMIT: u64 = 10 * 1024;
+/// Outbound traffic is averaged over this many seconds, so that a single big stanza doesn't
+/// count as a burst.
+const BURST_WINDOW_SECS: i64 = 5;
+/// Errors and disconnects this many seconds after a burst are blamed on throttling.
+const AFTERMATH_SECS: i64 = 10;
+/// Stanza error conditions servers use when rate limiting.
+const THROTTLING_CONDITIONS: [&str; 2] = ["policy-violation", "resource-constraint"];
+
+#[derive(Args, Debug, Clone)]
+pub struct ThrottleOptions {
+    /// Outbound bytes per second servers are assumed to allow before throttling.
+    #[arg(long, default_value_t = DEFAULT_RATE_LIMIT)]
+    pub rate_limit: u64,
+}
+
+/// A stretch of time in which the client sent more than the rate limit.
+#[derive(Debug, Clone)]
+pub struct Burst {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Line of the first stanza of the burst.
+    pub line_number: usize,
+    pub stanzas: usize,
+    pub bytes: usize,
+    /// What went wrong shortly after the burst, with its line number.
+    pub symptom: Option<(usize, Symptom)>,
+}
+
+impl fmt::Display for Burst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = (self.end - self.start).num_milliseconds() as f64 / 1000.0;
+        write!(
+            f,
+            "{} bytes in {} stanzas over {seconds:.1}s",
+            self.bytes, self.stanzas
+        )?;
+        if let Some((line_number, symptom)) = &self.symptom {
+            write!(f, ", followed by {symptom} on line {line_number}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Sign that the server throttled the client.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Symptom {
+    Error(String),
+    StanzaError(&'static str),
+    Disconnect,
+}
+
+impl fmt::Display for Symptom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Symptom::Error(message) => write!(f, "error '{message}'"),
+            Symptom::StanzaError(condition) => write!(f, "a {condition} stanza error"),
+            Symptom::Disconnect => f.write_str("a disconnect"),
+        }
+    }
+}
+
+impl Symptom {
+    fn of(message: &LogMessage) -> Option<Self> {
+        if message.is_stream_end() {
+            return Some(Symptom::Disconnect);
+        }
+        if message.fields.direction == Some(StanzaDirection::In) {
+            if message.stanza_attribute("type") != Some("error") {
+                return None;
+            }
+            return THROTTLING_CONDITIONS
+                .into_iter()
+                .find(|c| message.fields.message.contains(c))
+                .map(Symptom::StanzaError);
+        }
+        if message.level == Level::Error && message.fields.direction.is_none() {
+            let text = message.fields.message.lines().next().unwrap_or_default();
+            return Some(Symptom::Error(text.chars().take(80).collect()));
+        }
+        None
+    }
+}
+
+/// Finds outbound bursts over the rate limit and what followed them. Messages must be fed in
+/// chronological order. Memory use only depends on the traffic within the burst window.
+pub struct ThrottleDetector {
+    limit: u64,
+    /// Timestamps, line numbers and sizes of the stanzas sent within the burst window.
+    window: VecDeque<(DateTime<Utc>, usize, usize)>,
+    window_bytes: usize,
+    /// The burst in progress, if any.
+    current: Option<Burst>,
+    /// Bursts still within their aftermath.
+    recent: Vec<Burst>,
+    bursts: usize,
+    throttled: Vec<Burst>,
+}
+
+impl ThrottleDetector {
+    pub fn new(options: &ThrottleOptions) -> Self {
+        ThrottleDetector {
+            limit: options.rate_limit,
+            window: VecDeque::new(),
+            window_bytes: 0,
+            current: None,
+            recent: vec![],
+            bursts: 0,
+            throttled: vec![],
+        }
+    }
+
+    pub fn feed(&mut self, line_number: usize, message: &LogMessage) {
+        let timestamp = message.timestamp;
+        let window_start = timestamp - Duration::seconds(BURST_WINDOW_SECS);
+        // Once its last stanza left the window, the burst is over even if nothing was sent
+        // since
+        if self.current.as_ref().is_some_and(|b| b.end < window_start) {
+            self.recent.extend(self.current.take());
+        }
+
+        let cutoff = timestamp - Duration::seconds(AFTERMATH_SECS);
+        let (expired, recent) = self
+            .recent
+            .drain(..)
+            .partition::<Vec<_>, _>(|b| b.end < cutoff || b.symptom.is_some());
+        self.recent = recent;
+        self.throttled
+            .extend(expired.into_iter().filter(|b| b.symptom.is_some()));
+
+        if let Some(symptom) = Symptom::of(message) {
+            for burst in self.current.iter_mut().chain(&mut self.recent) {
+                if burst.symptom.is_none() {
+                    burst.symptom = Some((line_number, symptom.clone()));
+                }
+            }
+        }
+
+        if message.fields.direction != Some(StanzaDirection::Out) {
+            return;
+        }
+        let size = message.fields.message.len();
+        self.window.push_back((timestamp, line_number, size));
+        self.window_bytes += size;
+        while self.window.front().is_some_and(|(t, ..)| *t < window_start) {
+            let (.., size) = self.window.pop_front().expect("window isn't empty");
+            self.window_bytes -= size;
+        }
+
+        let over_limit = self.window_bytes as u64 > self.limit * BURST_WINDOW_SECS as u64;
+        match (&mut self.current, over_limit) {
+            (Some(burst), true) => {
+                burst.end = timestamp;
+                burst.stanzas += 1;
+                burst.bytes += size;
+            }
+            (None, true) => {
+                let (start, first_line, _) = self.window[0];
+                self.bursts += 1;
+                self.current = Some(Burst {
+                    start,
+                    end: timestamp,
+                    line_number: first_line,
+                    stanzas: self.window.len(),
+                    bytes: self.window_bytes,
+                    symptom: None,
+                });
+            }
+            (Some(_), false) => self.recent.extend(self.current.take()),
+            (None, false) => (),
+        }
+    }
+
+    /// Returns the number of bursts, and those followed by signs of throttling.
+    pub fn finish(mut self) -> (usize, Vec<Burst>) {
+        let remaining = self.current.into_iter().chain(self.recent);
+        self.throttled
+            .extend(remaining.filter(|b| b.symptom.is_some()));
+        self.throttled.sort_by_key(|b| b.start);
+        (self.bursts, self.throttled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Allows 100 bytes per second, so 500 bytes within the burst window.
+    const OPTIONS: ThrottleOptions = ThrottleOptions { rate_limit: 100 };
+
+    fn at(seconds: u32, direction: Option<StanzaDirection>, xml: &str) -> LogMessage {
+        let timestamp = format!("2024-01-01T00:{:02}:{:02}Z", seconds / 60, seconds % 60);
+        LogMessage::for_test(&timestamp, Level::Info, direction, xml)
+    }
+
+    /// An outbound message stanza of 200 bytes.
+    fn sent(seconds: u32) -> LogMessage {
+        let xml = format!("<message><body>{}</body></message>", "x".repeat(168));
+        at(seconds, Some(StanzaDirection::Out), &xml)
+    }
+
+    fn policy_violation(seconds: u32) -> LogMessage {
+        let xml = "<message type='error'><error type='wait'>\
+                   <policy-violation xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/></error></message>";
+        at(seconds, Some(StanzaDirection::In), xml)
+    }
+
+    fn detect(messages: impl IntoIterator<Item = LogMessage>) -> (usize, Vec<Burst>) {
+        let mut detector = ThrottleDetector::new(&OPTIONS);
+        for (i, message) in messages.into_iter().enumerate() {
+            detector.feed(i + 1, &message);
+        }
+        detector.finish()
+    }
+
+    #[test]
+    fn traffic_within_the_limit_is_not_a_burst() {
+        let (bursts, throttled) = detect((0..10).map(|i| sent(i * 3)));
+        assert_eq!(bursts, 0);
+        assert!(throttled.is_empty());
+    }
+
+    #[test]
+    fn bursts_followed_by_a_throttling_error_are_reported() {
+        let messages = (0..4).map(sent).chain([policy_violation(5)]);
+        let (bursts, throttled) = detect(messages);
+        assert_eq!(bursts, 1);
+        assert_eq!(throttled.len(), 1);
+        assert_eq!(throttled[0].line_number, 1);
+        assert_eq!(throttled[0].stanzas, 4);
+        assert_eq!(throttled[0].bytes, 800);
+        assert_eq!(
+            throttled[0].symptom,
+            Some((5, Symptom::StanzaError("policy-violation")))
+        );
+    }
+
+    #[test]
+    fn bursts_without_symptoms_are_only_counted() {
+        let messages =
+            (0..4)
+                .map(sent)
+                .chain([at(10, Some(StanzaDirection::In), "<iq type='result'/>")]);
+        let (bursts, throttled) = detect(messages);
+        assert_eq!(bursts, 1);
+        assert!(throttled.is_empty());
+    }
+
+    #[test]
+    fn symptoms_after_the_aftermath_are_not_blamed_on_bursts() {
+        let messages = (0..4)
+            .map(sent)
+            .chain([at(30, None, "idle"), policy_violation(30)]);
+        let (bursts, throttled) = detect(messages);
+        assert_eq!(bursts, 1);
+        assert!(throttled.is_empty());
+    }
+
+    #[test]
+    fn closing_stream_tags_are_disconnects() {
+        let close = at(6, Some(StanzaDirection::In), "</stream:stream>");
+        let (_, throttled) = detect((0..4).map(sent).chain([close]));
+        assert_eq!(throttled.len(), 1);
+        assert_eq!(throttled[0].symptom, Some((5, Symptom::Disconnect)));
+    }
+}
@@ -1,361 +1,2981 @@
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::collections::{HashMap, HashSet};
 use std::iter::once;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::Result;
-use clipboard::{ClipboardContext, ClipboardProvider};
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+use crossterm::event::KeyCode;
 use parking_lot::Mutex;
 use ratatui::backend::Backend;
-use ratatui::layout::{Margin, Rect};
-use ratatui::prelude::{Color, Modifier, Span, Style, Stylize, Text};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::prelude::{Color, Line, Modifier, Span, Style, Text};
 use ratatui::widgets::{
-    Block, Borders, List, ListItem, Paragraph, Scrollbar, ScrollbarOrientation,
+    Block, Borders, Clear, Gauge, List, ListItem, Paragraph, Scrollbar, ScrollbarOrientation, Wrap,
 };
 use ratatui::Frame;
-use syntect::highlighting::ThemeSet;
+use syntect::highlighting::{Theme, ThemeSet};
 use syntect::parsing::SyntaxSet;
 
-use crate::log_message::{LogMessage, StanzaDirection};
+use crate::export::{annotated_xml, export_messages, save_message, ExportFormat};
+use crate::lint::{Linter, DEFAULT_DUPLICATE_WINDOW_SECS};
+use crate::log_message::{Level, LogMessage, MessageId, StanzaDirection, StanzaKind};
+use crate::log_source::{LogInput, LogSource, MalformedLine, ReadOptions, ReadPosition};
+use crate::pairing::{ErrorPairing, PingPairing};
+use crate::reconnect::{ReconnectDetector, Storm};
+use crate::theme::{load_theme, DEFAULT_THEME};
+use crate::tui::background_task::{Progress, TaskManager};
+use crate::tui::capabilities::{reduce_to_256_colors, Capabilities};
+use crate::tui::clipboard::{copy_to_clipboard, ClipboardTarget};
+use crate::tui::command::{parse_command, Command, FilterCommand, GotoTarget};
+use crate::tui::diff::{render_diff, DiffMode};
+use crate::tui::export_dialog::{ExportDialog, ExportDialogEvent, ExportScope};
+use crate::tui::filter::Filter;
+use crate::tui::grouping::{group_messages, GroupHeader, Grouping};
+use crate::tui::help::Help;
+use crate::tui::keymap::Keymap;
+use crate::tui::pins::Pin;
+use crate::tui::pipe::run_piped;
+use crate::tui::popup::{centered_rect, Popup, TextPopup};
+use crate::tui::prompt::{Prompt, PromptEvent, PromptKind};
+use crate::tui::rate::RateMeter;
+use crate::tui::schema::SchemaRegistry;
+use crate::tui::search::SearchQuery;
 use crate::tui::selected_log_message::SelectedLogMessage;
+use crate::tui::similar::SimilarStanzas;
+use crate::tui::span_stats::SpanStats;
 use crate::tui::stateful_list::StatefulList;
+use crate::tui::timestamp::{
+    format_delta, TimeDisplay, DEFAULT_LIST_TIMESTAMP_FORMAT, DETAIL_TIMESTAMP_FORMAT,
+};
+use crate::tui::toast::Toast;
 
-#[derive(Debug, Clone, PartialEq, Default)]
+/// How long the selection has to rest in the message list before the detail pane shows the
+/// selected stanza, so that scrolling through big stanzas doesn't highlight every one passed.
+const DETAIL_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(100);
+/// Title of the background task loading the log files, and reloading them.
+const LOAD_TASK_TITLE: &str = "Loading messages";
+/// Title of the background task reading the lines appended to the log files.
+const APPEND_TASK_TITLE: &str = "Reading appended lines";
+/// Narrowest detail pane that is split into XML and metadata when splitting is on.
+const SPLIT_DETAIL_MIN_WIDTH: u16 = 120;
+/// Number of bytes of a malformed line quoted in the list of malformed lines.
+const MALFORMED_LINE_QUOTE: usize = 120;
+
+#[derive(ValueEnum, Debug, Clone, PartialEq, Default)]
 pub enum AppArea {
     Spans,
     #[default]
     Messages,
+    #[value(name = "detail")]
     MessageDetail,
 }
 
+impl AppArea {
+    const ALL: [AppArea; 3] = [AppArea::Spans, AppArea::Messages, AppArea::MessageDetail];
+
+    fn next(&self) -> AppArea {
+        let idx = Self::ALL.iter().position(|a| a == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()].clone()
+    }
+
+    fn index(&self) -> usize {
+        Self::ALL.iter().position(|a| a == self).unwrap_or(0)
+    }
+
+    fn prev(&self) -> AppArea {
+        let idx = Self::ALL.iter().position(|a| a == self).unwrap_or(0);
+        Self::ALL[(idx + Self::ALL.len() - 1) % Self::ALL.len()].clone()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum SidebarMode {
+    #[default]
+    Spans,
+    Targets,
+}
+
+/// What the colors of the message list stand for.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ListColors {
+    #[default]
+    Direction,
+    Level,
+}
+
+impl ListColors {
+    fn toggled(self) -> Self {
+        match self {
+            ListColors::Direction => ListColors::Level,
+            ListColors::Level => ListColors::Direction,
+        }
+    }
+
+    fn color(self, message: &LogMessage) -> Color {
+        match (self, &message.fields.direction) {
+            (ListColors::Direction, Some(StanzaDirection::In)) => Color::Yellow,
+            (ListColors::Direction, Some(StanzaDirection::Out)) => Color::Blue,
+            // Only highlight levels that stand out among plain messages
+            (ListColors::Direction, None) => match message.level {
+                Level::Trace | Level::Warn | Level::Error => level_color(message.level),
+                Level::Debug | Level::Info => Color::White,
+            },
+            (ListColors::Level, _) => level_color(message.level),
+        }
+    }
+
+    /// Colors and what they mean, as shown in the bottom bar and the help overlay.
+    pub fn legend(self) -> &'static [(Color, &'static str)] {
+        match self {
+            ListColors::Direction => &[(Color::Blue, "Sent"), (Color::Yellow, "Received")],
+            ListColors::Level => &[
+                (Color::DarkGray, "Trace"),
+                (Color::White, "Debug"),
+                (Color::Green, "Info"),
+                (Color::LightYellow, "Warn"),
+                (Color::Red, "Error"),
+            ],
+        }
+    }
+}
+
+fn level_color(level: Level) -> Color {
+    match level {
+        Level::Trace => Color::DarkGray,
+        Level::Debug => Color::White,
+        Level::Info => Color::Green,
+        Level::Warn => Color::LightYellow,
+        Level::Error => Color::Red,
+    }
+}
+
+/// What to do with the name of a mark once it's typed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MarkCommand {
+    Set,
+    Jump,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SortColumn {
+    #[default]
+    Time,
+    Level,
+    Message,
+}
+
+impl SortColumn {
+    const ALL: [SortColumn; 3] = [SortColumn::Time, SortColumn::Level, SortColumn::Message];
+
+    fn title(&self) -> &'static str {
+        match self {
+            SortColumn::Time => "Time",
+            SortColumn::Level => "Level",
+            SortColumn::Message => "Message",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Movement {
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+    First,
+    Last,
+}
+
+struct ExportRequest {
+    messages: Vec<LogMessage>,
+    format: ExportFormat,
+    redact: bool,
+    theme: Theme,
+    path: String,
+}
+
+struct PipeRequest {
+    /// Shell command, run with `sh -c`, or `cmd /C` on Windows.
+    command: String,
+    stanza: String,
+}
+
+/// Work started from a prompt that runs in the background.
+enum Job {
+    Export(Box<ExportRequest>),
+    Pipe(PipeRequest),
+}
+
 #[derive(Clone)]
 pub struct App {
-    path: PathBuf,
+    paths: Vec<PathBuf>,
+    /// How the log files are read, both when loading them and when reading appended lines:
+    /// whether malformed lines are kept as error messages and how long lines may be.
+    read_options: ReadOptions,
+    /// Languages the bodies of `<message/>` stanzas are shown in in the message list, most
+    /// preferred first.
+    body_languages: Vec<String>,
     inner: Arc<Mutex<AppInner>>,
+    tasks: TaskManager,
 }
 
 struct AppInner {
     syntax_set: SyntaxSet,
     theme_set: ThemeSet,
+    /// Name of the syntax highlighting theme in `theme_set`.
+    theme_name: String,
     all_messages: StatefulList<LogMessage>,
     messages: StatefulList<LogMessage>,
+    /// "[All Messages]", one collapsed item per reconnect storm, then the span names.
     spans: StatefulList<String>,
+    targets: StatefulList<String>,
+    /// Reconnect storms, in the order of their items in `spans`.
+    storms: Vec<Storm>,
+    /// Number of messages in each span and "[All Messages]", as of the last load.
+    span_counts: HashMap<String, usize>,
+    /// Number of messages of each target and "[All Messages]", as of the last load.
+    target_counts: HashMap<String, usize>,
+    /// Queries counted in the bottom bar, with the number of messages matching each, whatever
+    /// the filters.
+    pins: Vec<(Pin, usize)>,
+    sidebar_mode: SidebarMode,
     formatted_message: Option<SelectedLogMessage>,
+    /// When the selection last moved through the message list, while the detail pane waits
+    /// for it to rest.
+    detail_pending_since: Option<Instant>,
+    /// Whether long lines in the detail pane are wrapped instead of scrolled horizontally.
+    wrap_detail: bool,
+    /// Whether the detail pane shows the stanza exactly as logged instead of pretty-printed.
+    raw_detail: bool,
+    /// Whether the lines of the stanza in the detail pane are numbered.
+    line_numbers: bool,
+    /// Whether the detail pane shows the metadata next to the XML when it's wide enough.
+    split_detail: bool,
+    /// Whether the detail pane was last drawn split, which `formatted_message` is built for.
+    detail_is_split: bool,
     selected_area: AppArea,
+    zoomed_area: Option<AppArea>,
+    /// Width of the sidebar, in percent of the screen.
+    sidebar_percent: u16,
+    /// Height of the spans list, in percent of the sidebar.
+    spans_percent: u16,
+    /// Filters narrowing down `messages`. Spans, targets and accounts are kept in sync
+    /// with the sidebar and tab selections.
+    filter: Filter,
+    prompt: Option<Prompt>,
+    popup: Option<Popup>,
+    tabs: Vec<String>,
+    /// Index into `tabs`. With more than one file, tab 0 is the merged view of all files.
+    selected_tab: usize,
+    sort_column: SortColumn,
+    sort_ascending: bool,
+    /// Screen row and column ranges of the sortable headers, as of the last render.
+    sort_header_hit_areas: Vec<(Rect, SortColumn)>,
+    /// Number of visible rows per area, as of the last render. Used for paging.
+    page_sizes: [usize; 3],
+    background_error: Option<String>,
+    /// Whether the selection jumps to new errors as the log file grows.
+    follow_errors: bool,
+    time_display: TimeDisplay,
+    list_colors: ListColors,
+    /// strftime-style format of the timestamp column. Empty to hide the column.
+    timestamp_format: String,
+    /// Rows of messages kept visible above and below the selected one.
+    scrolloff: usize,
+    grouping: Grouping,
+    /// Starts of the groups showing only their header.
+    collapsed_groups: HashSet<DateTime<Utc>>,
+    /// Header of each group of the message list, by the index of its first row.
+    group_headers: HashMap<usize, GroupHeader>,
+    /// The bookmarked messages.
+    bookmarks: HashSet<MessageId>,
+    /// The messages tagged to copy or export together.
+    tagged: HashSet<MessageId>,
+    /// The messages at named marks, as set with `m a` in vim. `'` holds the position before
+    /// the last jump.
+    named_marks: HashMap<char, MessageId>,
+    /// Set while waiting for the name of a mark to set or jump to.
+    pending_mark: Option<MarkCommand>,
+    /// Message to diff the selected message against.
+    marked: Option<LogMessage>,
+    diff_mode: Option<DiffMode>,
+    /// Whether stanzas written out of the app are redacted. Remembered from the last export.
+    redact: bool,
+    /// Shell command the selected stanza was last piped to.
+    last_pipe_command: String,
+    capabilities: Capabilities,
+    /// Outcome of the last action, either a success message or an error, shown in the bottom
+    /// bar until the next key press.
+    status: Option<Result<String, String>>,
+    /// Outcome of the last copy, export or reload, until it expires.
+    toast: Option<Toast>,
+    /// Messages appended since the files were opened, per second. Set once the first ones
+    /// arrive.
+    rate: Option<RateMeter>,
+    /// Lines of the log files that failed to parse, in file order.
+    malformed: Vec<MalformedLine>,
+    /// Set once messages are loaded, to add lines appended to the files later. Taken while
+    /// appended lines are being read.
+    append_state: Option<AppendState>,
+    /// Set when the files changed while appended lines were being read, to read them again.
+    append_requested: bool,
+    /// Timestamp of the newest message before new ones arrived while another was selected.
+    /// A divider marks it in the message list until the newest message is selected.
+    seen_until: Option<DateTime<Utc>>,
 }
 
 impl App {
-    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
-        Ok(Self {
-            path: path.as_ref().to_path_buf(),
-            inner: Arc::new(Mutex::new(AppInner::new(path)?)),
-        })
-    }
+    /// Opens the log file at `path`. If `path` is a directory, all `*.log` files in it are
+    /// opened as one tab per account plus a merged tab.
+    pub fn new(
+        path: impl AsRef<Path>,
+        read_options: ReadOptions,
+        body_languages: Vec<String>,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        if LogSource::new(path).input == LogInput::Stdin {
+            anyhow::bail!("Standard input can't be browsed, since it can't be watched or reloaded");
+        }
+
+        let paths = if path.is_dir() {
+            let mut paths = std::fs::read_dir(path)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter(|p| p.extension().map(|ext| ext == "log").unwrap_or(false))
+                .collect::<Vec<_>>();
+            paths.sort();
+            if paths.is_empty() {
+                anyhow::bail!("No *.log files found in {}", path.display());
+            }
+            paths
+        } else {
+            vec![path.to_path_buf()]
+        };
 
-    pub fn path(&self) -> &Path {
-        self.path.as_path()
+        let app = Self {
+            inner: Arc::new(Mutex::new(AppInner::new(&paths))),
+            paths,
+            read_options,
+            body_languages,
+            tasks: Default::default(),
+        };
+        app.load_messages_in_background(false);
+        Ok(app)
     }
 
-    pub fn reload_messages(&self) {
-        let mut guard = self.inner.lock();
-        let inner = &*guard;
+    /// Reads the log files on a background thread, replacing the messages once they're all
+    /// read. When `reload`ing, the current messages stay until then, and keep being followed.
+    fn load_messages_in_background(&self, reload: bool) {
+        let paths = self.paths.clone();
+        let read_options = self.read_options.clone();
+        let body_languages = self.body_languages.clone();
+        let inner = self.inner.clone();
 
-        let selected_message_idx = inner.messages.state.selected();
-        let is_at_bottom =
-            selected_message_idx == Some(inner.messages.items.len().saturating_sub(1));
+        self.tasks.spawn(LOAD_TASK_TITLE, move |progress| {
+            let loaded = load_messages(&paths, &read_options, body_languages, progress);
+            let (messages, malformed, append_state) = match loaded {
+                Ok(loaded) => loaded,
+                Err(err) if reload => {
+                    let err = format!("Failed to reload: {err:#}");
+                    inner.lock().toast = Some(Toast::new(Err(err)));
+                    return Ok(());
+                }
+                Err(err) => return Err(err),
+            };
+            if progress.is_cancelled() {
+                let mut state = inner.lock();
+                if reload {
+                    state.toast = Some(Toast::new(Err("Reload cancelled".into())));
+                } else {
+                    state.background_error = Some("Loading cancelled. Press r to reload.".into());
+                }
+                return Ok(());
+            }
 
-        let mut updated_state = AppInner::new(&self.path).unwrap();
-        updated_state
-            .spans
-            .state
-            .select(inner.spans.state.selected());
-        updated_state.selected_area = inner.selected_area.clone();
+            let mut state = inner.lock();
+            state.append_state = Some(append_state);
+            state.malformed = malformed;
+            if let Some(search) = &state.filter.search {
+                search.clear_matches();
+            }
+            let count = messages.len();
+            state.set_messages(messages);
+            if reload {
+                state.toast = Some(Toast::new(Ok(format!("Reloaded {count} messages"))));
+            }
+            Ok(())
+        });
+    }
+
+    /// Returns true while background tasks are running or haven't been rendered after
+    /// finishing.
+    pub fn has_background_tasks(&self) -> bool {
+        !self.tasks.is_empty()
+    }
 
-        *updated_state.messages.state.offset_mut() = inner.messages.state.offset();
-        *updated_state.spans.state.offset_mut() = inner.spans.state.offset();
+    pub fn cancel_background_tasks(&self) {
+        self.tasks.cancel_all()
+    }
 
-        if is_at_bottom {
-            updated_state.messages.select_last();
-        } else {
-            updated_state.messages.state.select(selected_message_idx);
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Reads the log files again in the background, unless they're already being read.
+    pub fn reload_messages(&self) {
+        if self.tasks.is_running(LOAD_TASK_TITLE) {
+            self.inner.lock().status = Some(Err("Already loading the log files".to_string()));
+            return;
         }
+        self.load_messages_in_background(true);
+    }
 
-        updated_state.update_selected_message();
+    /// Adds the lines appended to the log files since they were last read, reloading them
+    /// whole if they were truncated or can't be read incrementally. The lines are read in the
+    /// background, and changes while they are read are picked up once done.
+    pub fn append_messages(&self) {
+        let mut state = self.inner.lock();
+        let Some(mut append_state) = state.append_state.take() else {
+            // Still loading, which will pick up the new lines, or already reading appended
+            // lines, which reads them again once done
+            state.append_requested = true;
+            return;
+        };
+        state.append_requested = false;
+        drop(state);
 
-        *guard = updated_state;
+        let app = self.clone();
+        self.tasks.spawn(APPEND_TASK_TITLE, move |_| loop {
+            let appended = load_appended_messages(&app.paths, &app.read_options, &mut append_state);
+            let mut state = app.inner.lock();
+            if state.append_state.is_some() {
+                // Reloaded in the meantime, which read these lines too
+                return Ok(());
+            }
+            match appended {
+                Ok(Some((messages, malformed))) => {
+                    state.add_appended_messages(messages, malformed, app.paths.len() > 1);
+                }
+                Ok(None) => {
+                    drop(state);
+                    app.reload_messages();
+                    return Ok(());
+                }
+                Err(err) => {
+                    // Try again on the next change, from where the last successful read stopped
+                    state.append_state = Some(append_state);
+                    state.status = Some(Err(format!("Failed to read appended lines: {err:#}")));
+                    return Ok(());
+                }
+            }
+            if !std::mem::take(&mut state.append_requested) {
+                state.append_state = Some(append_state);
+                return Ok(());
+            }
+        });
     }
 
     pub fn select_area(&self, area: AppArea) {
         self.inner.lock().selected_area = area
     }
 
-    pub fn handle_key_up(&self) {
-        self.inner.lock().handle_key_up()
+    pub fn select_next_area(&self) {
+        let mut state = self.inner.lock();
+        state.selected_area = state.selected_area.next();
     }
 
-    pub fn handle_key_down(&self) {
-        self.inner.lock().handle_key_down()
+    pub fn select_prev_area(&self) {
+        let mut state = self.inner.lock();
+        state.selected_area = state.selected_area.prev();
     }
 
-    pub fn jump_to_end(&self) {
+    /// Gives `area` the full terminal width, or restores the regular layout if `area` is
+    /// already zoomed.
+    pub fn toggle_zoom(&self, area: AppArea) {
         let mut state = self.inner.lock();
-        state.messages.select_last();
-        state.update_selected_message();
+        if state.zoomed_area.as_ref() == Some(&area) {
+            state.zoomed_area = None;
+            return;
+        }
+        state.selected_area = area.clone();
+        state.zoomed_area = Some(area);
     }
 
-    pub fn messages_len(&self) -> usize {
-        self.inner.lock().messages.items.len()
+    pub fn zoomed_area(&self) -> Option<AppArea> {
+        self.inner.lock().zoomed_area.clone()
     }
 
-    pub fn copy_selected_message_to_clipboard(&self) {
-        let Some(message) = self
-            .inner
-            .lock()
-            .messages
-            .selected_item()
-            .and_then(|m| m.pretty_printed_xml().ok())
-        else {
-            return;
-        };
+    /// Returns the sidebar width in percent of the screen and the spans list height in percent
+    /// of the sidebar.
+    pub fn layout_percentages(&self) -> (u16, u16) {
+        let state = self.inner.lock();
+        (state.sidebar_percent, state.spans_percent)
+    }
 
-        let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-        ctx.set_contents(message).unwrap();
+    /// Grows or shrinks the sidebar by `delta` percent of the screen width.
+    pub fn resize_sidebar(&self, delta: i16) {
+        let mut state = self.inner.lock();
+        state.sidebar_percent = resize_percent(state.sidebar_percent, delta);
     }
-}
 
-impl App {
-    pub fn render_selected_message<B: Backend>(&self, f: &mut Frame<B>, rect: Rect) {
-        let mut app = self.inner.lock();
+    /// Grows or shrinks the spans list by `delta` percent of the sidebar height.
+    pub fn resize_spans(&self, delta: i16) {
+        let mut state = self.inner.lock();
+        state.spans_percent = resize_percent(state.spans_percent, delta);
+    }
 
-        let text = app
-            .formatted_message
+    /// Prompts for a query filtering the message list, or with the detail pane focused, for
+    /// text to find in the selected stanza.
+    pub fn open_search_prompt(&self, kind: PromptKind) {
+        let mut state = self.inner.lock();
+        if kind == PromptKind::Search && state.selected_area == AppArea::MessageDetail {
+            if let Some(message) = &state.formatted_message {
+                let query = message.search_query().unwrap_or_default().to_string();
+                state.prompt = Some(Prompt::new(PromptKind::DetailSearch, query));
+                return;
+            }
+        }
+        let query = state
+            .filter
+            .search
             .as_ref()
-            .map(|m| m.message.clone())
-            .unwrap_or(Text::raw("<no selection>"));
+            .map(|s| s.input().to_string())
+            .unwrap_or_default();
+        state.prompt = Some(Prompt::new(kind, query));
+    }
 
-        let selected_area = app.selected_area.clone();
+    /// Prompts for the text of a message to explain which filters hide it.
+    pub fn open_explain_filters_prompt(&self) {
+        self.inner.lock().prompt = Some(Prompt::new(PromptKind::ExplainFilters, ""));
+    }
 
-        let Some(message) = &mut app.formatted_message else {
-            let paragraph = Paragraph::new(text)
-                .style(
-                    Style::default().fg(if app.selected_area == AppArea::MessageDetail {
-                        Color::White
-                    } else {
-                        Color::DarkGray
-                    }),
-                )
-                .block(Block::default().borders(Borders::ALL).title(Span::styled(
-                    "Message Detail (m)",
-                    Style::default().add_modifier(Modifier::BOLD),
-                )));
-            f.render_widget(paragraph, rect);
-            return;
-        };
+    /// Opens the `:` command line.
+    pub fn open_command_prompt(&self) {
+        self.inner.lock().prompt = Some(Prompt::new(PromptKind::Command, ""));
+    }
 
-        let paragraph = Paragraph::new(text)
-            .scroll(message.scroll_position())
-            .style(
-                Style::default().fg(if selected_area == AppArea::MessageDetail {
-                    Color::White
-                } else {
-                    Color::DarkGray
-                }),
-            )
-            .block(Block::default().borders(Borders::ALL).title(Span::styled(
-                "Message Detail (m)",
-                Style::default().add_modifier(Modifier::BOLD),
-            )));
+    /// Starts jumping through the focused list as text is typed, until Enter or Esc.
+    pub fn open_type_ahead(&self) {
+        let mut state = self.inner.lock();
+        if state.selected_area != AppArea::MessageDetail {
+            state.prompt = Some(Prompt::new(PromptKind::TypeAhead, ""));
+        }
+    }
 
-        let scrollbar = Scrollbar::default()
-            .orientation(ScrollbarOrientation::VerticalRight)
-            .begin_symbol(None)
-            .end_symbol(None)
-            .track_symbol(None)
-            .thumb_symbol("▐");
+    pub fn selected_message(&self) -> Option<LogMessage> {
+        self.inner.lock().messages.selected_item().cloned()
+    }
 
-        f.render_widget(paragraph, rect);
-        f.render_stateful_widget(
-            scrollbar,
-            rect.inner(&Margin {
-                vertical: 1,
-                horizontal: 0,
-            }), // using a inner vertical margin of 1 unit makes the scrollbar inside the block
-            &mut message.scroll_state,
-        );
+    /// Whether stanzas written out of the app should be redacted, as chosen in the last export.
+    pub fn redact(&self) -> bool {
+        self.inner.lock().redact
     }
 
-    pub fn render_spans_list<B: Backend>(&self, f: &mut Frame<B>, rect: Rect) {
-        let mut app = self.inner.lock();
+    /// Prompts for a file to save the selected message to.
+    pub fn open_save_prompt(&self) {
+        let mut state = self.inner.lock();
+        if state.messages.selected_item().is_some() {
+            state.prompt = Some(Prompt::new(PromptKind::SaveStanza, "stanza.xml"));
+        }
+    }
 
-        let span_items = app
-            .spans
-            .items
-            .iter()
-            .map(|s| ListItem::new(s.clone()))
-            .collect::<Vec<_>>();
-        let spans_list = List::new(span_items)
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .style(Style::default().fg(if app.selected_area == AppArea::Spans {
-                        Color::White
-                    } else {
-                        Color::DarkGray
-                    }))
-                    .title(Span::styled(
-                        "Spans (s)",
-                        Style::default().add_modifier(Modifier::BOLD),
-                    )),
-            )
-            .highlight_style(Style::default().bg(Color::LightYellow).fg(Color::Black));
+    /// Validates the payloads of the selected stanza against `schemas` in the background and
+    /// shows the results above the stanza until the selection changes.
+    pub fn validate_selected_message(&self, schemas: &SchemaRegistry) {
+        let (id, xml) = {
+            let mut state = self.inner.lock();
+            if schemas.is_empty() {
+                state.status = Some(Err("No schemas registered".to_string()));
+                return;
+            }
+            let Some(message) = state.messages.selected_item() else {
+                return;
+            };
+            if message.fields.direction.is_none() {
+                return;
+            }
+            (message.id(), message.fields.message.clone())
+        };
 
-        f.render_stateful_widget(spans_list, rect, &mut app.spans.state);
-    }
+        let schemas = schemas.clone();
+        let inner = self.inner.clone();
+        self.tasks.spawn("Validating payloads", move |_| {
+            let result = schemas.validate(&xml);
+            let mut state = inner.lock();
+            let reports = match result {
+                Ok(reports) => reports,
+                Err(err) => {
+                    state.status = Some(Err(format!("{err:#}")));
+                    return Ok(());
+                }
+            };
+            if reports.is_empty() {
+                state.status = Some(Err("No payloads with a registered schema".to_string()));
+                return Ok(());
+            }
 
-    pub fn render_messages_list<B: Backend>(&self, f: &mut Frame<B>, rect: Rect) {
-        let mut app = self.inner.lock();
+            let invalid = reports.iter().filter(|r| !r.errors.is_empty()).count();
+            state.status = Some(match invalid {
+                0 => Ok(format!("{} payloads valid", reports.len())),
+                _ => Err(format!("{invalid} of {} payloads invalid", reports.len())),
+            });
 
-        let message_items = app
-            .messages
-            .items
-            .iter()
-            .map(|m| {
-                let color = match m.fields.direction {
-                    None => Color::White,
-                    Some(StanzaDirection::In) => Color::Yellow,
-                    Some(StanzaDirection::Out) => Color::Blue,
-                };
-                ListItem::new(m.fields.message.clone()).fg(color)
-            })
-            .collect::<Vec<_>>();
+            if state.messages.selected_item().map(LogMessage::id) != Some(id) {
+                return Ok(());
+            }
+            let lines = reports
+                .into_iter()
+                .flat_map(|report| {
+                    if report.errors.is_empty() {
+                        let line = format!("✓ {}: valid", report.namespace);
+                        return vec![Line::styled(line, Style::default().fg(Color::Green))];
+                    }
+                    report
+                        .errors
+                        .into_iter()
+                        .map(|error| {
+                            let line = format!("✗ {}: {error}", report.namespace);
+                            Line::styled(line, Style::default().fg(Color::Red))
+                        })
+                        .collect()
+                })
+                .collect();
+            if let Some(formatted) = &mut state.formatted_message {
+                formatted.prepend(lines);
+            }
+            Ok(())
+        });
+    }
 
-        let highlight_color = app
-            .messages
-            .selected_item()
-            .and_then(|item| match item.fields.direction {
-                None => None,
-                Some(StanzaDirection::Out) => Some(Color::Blue),
-                Some(StanzaDirection::In) => Some(Color::Yellow),
-            })
-            .unwrap_or(Color::White);
+    pub fn status(&self) -> Option<Result<String, String>> {
+        self.inner.lock().status.clone()
+    }
 
-        // Create a List from all list items and highlight the currently selected one
-        let messages_list = List::new(message_items)
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .style(
-                        Style::default().fg(if app.selected_area == AppArea::Messages {
-                            Color::White
-                        } else {
-                            Color::DarkGray
-                        }),
-                    )
-                    .title(Span::styled(
-                        "All Messages (a)",
-                        Style::default().add_modifier(Modifier::BOLD),
-                    )),
-            )
-            .highlight_style(Style::default().bg(highlight_color).fg(Color::Black));
+    pub fn set_status(&self, status: Result<String, String>) {
+        self.inner.lock().status = Some(status);
+    }
 
-        // We can now render the item list
-        f.render_stateful_widget(messages_list, rect, &mut app.messages.state);
+    pub fn show_toast(&self, outcome: Result<String, String>) {
+        self.inner.lock().toast = Some(Toast::new(outcome));
     }
-}
 
-impl AppInner {
-    fn new(path: impl AsRef<Path>) -> Result<Self> {
-        let syntax_set = SyntaxSet::load_defaults_newlines();
-        let theme_set = ThemeSet::load_defaults();
+    pub fn clear_status(&self) {
+        self.inner.lock().status = None;
+    }
 
-        let file = File::open(&path)?;
-        let reader = BufReader::new(file);
-        let messages = reader
-            .lines()
-            .map(|line| {
-                line.map_err(anyhow::Error::from)
-                    .and_then(|line| line.parse::<LogMessage>().map_err(anyhow::Error::from))
-            })
-            .collect::<anyhow::Result<Vec<_>, _>>()?;
+    /// When the toast shown disappears, if any.
+    pub fn toast_deadline(&self) -> Option<Instant> {
+        self.inner.lock().toast.as_ref().map(Toast::deadline)
+    }
 
-        let mut spans = messages
-            .iter()
-            .filter_map(|m| {
-                m.spans
-                    .as_ref()
-                    .map(|spans| spans.iter().map(|s| s.name.clone()).collect::<Vec<_>>())
-            })
-            .flatten()
-            .collect::<HashSet<_>>()
-            .into_iter()
-            .collect::<Vec<_>>();
-        spans.sort();
+    pub fn expire_toast(&self) {
+        let mut state = self.inner.lock();
+        if state.toast.as_ref().is_some_and(Toast::is_expired) {
+            state.toast = None;
+        }
+    }
 
-        let all_messages = StatefulList::with_items(messages);
+    pub fn is_prompt_active(&self) -> bool {
+        self.inner.lock().prompt.is_some()
+    }
 
-        Ok(AppInner {
-            syntax_set,
-            theme_set,
-            all_messages: all_messages.clone(),
-            messages: all_messages,
-            spans: StatefulList::with_items(
-                once("[All Messages]".to_string())
-                    .chain(spans.into_iter())
-                    .collect(),
-            ),
-            formatted_message: None,
-            selected_area: Default::default(),
-        })
+    /// Returns the prompt line and an optional error to render in place of the bottom bar, if
+    /// a prompt is open.
+    pub fn prompt_line(&self) -> Option<(String, Option<String>)> {
+        self.inner
+            .lock()
+            .prompt
+            .as_ref()
+            .map(|p| (format!("{}{}", p.prefix(), p.input), p.error.clone()))
     }
 
-    fn update_selected_message(&mut self) {
-        self.formatted_message = self.messages.selected_item().and_then(|m| {
-            m.highlighted_stanza_xml_text(
-                &self.syntax_set,
-                &self.theme_set.themes["base16-ocean.dark"],
-            )
-            .ok()
-            .map(Into::into)
-        })
+    pub fn handle_prompt_key(&self, code: KeyCode) {
+        let job = self.inner.lock().handle_prompt_key(code);
+        match job {
+            Some(Job::Export(request)) => self.export_in_background(*request),
+            Some(Job::Pipe(request)) => self.pipe_in_background(request),
+            None => (),
+        }
     }
 
-    fn update_selected_span(&mut self) {
-        if self.spans.state.selected() == Some(0) {
-            self.messages = self.all_messages.clone();
-            return;
+    /// Asks for a shell command to pipe the selected stanza to, starting with the last one.
+    pub fn open_pipe_prompt(&self) {
+        let mut state = self.inner.lock();
+        if state.messages.selected_item().is_some() {
+            let command = state.last_pipe_command.clone();
+            state.prompt = Some(Prompt::new(PromptKind::Pipe, command));
         }
+    }
 
-        let Some(span_name) = self.spans.selected_item() else {
-            self.messages = StatefulList::with_items(vec![]);
-            return;
+    /// Runs `request.command` with the stanza on its standard input, and shows what it prints
+    /// in a popup.
+    fn pipe_in_background(&self, request: PipeRequest) {
+        let inner = self.inner.clone();
+
+        self.tasks
+            .spawn(format!("Running {}", request.command), move |_| {
+                let lines = run_piped(&request.command, &request.stanza)?;
+                let title = format!("| {} (Esc)", request.command);
+                inner.lock().popup = Some(Popup::Text(TextPopup::new(title, lines)));
+                Ok(())
+            });
+    }
+
+    pub fn toggle_level_filter_popup(&self) {
+        let mut state = self.inner.lock();
+        state.popup = match state.popup {
+            Some(Popup::LevelFilter(_)) => None,
+            _ => Some(Popup::LevelFilter(StatefulList::with_items(
+                Level::ALL.to_vec(),
+            ))),
         };
+    }
+
+    /// Cycles the message list between all messages, received stanzas only and sent stanzas
+    /// only.
+    pub fn cycle_direction_filter(&self) {
+        let mut state = self.inner.lock();
+        state.filter.direction = match state.filter.direction {
+            None => Some(StanzaDirection::In),
+            Some(StanzaDirection::In) => Some(StanzaDirection::Out),
+            Some(StanzaDirection::Out) => None,
+        };
+        state.update_selected_span();
+        state.update_selected_message();
+    }
+
+    /// Cycles the message list between all messages and only `<iq>`, `<message>` or
+    /// `<presence>` stanzas.
+    pub fn cycle_kind_filter(&self) {
+        let mut state = self.inner.lock();
+        state.filter.kind = match state.filter.kind {
+            None => Some(StanzaKind::Iq),
+            Some(StanzaKind::Iq) => Some(StanzaKind::Message),
+            Some(StanzaKind::Message) => Some(StanzaKind::Presence),
+            Some(StanzaKind::Presence) | Some(StanzaKind::Other) => None,
+        };
+        state.update_selected_span();
+        state.update_selected_message();
+    }
+
+    /// Shows or hides keepalive pings and the replies to them.
+    pub fn toggle_keepalive_filter(&self) {
+        let mut state = self.inner.lock();
+        state.filter.hide_keepalives = !state.filter.hide_keepalives;
+        state.update_selected_span();
+        state.update_selected_message();
+    }
+
+    /// Returns the titles of the per-account tabs, or an empty list if only one file is open.
+    pub fn tab_titles(&self) -> Vec<String> {
+        self.inner.lock().tabs.clone()
+    }
+
+    pub fn selected_tab(&self) -> usize {
+        self.inner.lock().selected_tab
+    }
+
+    pub fn select_next_tab(&self) {
+        let mut state = self.inner.lock();
+        if state.tabs.is_empty() {
+            return;
+        }
+        state.selected_tab = (state.selected_tab + 1) % state.tabs.len();
+        state.update_selected_span();
+        state.update_selected_message();
+    }
+
+    pub fn select_prev_tab(&self) {
+        let mut state = self.inner.lock();
+        if state.tabs.is_empty() {
+            return;
+        }
+        state.selected_tab = (state.selected_tab + state.tabs.len() - 1) % state.tabs.len();
+        state.update_selected_span();
+        state.update_selected_message();
+    }
+
+    /// Sorts the message list by the header at the given screen position, reversing the order
+    /// if the list is already sorted by that column.
+    pub fn handle_mouse_click(&self, column: u16, row: u16) {
+        let mut state = self.inner.lock();
+
+        let Some(sort_column) = state
+            .sort_header_hit_areas
+            .iter()
+            .find(|(rect, _)| rect.y == row && column >= rect.x && column < rect.x + rect.width)
+            .map(|(_, sort_column)| *sort_column)
+        else {
+            return;
+        };
+
+        if state.sort_column == sort_column {
+            state.sort_ascending = !state.sort_ascending;
+        } else {
+            state.sort_column = sort_column;
+            state.sort_ascending = true;
+        }
+        state.update_selected_span();
+        state.update_selected_message();
+    }
+
+    pub fn toggle_help_popup(&self, keymap: &Keymap) {
+        let mut state = self.inner.lock();
+        state.popup = match state.popup {
+            Some(Popup::Help(_)) => None,
+            _ => Some(Popup::Help(Help::new(
+                keymap,
+                state.list_colors,
+                state.capabilities.notes.clone(),
+            ))),
+        };
+    }
+
+    pub fn open_export_dialog(&self) {
+        let mut state = self.inner.lock();
+        let has_tagged = !state.tagged.is_empty();
+        state.popup = Some(Popup::Export(ExportDialog::new(state.redact, has_tagged)));
+    }
+
+    /// Shows statistics of the messages in the span or target highlighted in the sidebar,
+    /// within the selected tab.
+    pub fn open_span_stats(&self) {
+        let mut state = self.inner.lock();
+        let source = state.selected_tab.checked_sub(1);
+        let sidebar_mode = state.sidebar_mode.clone();
+        let list = match sidebar_mode {
+            SidebarMode::Spans => &state.spans,
+            SidebarMode::Targets => &state.targets,
+        };
+        let name = list.selected_item().cloned().unwrap_or_default();
+        let item = AppInner::selected_filter_item(list);
+        let storm = state
+            .selected_storm()
+            .filter(|_| sidebar_mode == SidebarMode::Spans);
+
+        let messages = state
+            .all_messages
+            .items
+            .iter()
+            .filter(|m| source.map(|s| m.source == s).unwrap_or(true))
+            .filter(|m| match (storm, item, &sidebar_mode) {
+                (Some(storm), ..) => storm.contains(m),
+                (None, None, _) => true,
+                (None, Some(span_name), SidebarMode::Spans) => m
+                    .spans
+                    .as_ref()
+                    .is_some_and(|spans| spans.iter().any(|s| &s.name == span_name)),
+                (None, Some(target), SidebarMode::Targets) => &m.target == target,
+            });
+        let stats = SpanStats::new(format!("{name} (i)"), messages);
+        state.popup = Some(Popup::SpanStats(stats));
+    }
+
+    /// Shows statistics of the messages passing the current filters.
+    pub fn open_filter_stats(&self) {
+        let mut state = self.inner.lock();
+        // Count the messages of collapsed groups too
+        let messages = state
+            .all_messages
+            .items
+            .iter()
+            .filter(|m| state.filter.matches(m));
+        let title = match state.active_filters().is_empty() {
+            true => "All messages (I)".to_string(),
+            false => "Filtered messages (I)".to_string(),
+        };
+        let stats = SpanStats::new(title, messages);
+        state.popup = Some(Popup::SpanStats(stats));
+    }
+
+    pub fn is_popup_active(&self) -> bool {
+        self.inner.lock().popup.is_some()
+    }
+
+    pub fn handle_popup_key(&self, code: KeyCode) {
+        let request = self.inner.lock().handle_popup_key(code);
+        if let Some(request) = request {
+            self.export_in_background(request);
+        }
+    }
+
+    fn export_in_background(&self, request: ExportRequest) {
+        let inner = self.inner.clone();
+
+        self.tasks.spawn("Exporting messages", move |progress| {
+            progress.set_total(request.messages.len() as u64);
+
+            let messages = request
+                .messages
+                .iter()
+                .take_while(|_| !progress.is_cancelled())
+                .inspect(|_| progress.advance(1));
+            let result = export_messages(
+                messages,
+                request.format,
+                request.redact,
+                &request.theme,
+                &request.path,
+            );
+
+            let status = match result {
+                Ok(_) if progress.is_cancelled() => Err("Export cancelled".to_string()),
+                Ok(count) => Ok(format!("Exported {count} messages to {}", request.path)),
+                Err(err) => Err(err.to_string()),
+            };
+            let mut state = inner.lock();
+            if let Some(Popup::Export(dialog)) = &mut state.popup {
+                dialog.status = Some(status.clone());
+            }
+            state.toast = Some(Toast::new(status));
+            Ok(())
+        });
+    }
+
+    /// Switches the sidebar list between grouping messages by span and by target.
+    pub fn toggle_sidebar_mode(&self) {
+        let mut state = self.inner.lock();
+        state.sidebar_mode = match state.sidebar_mode {
+            SidebarMode::Spans => SidebarMode::Targets,
+            SidebarMode::Targets => SidebarMode::Spans,
+        };
+    }
+
+    pub fn handle_key_up(&self) {
+        self.inner.lock().handle_key_up()
+    }
+
+    pub fn handle_key_down(&self) {
+        self.inner.lock().handle_key_down()
+    }
+
+    pub fn handle_key_left(&self) {
+        let mut state = self.inner.lock();
+        if state.selected_area != AppArea::MessageDetail {
+            return;
+        }
+        if let Some(m) = &mut state.formatted_message {
+            m.scroll_left()
+        }
+    }
+
+    pub fn handle_key_right(&self) {
+        let mut state = self.inner.lock();
+        if state.selected_area != AppArea::MessageDetail {
+            return;
+        }
+        if state.wrap_detail {
+            return;
+        }
+        if let Some(m) = &mut state.formatted_message {
+            m.scroll_right()
+        }
+    }
+
+    pub fn set_follow_errors(&self, follow_errors: bool) {
+        self.inner.lock().follow_errors = follow_errors;
+    }
+
+    /// Switches syntax highlighting to one of syntect's bundled themes.
+    pub fn set_theme(&self, name: &str) -> Result<()> {
+        let mut state = self.inner.lock();
+        if !state.theme_set.themes.contains_key(name) {
+            // Produces the error listing the available themes
+            load_theme(name)?;
+        }
+        state.theme_name = name.to_string();
+        state.update_selected_message();
+        Ok(())
+    }
+
+    /// Switches syntax highlighting to the next bundled theme.
+    pub fn cycle_theme(&self) {
+        let mut state = self.inner.lock();
+        let names = state.theme_set.themes.keys().cloned().collect::<Vec<_>>();
+        let index = names.iter().position(|n| *n == state.theme_name);
+        state.theme_name = names[index.map_or(0, |i| (i + 1) % names.len())].clone();
+        state.status = Some(Ok(format!("Theme: {}", state.theme_name)));
+        state.update_selected_message();
+    }
+
+    pub fn theme(&self) -> Theme {
+        self.inner.lock().theme().clone()
+    }
+
+    /// Toggles jumping to new errors whenever the log file grows.
+    pub fn toggle_follow_errors(&self) {
+        let mut state = self.inner.lock();
+        state.follow_errors = !state.follow_errors;
+        let mode = if state.follow_errors { "on" } else { "off" };
+        state.status = Some(Ok(format!("Jump to new errors: {mode}")));
+    }
+
+    pub fn set_time_display(&self, time_display: TimeDisplay) {
+        self.inner.lock().time_display = time_display;
+    }
+
+    pub fn set_capabilities(&self, capabilities: Capabilities) {
+        let mut state = self.inner.lock();
+        state.capabilities = capabilities;
+        state.update_selected_message();
+    }
+
+    pub fn set_pins(&self, pins: Vec<Pin>) {
+        let mut state = self.inner.lock();
+        state.pins = pins.into_iter().map(|pin| (pin, 0)).collect();
+        state.count_pins();
+    }
+
+    /// Stanzas received and sent per second, with a sparkline of the messages per second, while
+    /// lines are being appended to the log.
+    pub fn live_rate(&self) -> Option<(f64, f64, String)> {
+        let state = self.inner.lock();
+        let rate = state.rate.as_ref()?;
+        let (incoming, outgoing) = rate.rates();
+        Some((incoming, outgoing, rate.sparkline()))
+    }
+
+    /// Whether lines were appended to the log recently enough to show in [`App::live_rate`].
+    pub fn is_live(&self) -> bool {
+        self.inner
+            .lock()
+            .rate
+            .as_ref()
+            .is_some_and(RateMeter::is_live)
+    }
+
+    /// Number of lines that failed to parse.
+    pub fn malformed_count(&self) -> usize {
+        self.inner.lock().malformed.len()
+    }
+
+    /// Lists the lines that failed to parse, with their line numbers and errors.
+    pub fn show_malformed_lines(&self) {
+        let mut state = self.inner.lock();
+        if state.malformed.is_empty() {
+            state.status = Some(Ok("All lines were parsed".to_string()));
+            return;
+        }
+
+        let mut lines = vec![];
+        for malformed in &state.malformed {
+            let location = match self.paths.get(malformed.source) {
+                Some(path) if self.paths.len() > 1 => {
+                    format!("{}:{}", path.display(), malformed.line_number)
+                }
+                _ => format!("Line {}", malformed.line_number),
+            };
+            lines.push(Line::from(vec![
+                Span::styled(location, Style::default().fg(Color::LightYellow)),
+                Span::raw(format!(": {}", malformed.error)),
+            ]));
+            let quote = &malformed.line[..malformed.line.floor_char_boundary(MALFORMED_LINE_QUOTE)];
+            let ellipsis = if quote.len() < malformed.line.len() {
+                "…"
+            } else {
+                ""
+            };
+            lines.push(Line::styled(
+                format!("  {}{ellipsis}", quote.replace('\t', "    ")),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        let title = format!("{} malformed lines (Esc)", state.malformed.len());
+        state.popup = Some(Popup::Text(TextPopup::new(title, lines)));
+    }
+
+    /// Names of the pinned queries with the number of messages matching each.
+    pub fn pin_counts(&self) -> Vec<(String, usize)> {
+        let state = self.inner.lock();
+        state
+            .pins
+            .iter()
+            .map(|(pin, count)| (pin.name.clone(), *count))
+            .collect()
+    }
+
+    pub fn set_timestamp_format(&self, format: &str) {
+        self.inner.lock().timestamp_format = format.to_string();
+    }
+
+    pub fn set_scrolloff(&self, scrolloff: usize) {
+        self.inner.lock().scrolloff = scrolloff;
+    }
+
+    /// Whether timestamps are shown as ages, which need redrawing as time passes.
+    pub fn is_relative_time(&self) -> bool {
+        self.inner.lock().time_display == TimeDisplay::Relative
+    }
+
+    /// Cycles timestamps between absolute times, ages and deltas to the previous message.
+    pub fn cycle_time_display(&self) {
+        let mut state = self.inner.lock();
+        state.time_display = state.time_display.next();
+    }
+
+    pub fn list_colors(&self) -> ListColors {
+        self.inner.lock().list_colors
+    }
+
+    /// Switches the message list between coloring messages by direction and by level.
+    pub fn toggle_list_colors(&self) {
+        let mut state = self.inner.lock();
+        state.list_colors = state.list_colors.toggled();
+    }
+
+    /// Bookmarks the selected message, or removes its bookmark.
+    pub fn toggle_bookmark(&self) {
+        let mut state = self.inner.lock();
+        let Some(selected) = state.messages.selected_item() else {
+            return;
+        };
+
+        let id = selected.id();
+        if !state.bookmarks.remove(&id) {
+            state.bookmarks.insert(id);
+        }
+        let count = state.bookmarks.len();
+        state.status = Some(Ok(format!("{count} bookmarks")));
+    }
+
+    /// Tags the selected message to copy or export it with the other tagged messages, or
+    /// untags it.
+    pub fn toggle_tag(&self) {
+        let mut state = self.inner.lock();
+        let Some(selected) = state.messages.selected_item() else {
+            return;
+        };
+
+        let id = selected.id();
+        if !state.tagged.remove(&id) {
+            state.tagged.insert(id);
+        }
+        let count = state.tagged.len();
+        state.status = Some(Ok(format!("{count} tagged")));
+    }
+
+    pub fn clear_tags(&self) {
+        let mut state = self.inner.lock();
+        state.tagged.clear();
+        state.status = Some(Ok("Untagged all messages".to_string()));
+    }
+
+    /// Selects the next bookmarked message in the list, wrapping around at the end.
+    pub fn select_next_bookmark(&self) {
+        self.inner.lock().select_bookmark_or_match(true);
+    }
+
+    /// Selects the previous bookmarked message in the list, wrapping around at the start.
+    pub fn select_prev_bookmark(&self) {
+        self.inner.lock().select_bookmark_or_match(false);
+    }
+
+    /// Waits for the name of a mark to set at, or jump to.
+    pub fn start_named_mark(&self, command: MarkCommand) {
+        let mut state = self.inner.lock();
+        state.pending_mark = Some(command);
+        state.status = Some(Ok(match command {
+            MarkCommand::Set => "Set mark: type a letter".to_string(),
+            MarkCommand::Jump => "Jump to mark: type a letter, or ' to jump back".to_string(),
+        }));
+    }
+
+    pub fn is_mark_pending(&self) -> bool {
+        self.inner.lock().pending_mark.is_some()
+    }
+
+    pub fn handle_mark_key(&self, code: KeyCode) {
+        self.inner.lock().handle_mark_key(code)
+    }
+
+    /// Marks the selected message for diffing, or unmarks it if it's already marked.
+    pub fn toggle_mark(&self) {
+        let mut state = self.inner.lock();
+        let Some(selected) = state.messages.selected_item().cloned() else {
+            return;
+        };
+
+        let is_marked = state
+            .marked
+            .as_ref()
+            .is_some_and(|m| m.id() == selected.id());
+        if is_marked {
+            state.marked = None;
+            state.diff_mode = None;
+            state.status = Some(Ok("Unmarked message".to_string()));
+        } else {
+            let time = selected.timestamp.format("%H:%M:%S%.3f");
+            state.marked = Some(selected);
+            state.status = Some(Ok(format!("Marked message at {time}")));
+        }
+        state.update_selected_message();
+    }
+
+    /// Cycles the detail pane between the selected message, a unified diff against the marked
+    /// message and a side-by-side diff.
+    pub fn cycle_diff_mode(&self) {
+        let mut state = self.inner.lock();
+        if state.marked.is_none() {
+            state.status = Some(Err("Mark a message to diff against first".to_string()));
+            return;
+        }
+        state.diff_mode = match state.diff_mode {
+            None => Some(DiffMode::Unified),
+            Some(DiffMode::Unified) => Some(DiffMode::SideBySide),
+            Some(DiffMode::SideBySide) => None,
+        };
+        state.update_selected_message();
+    }
+
+    /// Toggles wrapping of long lines in the message detail pane.
+    pub fn toggle_detail_wrap(&self) {
+        let mut state = self.inner.lock();
+        state.wrap_detail = !state.wrap_detail;
+    }
+
+    /// Switches the detail pane between the highlighted, pretty-printed stanza and the stanza
+    /// exactly as logged, to spot whitespace or escaping the pretty printer hides.
+    pub fn toggle_raw_detail(&self) {
+        let mut state = self.inner.lock();
+        state.raw_detail = !state.raw_detail;
+        state.update_selected_message();
+    }
+
+    /// Toggles numbering the lines of the stanza in the detail pane.
+    pub fn toggle_line_numbers(&self) {
+        let mut state = self.inner.lock();
+        state.line_numbers = !state.line_numbers;
+        state.update_selected_message();
+    }
+
+    /// Toggles showing the metadata of the selected message next to its XML, on terminals wide
+    /// enough for both.
+    pub fn toggle_detail_split(&self) {
+        let mut state = self.inner.lock();
+        state.split_detail = !state.split_detail;
+    }
+
+    /// Folds or unfolds the XML element at the top of the detail pane, focusing it first if
+    /// needed.
+    pub fn toggle_fold(&self) {
+        let mut state = self.inner.lock();
+        if state.selected_area != AppArea::MessageDetail {
+            state.selected_area = AppArea::MessageDetail;
+            return;
+        }
+        if let Some(message) = &mut state.formatted_message {
+            message.toggle_fold();
+        }
+    }
+
+    /// Returns when the detail pane is due to show the selected message, if it's waiting for
+    /// the selection to rest.
+    pub fn pending_detail_deadline(&self) -> Option<Instant> {
+        self.inner
+            .lock()
+            .detail_pending_since
+            .map(|since| since + DETAIL_DEBOUNCE)
+    }
+
+    /// Shows the selected message in the detail pane once the selection has rested.
+    pub fn show_pending_detail(&self) {
+        let mut state = self.inner.lock();
+        if state
+            .detail_pending_since
+            .is_some_and(|since| since.elapsed() >= DETAIL_DEBOUNCE)
+        {
+            state.update_selected_message();
+        }
+    }
+
+    pub fn handle_movement(&self, movement: Movement) {
+        self.inner.lock().handle_movement(movement)
+    }
+
+    /// Selects the oldest message, at the top or bottom of the list depending on the order.
+    pub fn jump_to_start(&self) {
+        let mut state = self.inner.lock();
+        if state.is_newest_first() {
+            state.messages.select_last();
+        } else {
+            state.messages.select_first();
+            *state.messages.state.offset_mut() = 0;
+        }
+        state.update_selected_message();
+    }
+
+    /// Selects the newest message, at the top or bottom of the list depending on the order.
+    pub fn jump_to_end(&self) {
+        let mut state = self.inner.lock();
+        state.select_newest();
+        state.update_selected_message();
+    }
+
+    /// Switches the message list between oldest first and newest first, keeping the selected
+    /// message.
+    pub fn toggle_newest_first(&self) {
+        let mut state = self.inner.lock();
+        let newest_first = !state.is_newest_first();
+        state.sort_column = SortColumn::Time;
+        state.sort_ascending = !newest_first;
+
+        let selected = state.messages.selected_item().map(LogMessage::id);
+        state.update_selected_span();
+        if let Some(selected) = selected {
+            let position = state.messages.position_of(selected);
+            state.messages.state.select(position);
+        }
+        state.update_selected_message();
+
+        let order = if newest_first { "newest" } else { "oldest" };
+        state.status = Some(Ok(format!("Showing {order} messages first")));
+    }
+
+    /// Shows only the stanzas structurally similar to the selected one, with how similar they
+    /// are, or shows all messages again if they already are.
+    pub fn toggle_similar_stanzas(&self) {
+        let mut state = self.inner.lock();
+        let selected = state.messages.selected_item().cloned();
+        if state.filter.similar.take().is_some() {
+            state.status = Some(Ok("Showing all stanzas".to_string()));
+        } else {
+            let Some(similar) = selected
+                .as_ref()
+                .and_then(|m| SimilarStanzas::find(m, &state.all_messages.items))
+            else {
+                state.status = Some(Err("Select a stanza to find similar ones".to_string()));
+                return;
+            };
+            // The selected stanza is similar to itself
+            let others = similar.count().saturating_sub(1);
+            state.status = Some(Ok(format!("{others} similar <{}> stanzas", similar.root)));
+            state.filter.similar = Some(similar);
+        }
+
+        state.update_selected_span();
+        if let Some(selected) = selected {
+            let position = state.messages.position_of(selected.id());
+            state.messages.state.select(position);
+        }
+        state.update_selected_message();
+    }
+
+    /// Resets the span, target, level, direction, kind, hash, keepalive, search, similar
+    /// stanzas and time range filters at once, keeping the selected message.
+    pub fn clear_all_filters(&self) {
+        let mut state = self.inner.lock();
+        let selected = state.messages.selected_item().map(LogMessage::id);
+        state.filter.apply(FilterCommand::Clear);
+        state.filter.hide_keepalives = false;
+        state.spans.state.select(Some(0));
+        state.targets.state.select(Some(0));
+
+        state.update_selected_span();
+        if let Some(selected) = selected {
+            let position = state.messages.position_of(selected);
+            if position.is_some() {
+                state.messages.state.select(position);
+            }
+        }
+        state.update_selected_message();
+        state.status = Some(Ok("Cleared all filters".to_string()));
+    }
+
+    /// Starts the time range at the selected message, or ends it there.
+    pub fn set_time_range_bound(&self, start: bool) {
+        let mut state = self.inner.lock();
+        let Some(timestamp) = state.messages.selected_item().map(|m| m.timestamp) else {
+            state.status = Some(Err("No message selected".to_string()));
+            return;
+        };
+        if start {
+            state.filter.since = Some(timestamp);
+        } else {
+            state.filter.until = Some(timestamp);
+        }
+        state.update_time_range();
+    }
+
+    /// Cycles grouping the message list by hour, by session or not at all, keeping the
+    /// selected message.
+    pub fn cycle_grouping(&self) {
+        let mut state = self.inner.lock();
+        state.grouping = state.grouping.next();
+        state.collapsed_groups.clear();
+
+        let selected = state.messages.selected_item().map(LogMessage::id);
+        state.update_selected_span();
+        if let Some(selected) = selected {
+            let position = state.messages.position_of(selected);
+            state.messages.state.select(position);
+        }
+        state.update_selected_message();
+
+        state.status = Some(match (state.grouping, &state.sort_column) {
+            (Grouping::None, _) => Ok("Not grouping messages".to_string()),
+            (grouping, SortColumn::Time) => Ok(format!("Grouping messages by {grouping}")),
+            (grouping, _) => Ok(format!(
+                "Grouping messages by {grouping} once sorted by time"
+            )),
+        });
+    }
+
+    /// Collapses the group of the selected message to its header, or expands it.
+    pub fn toggle_group(&self) {
+        let mut state = self.inner.lock();
+        let Some(start) = state.selected_group().map(|header| header.start) else {
+            state.status = Some(Err("Messages aren't grouped".to_string()));
+            return;
+        };
+        if !state.collapsed_groups.remove(&start) {
+            state.collapsed_groups.insert(start);
+        }
+        state.update_selected_span();
+        state.select_group(start);
+        state.update_selected_message();
+    }
+
+    /// Collapses all groups to their headers, or expands them all if they already are.
+    pub fn toggle_all_groups(&self) {
+        let mut state = self.inner.lock();
+        let Some(start) = state.selected_group().map(|header| header.start) else {
+            state.status = Some(Err("Messages aren't grouped".to_string()));
+            return;
+        };
+        if state.group_headers.values().all(|header| header.collapsed) {
+            state.collapsed_groups.clear();
+        } else {
+            let starts = state
+                .group_headers
+                .values()
+                .map(|header| header.start)
+                .collect::<Vec<_>>();
+            state.collapsed_groups.extend(starts);
+        }
+        state.update_selected_span();
+        state.select_group(start);
+        state.update_selected_message();
+    }
+
+    pub fn messages_len(&self) -> usize {
+        self.inner.lock().messages.items.len()
+    }
+
+    /// Number of messages before filtering.
+    pub fn total_messages_len(&self) -> usize {
+        self.inner.lock().all_messages.items.len()
+    }
+
+    /// Describes the filters hiding messages, e.g. "span: connect".
+    pub fn active_filters(&self) -> Vec<String> {
+        self.inner.lock().active_filters()
+    }
+
+    /// Copies the tagged messages as one document of stanzas annotated with their direction, or
+    /// the selected stanza if none are tagged.
+    pub fn copy_messages_to_clipboard(&self) {
+        let tagged = {
+            let app = self.inner.lock();
+            tagged_messages(&app.all_messages.items, &app.tagged)
+        };
+        let (text, what) = if tagged.is_empty() {
+            let Some(message) = self
+                .inner
+                .lock()
+                .messages
+                .selected_item()
+                .and_then(|m| m.pretty_printed_xml().ok())
+            else {
+                return;
+            };
+            (message, String::new())
+        } else {
+            let text = match tagged.iter().map(annotated_xml).collect::<Result<Vec<_>>>() {
+                Ok(stanzas) => stanzas.join("\n\n"),
+                Err(err) => {
+                    self.inner.lock().toast = Some(Toast::new(Err(format!("{err:#}"))));
+                    return;
+                }
+            };
+            (text, format!("{} tagged messages ", tagged.len()))
+        };
+
+        let native = self.inner.lock().capabilities.clipboard;
+        let status = match copy_to_clipboard(&text, native) {
+            Ok(ClipboardTarget::System) => Ok(format!("Copied {what}to clipboard")),
+            Ok(ClipboardTarget::Terminal) => {
+                Ok(format!("Sent {what}to terminal clipboard (OSC 52)"))
+            }
+            Err(err) => Err(format!("{err:#}")),
+        };
+        self.inner.lock().toast = Some(Toast::new(status));
+    }
+}
+
+impl App {
+    pub fn render_selected_message<B: Backend>(&self, f: &mut Frame<B>, rect: Rect) {
+        let mut app = self.inner.lock();
+
+        let split = app.split_detail && rect.width >= SPLIT_DETAIL_MIN_WIDTH;
+        if split != app.detail_is_split {
+            app.detail_is_split = split;
+            if app.formatted_message.is_some() {
+                app.update_selected_message();
+            }
+        }
+
+        let text = app
+            .formatted_message
+            .as_ref()
+            .map(|m| match app.selected_area {
+                AppArea::MessageDetail => m.highlighted_text(),
+                _ => m.message.clone(),
+            })
+            .unwrap_or_else(
+                || match (&app.background_error, app.messages.selected_item()) {
+                    (Some(err), _) => Text::styled(err.clone(), Style::default().fg(Color::Red)),
+                    // Show the raw message while the selection moves, which is cheap to draw
+                    (None, Some(m)) if app.detail_pending_since.is_some() => Text::styled(
+                        m.fields.message.clone(),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    (None, _) => Text::raw("<no selection>"),
+                },
+            );
+
+        let selected_area = app.selected_area.clone();
+        let selected = app.messages.selected_item().cloned();
+        let title = match (app.messages.selected_item(), &app.marked, app.diff_mode) {
+            (Some(m), Some(marked), Some(_)) => format!(
+                "Diff (m) — {} → {}",
+                app.time_display
+                    .format(marked.timestamp, Utc::now(), DETAIL_TIMESTAMP_FORMAT),
+                app.time_display
+                    .format(m.timestamp, Utc::now(), DETAIL_TIMESTAMP_FORMAT)
+            ),
+            (Some(m), ..) => format!(
+                "Message Detail (m){} — {}",
+                if app.raw_detail { " — raw" } else { "" },
+                app.time_display
+                    .format(m.timestamp, Utc::now(), DETAIL_TIMESTAMP_FORMAT)
+            ),
+            (None, ..) => "Message Detail (m)".to_string(),
+        };
+
+        if app.formatted_message.is_none() {
+            app.set_page_size(AppArea::MessageDetail, rect);
+            let paragraph = Paragraph::new(text)
+                .style(
+                    Style::default().fg(if app.selected_area == AppArea::MessageDetail {
+                        Color::White
+                    } else {
+                        Color::DarkGray
+                    }),
+                )
+                .block(Block::default().borders(Borders::ALL).title(Span::styled(
+                    title,
+                    Style::default().add_modifier(Modifier::BOLD),
+                )));
+            f.render_widget(paragraph, rect);
+            return;
+        }
+
+        // The metadata moves from above the stanza to its own pane when split
+        let (rect, metadata) = match &selected {
+            Some(m) if split => {
+                let chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+                    .split(rect);
+                (chunks[0], Some((chunks[1], app.metadata_pane(m))))
+            }
+            _ => (rect, None),
+        };
+        let wrap_width = app.wrap_detail.then(|| rect.width.saturating_sub(2));
+        let header = match &selected {
+            Some(m) if !split => metadata_header(m, rect.width.saturating_sub(2) as usize),
+            _ => vec![],
+        };
+        let block = Block::default().borders(Borders::ALL).title(Span::styled(
+            title,
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+        let inner = block.inner(rect);
+        let header_height = (header.len() as u16).min(inner.height);
+        let body = Rect {
+            y: inner.y + header_height,
+            height: inner.height - header_height,
+            ..inner
+        };
+        app.set_page_size(
+            AppArea::MessageDetail,
+            Rect {
+                height: rect.height - header_height,
+                ..rect
+            },
+        );
+        let Some(message) = &mut app.formatted_message else {
+            return;
+        };
+
+        message.set_wrap_width(wrap_width);
+        let style = Style::default().fg(if selected_area == AppArea::MessageDetail {
+            Color::White
+        } else {
+            Color::DarkGray
+        });
+        let mut paragraph = Paragraph::new(text)
+            .scroll(message.scroll_position())
+            .style(style);
+        if wrap_width.is_some() {
+            paragraph = paragraph.wrap(Wrap { trim: false });
+        }
+
+        let scrollbar = Scrollbar::default()
+            .orientation(ScrollbarOrientation::VerticalRight)
+            .begin_symbol(None)
+            .end_symbol(None)
+            .track_symbol(None)
+            .thumb_symbol("▐");
+
+        f.render_widget(block.style(style), rect);
+        f.render_widget(
+            Paragraph::new(header),
+            Rect {
+                height: header_height,
+                ..inner
+            },
+        );
+        f.render_widget(paragraph, body);
+        // Draw the scrollbar over the right border, next to the scrolling part
+        f.render_stateful_widget(
+            scrollbar,
+            Rect {
+                x: rect.x,
+                width: rect.width,
+                ..body
+            },
+            &mut message.scroll_state,
+        );
+
+        if let Some((metadata_rect, lines)) = metadata {
+            let paragraph = Paragraph::new(lines)
+                .style(style)
+                .wrap(Wrap { trim: false })
+                .block(Block::default().borders(Borders::ALL).title(Span::styled(
+                    "Metadata",
+                    Style::default().add_modifier(Modifier::BOLD),
+                )));
+            f.render_widget(paragraph, metadata_rect);
+        }
+    }
+
+    pub fn render_spans_list<B: Backend>(&self, f: &mut Frame<B>, rect: Rect) {
+        let mut app = self.inner.lock();
+        let is_selected = app.selected_area == AppArea::Spans;
+
+        let title = match app.sidebar_mode {
+            SidebarMode::Spans => "Spans (s)",
+            SidebarMode::Targets => "Targets (s)",
+        };
+        let app = &mut *app;
+        let counts = match app.sidebar_mode {
+            SidebarMode::Spans => &app.span_counts,
+            SidebarMode::Targets => &app.target_counts,
+        };
+        let list = match app.sidebar_mode {
+            SidebarMode::Spans => &mut app.spans,
+            SidebarMode::Targets => &mut app.targets,
+        };
+
+        let span_items = list
+            .items
+            .iter()
+            .map(|s| match counts.get(s) {
+                Some(count) => ListItem::new(format!("{s} ({count})")),
+                None => ListItem::new(s.clone()),
+            })
+            .collect::<Vec<_>>();
+        let spans_list = List::new(span_items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .style(Style::default().fg(if is_selected {
+                        Color::White
+                    } else {
+                        Color::DarkGray
+                    }))
+                    .title(Span::styled(
+                        title,
+                        Style::default().add_modifier(Modifier::BOLD),
+                    )),
+            )
+            .highlight_style(Style::default().bg(Color::LightYellow).fg(Color::Black));
+
+        f.render_stateful_widget(spans_list, rect, &mut list.state);
+        app.set_page_size(AppArea::Spans, rect);
+    }
+
+    pub fn render_messages_list<B: Backend>(&self, f: &mut Frame<B>, rect: Rect) {
+        let mut app = self.inner.lock();
+
+        let title = app.messages_list_title();
+
+        let now = Utc::now();
+        let timestamps = if app.timestamp_format.is_empty() {
+            vec![]
+        } else {
+            let items = &app.messages.items;
+            let newest_first = app.is_newest_first();
+            items
+                .iter()
+                .enumerate()
+                .map(|(idx, m)| {
+                    if app.time_display != TimeDisplay::Delta {
+                        return app
+                            .time_display
+                            .format(m.timestamp, now, &app.timestamp_format);
+                    }
+                    // Compare with the chronologically previous row
+                    let previous = if newest_first {
+                        items.get(idx + 1)
+                    } else {
+                        idx.checked_sub(1).map(|idx| &items[idx])
+                    };
+                    match previous {
+                        Some(previous) => format_delta(m.timestamp - previous.timestamp),
+                        None => String::new(),
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        let timestamp_width = timestamps.iter().map(|t| t.chars().count()).max();
+
+        // Boundaries only make sense between chronologically adjacent rows
+        let show_boundaries = app.sort_column == SortColumn::Time;
+        let newest_first = app.is_newest_first();
+        let divider_width = rect.width.saturating_sub(2) as usize;
+        let divider = |label: &str, color: Color| {
+            let label = format!("──── {label} ");
+            let fill = divider_width.saturating_sub(label.chars().count());
+            Line::styled(
+                format!("{label}{}", "─".repeat(fill)),
+                Style::default().fg(color),
+            )
+        };
+
+        // The row of the newest message seen before new ones arrived, if any are shown
+        if app.is_at_newest() {
+            app.seen_until = None;
+        }
+        let items = &app.messages.items;
+        let last_seen_idx = app
+            .seen_until
+            .filter(|_| show_boundaries)
+            .and_then(|seen_until| match newest_first {
+                true => items
+                    .iter()
+                    .position(|m| m.timestamp <= seen_until)
+                    .filter(|idx| *idx > 0),
+                false => items
+                    .iter()
+                    .rposition(|m| m.timestamp <= seen_until)
+                    .filter(|idx| idx + 1 < items.len()),
+            });
+
+        let message_items = app
+            .messages
+            .items
+            .iter()
+            .enumerate()
+            .map(|(idx, m)| {
+                if let Some(header) = app.group_headers.get(&idx).filter(|h| h.collapsed) {
+                    return ListItem::new(header.line(app.grouping, divider_width));
+                }
+                let color = app.list_colors.color(m);
+                let mut spans = vec![];
+                if let (Some(timestamp), Some(width)) = (timestamps.get(idx), timestamp_width) {
+                    spans.push(Span::styled(
+                        format!("{timestamp:>width$} "),
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+                let is_marked = app
+                    .marked
+                    .as_ref()
+                    .is_some_and(|marked| marked.id() == m.id());
+                if is_marked {
+                    spans.push(Span::styled("◆ ", Style::default().fg(Color::Magenta)));
+                }
+                if app.bookmarks.contains(&m.id()) {
+                    spans.push(Span::styled("★ ", Style::default().fg(Color::LightCyan)));
+                }
+                if app.tagged.contains(&m.id()) {
+                    spans.push(Span::styled("✓ ", Style::default().fg(Color::LightYellow)));
+                }
+                if !m.lint_warnings.is_empty() {
+                    spans.push(Span::styled("⚠ ", Style::default().fg(Color::Red)));
+                }
+                if let Some(score) = app.filter.similar.as_ref().and_then(|s| s.score(m)) {
+                    spans.push(Span::styled(
+                        format!("{score:>3}% "),
+                        Style::default().fg(Color::LightMagenta),
+                    ));
+                }
+                let text = match &m.preferred_body {
+                    Some(body) => format!("✉ {}", body.replace('\n', " ")),
+                    None => m.fields.message.clone(),
+                };
+                spans.push(Span::styled(text, Style::default().fg(color)));
+
+                let mut lines = vec![Line::from(spans)];
+                if let Some(boundary) = m.channel_boundary().filter(|_| show_boundaries) {
+                    // Draw the divider on the side of the row where the change happens
+                    let line = divider(&boundary.to_string(), Color::Cyan);
+                    if boundary.includes_message() != newest_first {
+                        lines.insert(0, line);
+                    } else {
+                        lines.push(line);
+                    }
+                }
+                if last_seen_idx == Some(idx) {
+                    let line = divider("new messages", Color::LightGreen);
+                    if newest_first {
+                        lines.insert(0, line);
+                    } else {
+                        lines.push(line);
+                    }
+                }
+                if let Some(header) = app.group_headers.get(&idx) {
+                    lines.insert(0, header.line(app.grouping, divider_width));
+                }
+                ListItem::new(lines)
+            })
+            .collect::<Vec<_>>();
+
+        let highlight_color = app
+            .messages
+            .selected_item()
+            .map_or(Color::White, |item| app.list_colors.color(item));
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .style(
+                Style::default().fg(if app.selected_area == AppArea::Messages {
+                    Color::White
+                } else {
+                    Color::DarkGray
+                }),
+            )
+            .title(Span::styled(
+                title,
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+        let inner_rect = block.inner(rect);
+        f.render_widget(block, rect);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(inner_rect);
+
+        // Render the sortable column headers and remember where they are for mouse clicks
+        let mut header_spans = vec![];
+        let mut hit_areas = vec![];
+        let mut x = chunks[0].x;
+        for column in SortColumn::ALL {
+            let arrow = match (app.sort_column == column, app.sort_ascending) {
+                (true, true) => " ▲",
+                (true, false) => " ▼",
+                (false, _) => "",
+            };
+            let label = format!("{}{} ", column.title(), arrow);
+            let width = label.chars().count() as u16;
+            hit_areas.push((Rect::new(x, chunks[0].y, width, 1), column));
+            x += width;
+            header_spans.push(Span::styled(
+                label,
+                Style::default().add_modifier(Modifier::UNDERLINED),
+            ));
+        }
+        app.sort_header_hit_areas = hit_areas;
+        f.render_widget(Paragraph::new(Line::from(header_spans)), chunks[0]);
+
+        // Create a List from all list items and highlight the currently selected one
+        let messages_list = List::new(message_items)
+            .highlight_style(Style::default().bg(highlight_color).fg(Color::Black));
+
+        // Scroll so that `scrolloff` rows stay visible around the selection, without scrolling
+        // past the end of the list. Rows with dividers are taller, so this is approximate there.
+        let height = chunks[1].height as usize;
+        if let Some(selected) = app.messages.state.selected() {
+            let margin = app.scrolloff.min(height.saturating_sub(1) / 2);
+            let min_offset = (selected + margin + 1)
+                .saturating_sub(height)
+                .min(app.messages.items.len().saturating_sub(height));
+            let max_offset = selected.saturating_sub(margin);
+            let offset = app.messages.state.offset().max(min_offset).min(max_offset);
+            *app.messages.state.offset_mut() = offset;
+        }
+
+        // We can now render the item list
+        f.render_stateful_widget(messages_list, chunks[1], &mut app.messages.state);
+        app.page_sizes[AppArea::Messages.index()] = chunks[1].height as usize;
+    }
+}
+
+impl App {
+    pub fn render_toast<B: Backend>(&self, f: &mut Frame<B>, rect: Rect) {
+        if let Some(toast) = &self.inner.lock().toast {
+            toast.render(f, rect);
+        }
+    }
+
+    /// Renders a progress bar for each background task that has been running for a while and
+    /// collects tasks that have finished. Failed tasks are reported in the detail pane.
+    pub fn render_background_tasks<B: Backend>(&self, f: &mut Frame<B>, rect: Rect) {
+        for (idx, (title, ratio)) in self.tasks.visible_tasks().into_iter().enumerate() {
+            let mut rect = centered_rect(60, 3, rect);
+            rect.y = rect.y.saturating_add(idx as u16 * 3);
+
+            let gauge = Gauge::default()
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(format!("{title} (Esc to cancel)")),
+                )
+                .gauge_style(Style::default().fg(Color::LightYellow).bg(Color::DarkGray))
+                .ratio(ratio);
+            f.render_widget(Clear, rect);
+            f.render_widget(gauge, rect);
+        }
+
+        let failures = self.tasks.collect_finished();
+        if let Some((title, failure)) = failures.last() {
+            self.inner.lock().background_error = Some(format!("{title} failed: {failure}"));
+        }
+    }
+
+    pub fn render_popup<B: Backend>(&self, f: &mut Frame<B>, rect: Rect) {
+        let mut app = self.inner.lock();
+        let app = &mut *app;
+
+        let Some(popup) = &mut app.popup else {
+            return;
+        };
+
+        match popup {
+            Popup::LevelFilter(levels) => {
+                let items = levels
+                    .items
+                    .iter()
+                    .map(|level| {
+                        let checkbox = if app.filter.excluded_levels.contains(level) {
+                            "[ ]"
+                        } else {
+                            "[x]"
+                        };
+                        ListItem::new(format!("{checkbox} {level}"))
+                    })
+                    .collect::<Vec<_>>();
+
+                let list = List::new(items)
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .style(Style::default().fg(Color::White))
+                            .title(Span::styled(
+                                "Levels (l)",
+                                Style::default().add_modifier(Modifier::BOLD),
+                            )),
+                    )
+                    .highlight_style(Style::default().bg(Color::LightYellow).fg(Color::Black));
+
+                let rect = centered_rect(24, levels.items.len() as u16 + 2, rect);
+                f.render_widget(Clear, rect);
+                f.render_stateful_widget(list, rect, &mut levels.state);
+            }
+            Popup::Export(dialog) => dialog.render(f, rect),
+            Popup::Help(help) => help.render(f, rect),
+            Popup::SpanStats(stats) => stats.render(f, rect),
+            Popup::Text(popup) => popup.render(f, rect),
+        }
+    }
+}
+
+impl AppInner {
+    fn new(paths: &[PathBuf]) -> Self {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let theme_set = ThemeSet::load_defaults();
+
+        let tabs = if paths.len() > 1 {
+            once("[All]".to_string())
+                .chain(paths.iter().map(|p| {
+                    p.file_stem()
+                        .map(|s| s.to_string_lossy().into_owned())
+                        .unwrap_or_default()
+                }))
+                .collect()
+        } else {
+            vec![]
+        };
+
+        AppInner {
+            syntax_set,
+            theme_set,
+            theme_name: DEFAULT_THEME.to_string(),
+            all_messages: StatefulList::with_items(vec![]),
+            messages: StatefulList::with_items(vec![]),
+            spans: StatefulList::with_items(vec!["[All Messages]".to_string()]),
+            targets: StatefulList::with_items(vec!["[All Messages]".to_string()]),
+            storms: vec![],
+            span_counts: HashMap::new(),
+            pins: vec![],
+            target_counts: HashMap::new(),
+            sidebar_mode: Default::default(),
+            formatted_message: None,
+            detail_pending_since: None,
+            wrap_detail: false,
+            raw_detail: false,
+            line_numbers: false,
+            split_detail: false,
+            detail_is_split: false,
+            selected_area: Default::default(),
+            zoomed_area: None,
+            sidebar_percent: 30,
+            spans_percent: 30,
+            filter: Filter::default(),
+            prompt: None,
+            popup: None,
+            tabs,
+            selected_tab: 0,
+            sort_column: Default::default(),
+            sort_ascending: true,
+            sort_header_hit_areas: vec![],
+            page_sizes: [1; 3],
+            background_error: None,
+            follow_errors: false,
+            time_display: TimeDisplay::default(),
+            list_colors: ListColors::default(),
+            timestamp_format: DEFAULT_LIST_TIMESTAMP_FORMAT.to_string(),
+            scrolloff: 0,
+            grouping: Grouping::default(),
+            collapsed_groups: HashSet::new(),
+            group_headers: HashMap::new(),
+            bookmarks: HashSet::new(),
+            tagged: HashSet::new(),
+            named_marks: HashMap::new(),
+            pending_mark: None,
+            marked: None,
+            diff_mode: None,
+            redact: false,
+            last_pipe_command: String::new(),
+            capabilities: Capabilities::default(),
+            status: None,
+            toast: None,
+            rate: None,
+            malformed: vec![],
+            append_state: None,
+            append_requested: false,
+            seen_until: None,
+        }
+    }
+
+    /// Replaces all messages while keeping the selection, filters and scroll offsets.
+    /// Adds the messages of appended lines, which are in chronological order if `merge`d
+    /// into the messages of several files.
+    fn add_appended_messages(
+        &mut self,
+        messages: Vec<LogMessage>,
+        malformed: Vec<MalformedLine>,
+        merge: bool,
+    ) {
+        self.malformed.extend(malformed);
+        if messages.is_empty() {
+            return;
+        }
+        self.rate
+            .get_or_insert_with(RateMeter::default)
+            .record(&messages);
+        let newest_error = messages
+            .iter()
+            .filter(|m| m.is_error())
+            .max_by_key(|m| m.chronological_key())
+            .cloned();
+
+        let mut all_messages = std::mem::take(&mut self.all_messages.items);
+        match merge {
+            true => merge_chronologically(&mut all_messages, messages),
+            false => all_messages.extend(messages),
+        }
+        self.set_messages(all_messages);
+        if let Some(error) = newest_error.filter(|_| self.follow_errors) {
+            self.select_new_error(&error);
+        }
+    }
+
+    fn set_messages(&mut self, messages: Vec<LogMessage>) {
+        let previous_len = self.all_messages.items.len();
+        let selected_message_idx = self.messages.state.selected();
+        let selected_id = self.messages.selected_item().map(LogMessage::id);
+        let previous_filtered_len = self.messages.items.len();
+        let is_at_newest = selected_message_idx.is_some() && self.is_at_newest();
+        let previous_newest = self.all_messages.items.iter().map(|m| m.timestamp).max();
+        if is_at_newest {
+            self.seen_until = None;
+        } else if messages.len() > previous_len && self.seen_until.is_none() {
+            self.seen_until = previous_newest;
+        }
+
+        let mut span_counts = HashMap::<String, usize>::new();
+        let mut target_counts = HashMap::<String, usize>::new();
+        for message in &messages {
+            // Count messages once per span, even if nested in spans of the same name
+            let names = message
+                .spans
+                .iter()
+                .flatten()
+                .map(|s| &s.name)
+                .collect::<HashSet<_>>();
+            for name in names {
+                *span_counts.entry(name.clone()).or_default() += 1;
+            }
+            *target_counts.entry(message.target.clone()).or_default() += 1;
+        }
+
+        let mut spans = span_counts.keys().cloned().collect::<Vec<_>>();
+        spans.sort();
+        let mut targets = target_counts.keys().cloned().collect::<Vec<_>>();
+        targets.sort();
+        span_counts.insert("[All Messages]".to_string(), messages.len());
+        target_counts.insert("[All Messages]".to_string(), messages.len());
+
+        let mut reconnect_detector = ReconnectDetector::default();
+        for message in &messages {
+            reconnect_detector.feed(message);
+        }
+        self.storms = reconnect_detector.finish();
+        let mut storm_items = vec![];
+        for storm in &self.storms {
+            let item = format!(
+                "[Reconnect storm {}, {} connects]",
+                storm.start.format("%H:%M:%S"),
+                storm.connects
+            );
+            let count = messages.iter().filter(|m| storm.contains(m)).count();
+            span_counts.insert(item.clone(), count);
+            storm_items.push(item);
+        }
+
+        self.spans.items = once("[All Messages]".to_string())
+            .chain(storm_items)
+            .chain(spans)
+            .collect();
+        self.targets.items = once("[All Messages]".to_string()).chain(targets).collect();
+        self.span_counts = span_counts;
+        self.target_counts = target_counts;
+        self.all_messages = StatefulList::with_items(messages);
+        self.background_error = None;
+        self.count_pins();
+
+        let mut messages_offset = self.messages.state.offset();
+        let mut selected_message_idx = selected_message_idx;
+        self.update_selected_span();
+
+        // New messages are inserted above the selection when the newest are shown first
+        if self.is_newest_first() {
+            let added = self
+                .messages
+                .items
+                .len()
+                .saturating_sub(previous_filtered_len);
+            messages_offset += added;
+            selected_message_idx = selected_message_idx.map(|i| i + added);
+        }
+        *self.messages.state.offset_mut() = messages_offset;
+
+        if is_at_newest {
+            self.select_newest();
+        } else {
+            // New messages of other files may be interleaved before the selected one
+            let position = selected_id.and_then(|id| self.messages.position_of(id));
+            self.messages
+                .state
+                .select(position.or(selected_message_idx));
+        }
+
+        self.update_selected_message();
+    }
+
+    fn count_pins(&mut self) {
+        for (pin, count) in &mut self.pins {
+            *count = self
+                .all_messages
+                .items
+                .iter()
+                .filter(|m| pin.matches(m))
+                .count();
+        }
+    }
+
+    /// Jumps to the next or previous match of the detail search while the detail pane is
+    /// focused, or to a bookmarked message otherwise.
+    fn select_bookmark_or_match(&mut self, forward: bool) {
+        if self.selected_area == AppArea::MessageDetail {
+            if let Some(message) = self
+                .formatted_message
+                .as_mut()
+                .filter(|m| m.search_query().is_some())
+            {
+                self.status = Some(match message.select_match(forward) {
+                    Some((position, count)) => Ok(format!("Match {position} of {count}")),
+                    None => Err("No matches".to_string()),
+                });
+                return;
+            }
+        }
+        self.select_bookmark(forward);
+    }
+
+    fn handle_mark_key(&mut self, code: KeyCode) {
+        let Some(command) = self.pending_mark.take() else {
+            return;
+        };
+        let name = match code {
+            KeyCode::Char(c) if c.is_ascii_alphanumeric() || c == '\'' => c,
+            _ => {
+                self.status = None;
+                return;
+            }
+        };
+
+        let selected_id = self.messages.selected_item().map(LogMessage::id);
+        match command {
+            MarkCommand::Set => {
+                let Some(id) = selected_id else {
+                    self.status = Some(Err("No message selected".to_string()));
+                    return;
+                };
+                self.named_marks.insert(name, id);
+                self.status = Some(Ok(format!("Mark '{name}' set")));
+            }
+            MarkCommand::Jump => {
+                let Some(&id) = self.named_marks.get(&name) else {
+                    self.status = Some(Err(format!("Mark '{name}' isn't set")));
+                    return;
+                };
+                let Some(position) = self.messages.position_of(id) else {
+                    let error = format!("Mark '{name}' is hidden by the current filters");
+                    self.status = Some(Err(error));
+                    return;
+                };
+                if let Some(id) = selected_id {
+                    self.named_marks.insert('\'', id);
+                }
+                self.messages.state.select(Some(position));
+                self.update_selected_message();
+            }
+        }
+    }
+
+    fn select_bookmark(&mut self, forward: bool) {
+        if self.bookmarks.is_empty() {
+            self.status = Some(Err("No bookmarks".to_string()));
+            return;
+        }
+
+        let len = self.messages.items.len();
+        let start = self
+            .messages
+            .state
+            .selected()
+            .unwrap_or(len.saturating_sub(1));
+        let position = (1..=len)
+            .map(|offset| {
+                if forward {
+                    (start + offset) % len
+                } else {
+                    (start + len - offset) % len
+                }
+            })
+            .find(|&i| self.bookmarks.contains(&self.messages.items[i].id()));
+
+        match position {
+            Some(position) => {
+                self.messages.state.select(Some(position));
+                self.update_selected_message();
+            }
+            None => {
+                self.status = Some(Err("No bookmarks match the current filters".to_string()));
+            }
+        }
+    }
+
+    /// Whether the message list is sorted by time with the newest message at the top.
+    fn is_newest_first(&self) -> bool {
+        self.sort_column == SortColumn::Time && !self.sort_ascending
+    }
+
+    fn is_at_newest(&self) -> bool {
+        let newest = if self.is_newest_first() {
+            0
+        } else {
+            self.messages.items.len().saturating_sub(1)
+        };
+        self.messages.state.selected() == Some(newest)
+    }
+
+    fn select_newest(&mut self) {
+        if self.is_newest_first() {
+            self.messages.select_first();
+        } else {
+            self.messages.select_last();
+        }
+    }
+
+    /// Selects `error`, one of the messages just appended, if it passes the current filters.
+    fn select_new_error(&mut self, error: &LogMessage) {
+        let Some(position) = self.messages.position_of(error.id()) else {
+            return;
+        };
+
+        self.messages.state.select(Some(position));
+        self.status = Some(Err(format!(
+            "New error at {}",
+            error.timestamp.format("%H:%M:%S%.3f")
+        )));
+        self.update_selected_message();
+    }
+
+    /// Describes the filters that are currently narrowing down `messages`.
+    fn active_filters(&self) -> Vec<String> {
+        let mut filters = self.filter.descriptions();
+        let collapsed = self.group_headers.values().filter(|h| h.collapsed).count();
+        if collapsed > 0 {
+            filters.push(format!("{collapsed} groups collapsed"));
+        }
+        filters
+    }
+
+    fn messages_list_title(&self) -> String {
+        let filters = self.active_filters();
+        if filters.is_empty() {
+            return "All Messages (a)".to_string();
+        }
+        format!("Messages — {} (a)", filters.join(", "))
+    }
+
+    /// Clears the detail pane until the selection has rested for `DETAIL_DEBOUNCE`.
+    fn defer_selected_message(&mut self) {
+        self.formatted_message = None;
+        self.detail_pending_since = Some(Instant::now());
+    }
+
+    fn update_selected_message(&mut self) {
+        self.detail_pending_since = None;
+        self.formatted_message = self.messages.selected_item().and_then(|m| {
+            if let (Some(marked), Some(mode)) = (&self.marked, self.diff_mode) {
+                let old = marked.pretty_printed_xml().ok()?;
+                let new = m.pretty_printed_xml().ok()?;
+                return Some(render_diff(&old, &new, mode).into());
+            }
+
+            let xml = match self.raw_detail {
+                true => m
+                    .fields
+                    .message
+                    .split('\n')
+                    .map(|line| Line::from(line.to_string()))
+                    .collect(),
+                false => self.highlighted_lines(m).ok()?,
+            };
+            // When split, the annotations are shown in the metadata pane instead
+            let mut lines = match self.detail_is_split {
+                true => vec![],
+                false => lint_warning_lines(m),
+            };
+            let stanza = lines.len()..lines.len() + xml.len();
+            lines.extend(xml);
+            if !self.detail_is_split {
+                lines.extend(self.related_stanza_lines(m));
+            }
+            let mut message = SelectedLogMessage::from(lines);
+            if self.line_numbers {
+                message.set_numbered_lines(stanza);
+            }
+            Some(message)
+        })
+    }
+
+    /// Lines of the detail pane's metadata pane: the context of `message`, its lint warnings
+    /// and the stanza related to it.
+    fn metadata_pane(&self, message: &LogMessage) -> Vec<Line<'static>> {
+        let mut lines = message_context(message);
+        let warnings = lint_warning_lines(message);
+        if !warnings.is_empty() {
+            lines.push(Line::from(""));
+            lines.extend(warnings);
+        }
+        lines.extend(self.related_stanza_lines(message));
+        lines
+    }
+
+    /// The highlighted stanza of `message`, in colors the terminal can show.
+    fn highlighted_lines(&self, message: &LogMessage) -> Result<Vec<Line<'static>>> {
+        let mut lines = message.highlighted_stanza_xml_text(&self.syntax_set, self.theme())?;
+        if !self.capabilities.truecolor {
+            reduce_to_256_colors(&mut lines);
+        }
+        Ok(lines)
+    }
+
+    /// Lines showing the stanza related to an error, introduced by a blank line, if any.
+    fn related_stanza_lines(&self, message: &LogMessage) -> Vec<Line<'static>> {
+        let Some(stanza) = &message.related_stanza else {
+            return vec![];
+        };
+        let Ok(xml) = self.highlighted_lines(stanza) else {
+            return vec![];
+        };
+        let direction = match stanza.fields.direction {
+            Some(StanzaDirection::In) => "received",
+            _ => "sent",
+        };
+        let delta = format_delta(stanza.timestamp - message.timestamp);
+        let mut lines = vec![
+            Line::from(""),
+            Line::styled(
+                format!("── Last stanza {direction} in this span ({delta}) ──"),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ];
+        lines.extend(xml);
+        lines
+    }
+
+    fn theme(&self) -> &Theme {
+        &self.theme_set.themes[&self.theme_name]
+    }
+
+    /// Returns the selected item of a sidebar list, or `None` if "[All Messages]" is selected.
+    fn selected_filter_item(list: &StatefulList<String>) -> Option<&String> {
+        if list.state.selected() == Some(0) {
+            return None;
+        }
+        list.selected_item()
+    }
+
+    /// Returns the reconnect storm selected in the spans list, if any.
+    fn selected_storm(&self) -> Option<&Storm> {
+        let index = self.spans.state.selected()?.checked_sub(1)?;
+        self.storms.get(index)
+    }
+
+    fn sidebar_list_mut(&mut self) -> &mut StatefulList<String> {
+        match self.sidebar_mode {
+            SidebarMode::Spans => &mut self.spans,
+            SidebarMode::Targets => &mut self.targets,
+        }
+    }
+
+    fn update_selected_span(&mut self) {
+        self.filter.storm = self.selected_storm().cloned();
+        self.filter.span = Self::selected_filter_item(&self.spans)
+            .filter(|_| self.filter.storm.is_none())
+            .cloned();
+        self.filter.target = Self::selected_filter_item(&self.targets).cloned();
+        self.filter.account = self
+            .selected_tab
+            .checked_sub(1)
+            .map(|source| (source, self.tabs[self.selected_tab].clone()));
 
         self.messages = StatefulList::with_items(
             self.all_messages
                 .items
                 .iter()
-                .filter(|m| {
-                    m.spans
-                        .as_ref()
-                        .and_then(|s| s.iter().find(|s| &s.name == span_name))
-                        .is_some()
-                })
+                .filter(|m| self.filter.matches(m))
                 .cloned()
                 .collect(),
         );
+
+        // Break ties chronologically, so that equal keys keep the order they were logged in
+        match self.sort_column {
+            SortColumn::Time => self
+                .messages
+                .items
+                .sort_by_key(LogMessage::chronological_key),
+            SortColumn::Level => self
+                .messages
+                .items
+                .sort_by_key(|m| (m.level, m.chronological_key())),
+            SortColumn::Message => self.messages.items.sort_by(|a, b| {
+                a.fields
+                    .message
+                    .cmp(&b.fields.message)
+                    .then_with(|| a.chronological_key().cmp(&b.chronological_key()))
+            }),
+        }
+        if !self.sort_ascending {
+            self.messages.items.reverse();
+        }
+
+        // Groups only make sense for chronologically adjacent rows
+        let grouping = match self.sort_column {
+            SortColumn::Time => self.grouping,
+            _ => Grouping::None,
+        };
+        let newest_first = self.is_newest_first();
+        self.group_headers = group_messages(
+            &mut self.messages.items,
+            grouping,
+            newest_first,
+            &self.collapsed_groups,
+        );
+    }
+
+    /// Returns the header of the group the selected row is in, if the list is grouped.
+    fn selected_group(&self) -> Option<&GroupHeader> {
+        let selected = self.messages.state.selected()?;
+        self.group_headers
+            .iter()
+            .filter(|(idx, _)| **idx <= selected)
+            .max_by_key(|(idx, _)| **idx)
+            .map(|(_, header)| header)
+    }
+
+    /// Selects the header row of the group starting at `start`, if it's shown.
+    fn select_group(&mut self, start: DateTime<Utc>) {
+        let row = self
+            .group_headers
+            .iter()
+            .find(|(_, header)| header.start == start)
+            .map(|(idx, _)| *idx);
+        if row.is_some() {
+            self.messages.state.select(row);
+        }
+    }
+
+    /// Resolves a time of day on the day of the selected message, or of the first message if
+    /// none is selected.
+    fn resolve_time(&self, target: GotoTarget) -> Result<DateTime<Utc>> {
+        let date_time = match target {
+            GotoTarget::Time(time) => {
+                let reference = self
+                    .messages
+                    .selected_item()
+                    .or(self.messages.items.first())
+                    .or(self.all_messages.items.first())
+                    .ok_or_else(|| anyhow::format_err!("No messages to go to"))?;
+                reference.timestamp.date_naive().and_time(time)
+            }
+            GotoTarget::DateTime(date_time) => date_time,
+        };
+        Ok(date_time.and_utc())
+    }
+
+    /// Filters the messages to the time range just set, keeping the selected message if it's
+    /// in the range.
+    fn update_time_range(&mut self) {
+        if let (Some(since), Some(until)) = (self.filter.since, self.filter.until) {
+            if since > until {
+                self.filter.since = Some(until);
+                self.filter.until = Some(since);
+            }
+        }
+
+        let selected = self.messages.selected_item().map(LogMessage::id);
+        self.update_selected_span();
+        if let Some(selected) = selected {
+            let position = self.messages.position_of(selected);
+            if position.is_some() {
+                self.messages.state.select(position);
+            }
+        }
+        self.update_selected_message();
+
+        self.status = Some(Ok(match self.filter.time_range_description() {
+            Some(time_range) => format!("Time range: {time_range}"),
+            None => "Showing all times".to_string(),
+        }));
+    }
+
+    fn handle_prompt_key(&mut self, code: KeyCode) -> Option<Job> {
+        let Some(prompt) = &mut self.prompt else {
+            return None;
+        };
+
+        match prompt.handle_key(code) {
+            PromptEvent::Pending if prompt.kind == PromptKind::TypeAhead => {
+                let input = prompt.input.clone();
+                self.type_ahead(&input);
+                return None;
+            }
+            PromptEvent::Pending => return None,
+            PromptEvent::Cancelled => (),
+            PromptEvent::Submitted(input) => match prompt.kind {
+                PromptKind::Search | PromptKind::RegexSearch => {
+                    self.filter.search = match prompt.kind {
+                        PromptKind::RegexSearch => match SearchQuery::regex(&input) {
+                            Ok(search) => search,
+                            Err(err) => {
+                                prompt.error = Some(err.to_string());
+                                return None;
+                            }
+                        },
+                        _ => SearchQuery::text(input),
+                    };
+                    self.update_selected_span();
+                    self.update_selected_message();
+                }
+                PromptKind::DetailSearch => {
+                    let Some(message) = &mut self.formatted_message else {
+                        return None;
+                    };
+                    let matches = message.search(&input);
+                    if matches == 0 && !input.is_empty() {
+                        self.status = Some(Err(format!("No matches for \"{input}\"")));
+                    } else if matches > 0 {
+                        let lines = if matches == 1 { "line" } else { "lines" };
+                        self.status =
+                            Some(Ok(format!("{matches} matching {lines}, n / N to jump")));
+                    }
+                }
+                PromptKind::ExplainFilters => {
+                    let matching = self
+                        .all_messages
+                        .items
+                        .iter()
+                        .filter(|m| !input.is_empty() && m.fields.message.contains(input.as_str()))
+                        .collect::<Vec<_>>();
+                    let message = match (matching.first(), &self.marked) {
+                        (Some(message), _) => *message,
+                        (None, Some(marked)) if input.is_empty() => marked,
+                        (None, _) if input.is_empty() => {
+                            prompt.error = Some("No message is marked".to_string());
+                            return None;
+                        }
+                        (None, _) => {
+                            prompt.error = Some("No message contains this text".to_string());
+                            return None;
+                        }
+                    };
+
+                    let mut lines = vec![Line::from(
+                        message.fields.message.chars().take(80).collect::<String>(),
+                    )];
+                    if matching.len() > 1 {
+                        lines.push(Line::styled(
+                            format!("First of {} messages containing the text", matching.len()),
+                            Style::default().fg(Color::DarkGray),
+                        ));
+                    }
+                    lines.push(Line::from(""));
+                    let verdicts = self.filter.explain(message);
+                    if verdicts.is_empty() {
+                        lines.push(Line::from("No filters are active"));
+                    }
+                    for (filter, value, passes) in verdicts {
+                        let (mark, color) = if passes {
+                            ("✓", Color::Green)
+                        } else {
+                            ("✗", Color::Red)
+                        };
+                        let mut spans = vec![Span::styled(
+                            format!("{mark} {filter}"),
+                            Style::default().fg(color),
+                        )];
+                        if let Some(value) = value {
+                            spans.push(Span::styled(
+                                format!("  (message: {value})"),
+                                Style::default().fg(Color::DarkGray),
+                            ));
+                        }
+                        lines.push(Line::from(spans));
+                    }
+                    self.popup = Some(Popup::Text(TextPopup::new("Filters (Esc)", lines)));
+                }
+                PromptKind::SaveStanza => {
+                    let message = self.messages.selected_item()?;
+                    if let Err(err) = save_message(message, self.redact, &input) {
+                        prompt.error = Some(err.to_string());
+                        return None;
+                    }
+                    self.status = Some(Ok(format!("Saved to {input}")));
+                }
+                // The selection already moved while typing
+                PromptKind::TypeAhead => (),
+                PromptKind::Pipe => {
+                    let message = self.messages.selected_item()?;
+                    if input.trim().is_empty() {
+                        prompt.error = Some("Type a command to pipe the stanza to".to_string());
+                        return None;
+                    }
+                    let stanza = match self.redact {
+                        true => message.redacted().and_then(|m| m.pretty_printed_xml()),
+                        false => message.pretty_printed_xml(),
+                    };
+                    let stanza = match stanza {
+                        Ok(stanza) => stanza,
+                        Err(err) => {
+                            prompt.error = Some(err.to_string());
+                            return None;
+                        }
+                    };
+                    self.last_pipe_command = input.clone();
+                    self.prompt = None;
+                    return Some(Job::Pipe(PipeRequest {
+                        command: input,
+                        stanza,
+                    }));
+                }
+                PromptKind::Command => {
+                    let result = parse_command(&input).and_then(|c| self.run_command(c));
+                    match result {
+                        Ok(request) => {
+                            self.prompt = None;
+                            return request.map(|request| Job::Export(Box::new(request)));
+                        }
+                        Err(err) => {
+                            if let Some(prompt) = &mut self.prompt {
+                                prompt.error = Some(err.to_string());
+                            }
+                            return None;
+                        }
+                    }
+                }
+            },
+        }
+        self.prompt = None;
+        None
+    }
+
+    /// Selects the first entry of the focused list, from the selected one on, whose text
+    /// starts with `text`, or failing that contains it, ignoring case.
+    fn type_ahead(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let text = text.to_lowercase();
+        match self.selected_area {
+            AppArea::Spans => {
+                let list = self.sidebar_list_mut();
+                let selected = list.state.selected();
+                if let Some(position) =
+                    type_ahead_position(&list.items, selected, &text, |s| s.as_str())
+                {
+                    list.state.select(Some(position));
+                    self.update_selected_span();
+                }
+            }
+            AppArea::Messages => {
+                let selected = self.messages.state.selected();
+                let position = type_ahead_position(&self.messages.items, selected, &text, |m| {
+                    m.fields.message.as_str()
+                });
+                if let Some(position) = position {
+                    self.messages.state.select(Some(position));
+                    self.defer_selected_message();
+                }
+            }
+            AppArea::MessageDetail => (),
+        }
+    }
+
+    /// Runs a `:` command, returning the export to start if it's an export.
+    fn run_command(&mut self, command: Command) -> Result<Option<ExportRequest>> {
+        match command {
+            Command::Filter(filter) => {
+                self.filter.apply(filter);
+                self.update_selected_span();
+                self.update_selected_message();
+            }
+            Command::Goto(target) => {
+                let time = self.resolve_time(target)?;
+                let position = self
+                    .messages
+                    .items
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, m)| m.timestamp >= time)
+                    .min_by_key(|(_, m)| m.timestamp)
+                    .map(|(position, _)| position)
+                    .ok_or_else(|| anyhow::format_err!("No messages at or after {time}"))?;
+                self.messages.state.select(Some(position));
+                self.update_selected_message();
+            }
+            Command::Range { since, until } => {
+                self.filter.since = since.map(|t| self.resolve_time(t)).transpose()?;
+                self.filter.until = until.map(|t| self.resolve_time(t)).transpose()?;
+                self.update_time_range();
+            }
+            Command::Export { path, format } => {
+                return Ok(Some(ExportRequest {
+                    messages: self.messages.items.clone(),
+                    format,
+                    redact: self.redact,
+                    theme: self.theme().clone(),
+                    path,
+                }));
+            }
+            Command::Theme(name) => {
+                let lowercase = name.to_lowercase();
+                let theme = self
+                    .theme_set
+                    .themes
+                    .keys()
+                    .find(|t| **t == name)
+                    .or_else(|| {
+                        let mut names = self.theme_set.themes.keys();
+                        names.find(|t| t.to_lowercase().starts_with(&lowercase))
+                    })
+                    .ok_or_else(|| anyhow::format_err!("No theme starts with `{name}`"))?;
+                self.theme_name = theme.clone();
+                self.status = Some(Ok(format!("Theme: {}", self.theme_name)));
+                self.update_selected_message();
+            }
+            Command::Pin { name, query } => {
+                let pin = Pin::new(&name, &query)?;
+                match self.pins.iter_mut().find(|(p, _)| p.name == name) {
+                    Some((existing, _)) => *existing = pin,
+                    None => self.pins.push((pin, 0)),
+                }
+                self.count_pins();
+                self.status = Some(Ok(format!("Pinned {name}")));
+            }
+            Command::Unpin(name) => {
+                let len = self.pins.len();
+                self.pins.retain(|(pin, _)| pin.name != name);
+                if self.pins.len() == len {
+                    return Err(anyhow::format_err!("No pinned query is named `{name}`"));
+                }
+                self.status = Some(Ok(format!("Unpinned {name}")));
+            }
+        }
+        Ok(None)
+    }
+
+    fn handle_popup_key(&mut self, code: KeyCode) -> Option<ExportRequest> {
+        let Some(popup) = &mut self.popup else {
+            return None;
+        };
+
+        match popup {
+            Popup::LevelFilter(levels) => match code {
+                KeyCode::Up => levels.prev(),
+                KeyCode::Down => levels.next(),
+                KeyCode::Char(' ') | KeyCode::Enter => {
+                    let level = levels.selected_item().copied()?;
+                    if !self.filter.excluded_levels.remove(&level) {
+                        self.filter.excluded_levels.insert(level);
+                    }
+                    self.update_selected_span();
+                    self.update_selected_message();
+                }
+                KeyCode::Esc | KeyCode::Char('l') => self.popup = None,
+                _ => (),
+            },
+            Popup::Export(dialog) => match dialog.handle_key(code) {
+                ExportDialogEvent::Pending => (),
+                ExportDialogEvent::Closed => self.popup = None,
+                ExportDialogEvent::Submitted => {
+                    let messages = match dialog.scope {
+                        ExportScope::Selection => {
+                            self.messages.selected_item().into_iter().cloned().collect()
+                        }
+                        ExportScope::Tagged => {
+                            tagged_messages(&self.all_messages.items, &self.tagged)
+                        }
+                        ExportScope::Filter => self.messages.items.clone(),
+                        ExportScope::All => self.all_messages.items.clone(),
+                    };
+                    dialog.status = None;
+                    self.redact = dialog.redact;
+                    return Some(ExportRequest {
+                        messages,
+                        format: dialog.format,
+                        redact: dialog.redact,
+                        theme: self.theme_set.themes[&self.theme_name].clone(),
+                        path: dialog.path.clone(),
+                    });
+                }
+            },
+            Popup::Help(help) => match code {
+                KeyCode::Esc | KeyCode::Char('?') => self.popup = None,
+                KeyCode::Up => help.scroll_by(-1),
+                KeyCode::Down => help.scroll_by(1),
+                _ => (),
+            },
+            Popup::SpanStats(_) => {
+                if matches!(code, KeyCode::Esc | KeyCode::Char('i' | 'I')) {
+                    self.popup = None
+                }
+            }
+            Popup::Text(popup) => match code {
+                KeyCode::Esc => self.popup = None,
+                KeyCode::Up => popup.scroll_by(-1),
+                KeyCode::Down => popup.scroll_by(1),
+                _ => (),
+            },
+        }
+        None
+    }
+
+    /// Remembers the number of rows visible inside the bordered `rect` of `area`.
+    fn set_page_size(&mut self, area: AppArea, rect: Rect) {
+        self.page_sizes[area.index()] = rect.height.saturating_sub(2).max(1) as usize;
+    }
+
+    fn handle_movement(&mut self, movement: Movement) {
+        let mut page_size = self.page_sizes[self.selected_area.index()];
+        let movement = match movement {
+            Movement::HalfPageUp | Movement::HalfPageDown => {
+                page_size = (page_size / 2).max(1);
+                if movement == Movement::HalfPageUp {
+                    Movement::PageUp
+                } else {
+                    Movement::PageDown
+                }
+            }
+            movement => movement,
+        };
+
+        match self.selected_area {
+            AppArea::Spans => {
+                move_list_selection(self.sidebar_list_mut(), movement, page_size);
+                self.update_selected_span();
+                self.update_selected_message();
+            }
+            AppArea::Messages => {
+                move_list_selection(&mut self.messages, movement, page_size);
+                self.defer_selected_message();
+            }
+            AppArea::MessageDetail => {
+                let Some(m) = &mut self.formatted_message else {
+                    return;
+                };
+                match movement {
+                    Movement::PageUp | Movement::HalfPageUp => m.scroll_by(-(page_size as i32)),
+                    Movement::PageDown | Movement::HalfPageDown => m.scroll_by(page_size as i32),
+                    Movement::First => m.scroll_to_top(),
+                    Movement::Last => m.scroll_to_bottom(),
+                }
+            }
+        }
     }
 
     fn handle_key_up(&mut self) {
         match self.selected_area {
             AppArea::Spans => {
-                self.spans.prev();
+                self.sidebar_list_mut().prev();
                 self.update_selected_span();
             }
             AppArea::Messages => {
                 self.messages.prev();
-                self.update_selected_message();
+                self.defer_selected_message();
             }
             AppArea::MessageDetail => {
                 if let Some(m) = &mut self.formatted_message {
@@ -368,12 +2988,12 @@ impl AppInner {
     fn handle_key_down(&mut self) {
         match self.selected_area {
             AppArea::Spans => {
-                self.spans.next();
+                self.sidebar_list_mut().next();
                 self.update_selected_span();
             }
             AppArea::Messages => {
                 self.messages.next();
-                self.update_selected_message();
+                self.defer_selected_message();
             }
             AppArea::MessageDetail => {
                 if let Some(m) = &mut self.formatted_message {
@@ -383,3 +3003,288 @@ impl AppInner {
         }
     }
 }
+
+/// Lines shown above the stanza in the detail pane with the context of `message` that the
+/// stanza doesn't show, followed by a rule `width` columns wide.
+fn metadata_header(message: &LogMessage, width: usize) -> Vec<Line<'static>> {
+    let mut lines = message_context(message);
+    lines.push(Line::styled(
+        "─".repeat(width),
+        Style::default().fg(Color::DarkGray),
+    ));
+    lines
+}
+
+/// Lines with the timestamp, level, target, direction, spans and stanza hash of `message`.
+fn message_context(message: &LogMessage) -> Vec<Line<'static>> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut first = vec![
+        Span::styled(message.timestamp.format("%F %T%.f UTC").to_string(), dim),
+        Span::raw("  "),
+        Span::styled(
+            message.level.to_string(),
+            Style::default()
+                .fg(level_color(message.level))
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::raw("  "),
+        Span::styled(message.target.clone(), Style::default().fg(Color::Cyan)),
+    ];
+    if let Some(direction) = &message.fields.direction {
+        let label = match direction {
+            StanzaDirection::In => "  ← received",
+            StanzaDirection::Out => "  → sent",
+        };
+        first.push(Span::styled(
+            label,
+            Style::default().fg(ListColors::Direction.color(message)),
+        ));
+    }
+
+    let spans = match (&message.spans, &message.span) {
+        (Some(spans), _) if !spans.is_empty() => spans
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>()
+            .join(" › "),
+        (_, Some(span)) => span.name.clone(),
+        _ => "no spans".to_string(),
+    };
+
+    let mut lines = vec![
+        Line::from(first),
+        Line::from(vec![Span::styled("spans  ", dim), Span::raw(spans)]),
+    ];
+    if let Some(hash) = message.stanza_hash_hex() {
+        lines.push(Line::from(vec![
+            Span::styled("hash   ", dim),
+            Span::raw(hash),
+        ]));
+    }
+    lines
+}
+
+fn lint_warning_lines(message: &LogMessage) -> Vec<Line<'static>> {
+    message
+        .lint_warnings
+        .iter()
+        .map(|warning| Line::styled(format!("⚠ {warning}"), Style::default().fg(Color::Red)))
+        .collect()
+}
+
+/// Returns the position of the first of `items`, starting at `start` and wrapping around,
+/// whose text starts with the lowercase `text`, or failing that, contains it.
+fn type_ahead_position<T>(
+    items: &[T],
+    start: Option<usize>,
+    text: &str,
+    item_text: impl Fn(&T) -> &str,
+) -> Option<usize> {
+    let start = start.unwrap_or(0).min(items.len());
+    let order = (start..items.len()).chain(0..start);
+    let lowercase = |i: &usize| item_text(&items[*i]).trim_start().to_lowercase();
+    order
+        .clone()
+        .find(|i| lowercase(i).starts_with(text))
+        .or_else(|| order.clone().find(|i| lowercase(i).contains(text)))
+}
+
+/// Applies `delta` to a pane size, keeping both panes of the split visible.
+fn resize_percent(percent: u16, delta: i16) -> u16 {
+    percent.saturating_add_signed(delta).clamp(10, 90)
+}
+
+/// The messages of `all_messages` in `tagged`, in log order, including those the filters hide.
+fn tagged_messages(all_messages: &[LogMessage], tagged: &HashSet<MessageId>) -> Vec<LogMessage> {
+    if tagged.is_empty() {
+        return vec![];
+    }
+    all_messages
+        .iter()
+        .filter(|m| tagged.contains(&m.id()))
+        .cloned()
+        .collect()
+}
+
+fn move_list_selection<T>(list: &mut StatefulList<T>, movement: Movement, page_size: usize) {
+    match movement {
+        Movement::PageUp | Movement::HalfPageUp => list.select_relative(-(page_size as isize)),
+        Movement::PageDown | Movement::HalfPageDown => list.select_relative(page_size as isize),
+        Movement::First => list.select_first(),
+        Movement::Last => list.select_last(),
+    }
+}
+
+/// What's needed to read and analyze only the lines appended to the log files since they
+/// were loaded.
+struct AppendState {
+    /// Where reading stopped in each file, or `None` if the files have to be reloaded whole.
+    positions: Option<Vec<ReadPosition>>,
+    linter: Linter,
+    pairing: ErrorPairing,
+    pings: PingPairing,
+    body_languages: Vec<String>,
+}
+
+impl AppendState {
+    fn new(body_languages: Vec<String>) -> Self {
+        AppendState {
+            positions: Some(vec![]),
+            linter: Linter::new(Duration::seconds(DEFAULT_DUPLICATE_WINDOW_SECS as i64)),
+            pairing: ErrorPairing::default(),
+            pings: PingPairing::default(),
+            body_languages,
+        }
+    }
+
+    /// Lints and pairs new messages, which must be in chronological order, and picks their
+    /// bodies.
+    fn analyze(&mut self, messages: &mut [LogMessage]) {
+        for message in messages {
+            message.lint_warnings = self.linter.check(message);
+            self.pairing.pair(message);
+            self.pings.pair(message);
+            message.preferred_body = message.body(&self.body_languages);
+        }
+    }
+}
+
+/// Reads and parses all `paths`, reporting progress in bytes. Returns early with the messages
+/// read so far if `progress` is cancelled.
+fn load_messages(
+    paths: &[PathBuf],
+    read_options: &ReadOptions,
+    body_languages: Vec<String>,
+    progress: &Progress,
+) -> Result<(Vec<LogMessage>, Vec<MalformedLine>, AppendState)> {
+    let total = paths
+        .iter()
+        .map(|p| LogSource::new(p).size().unwrap_or(0))
+        .sum();
+    progress.set_total(total);
+
+    let mut append_state = AppendState::new(body_languages);
+    let mut messages = vec![];
+    let mut malformed = vec![];
+    for (source, path) in paths.iter().enumerate() {
+        let log_source = LogSource {
+            options: read_options.clone(),
+            ..LogSource::new(path)
+        };
+        let (read, malformed_read, position) = log_source.read_all(|len| {
+            progress.advance(len);
+            !progress.is_cancelled()
+        })?;
+        messages.extend(read.into_iter().map(|mut message| {
+            message.source = source;
+            message
+        }));
+        malformed.extend(malformed_read.into_iter().map(|mut line| {
+            line.source = source;
+            line
+        }));
+        match (&mut append_state.positions, position) {
+            (Some(positions), Some(position)) => positions.push(position),
+            _ => append_state.positions = None,
+        }
+        if progress.is_cancelled() {
+            return Ok((messages, malformed, append_state));
+        }
+    }
+    // Merge the files chronologically, keeping file order for equal timestamps.
+    if paths.len() > 1 {
+        messages.sort_by_key(LogMessage::chronological_key);
+    }
+
+    append_state.analyze(&mut messages);
+    Ok((messages, malformed, append_state))
+}
+
+/// Reads the lines appended to `paths` since they were last read, returning their messages
+/// and the lines that failed to parse. Returns `None` if a file can't be read incrementally
+/// and all have to be reloaded. Nothing is consumed if reading any of the files fails.
+fn load_appended_messages(
+    paths: &[PathBuf],
+    read_options: &ReadOptions,
+    append_state: &mut AppendState,
+) -> Result<Option<(Vec<LogMessage>, Vec<MalformedLine>)>> {
+    let Some(positions) = &mut append_state.positions else {
+        return Ok(None);
+    };
+
+    let mut messages = vec![];
+    let mut malformed = vec![];
+    let mut new_positions = Vec::with_capacity(positions.len());
+    for (source, (path, position)) in paths.iter().zip(positions.iter()).enumerate() {
+        let log_source = LogSource {
+            options: read_options.clone(),
+            ..LogSource::new(path)
+        };
+        let Some((read, malformed_read, new_position)) = log_source.read_appended(position)? else {
+            return Ok(None);
+        };
+        new_positions.push(new_position);
+        messages.extend(read.into_iter().map(|mut message| {
+            message.source = source;
+            message
+        }));
+        malformed.extend(malformed_read.into_iter().map(|mut line| {
+            line.source = source;
+            line
+        }));
+    }
+    *positions = new_positions;
+    if paths.len() > 1 {
+        messages.sort_by_key(LogMessage::chronological_key);
+    }
+
+    append_state.analyze(&mut messages);
+    Ok(Some((messages, malformed)))
+}
+
+/// Merges `batch` into `messages`, both in chronological order. Appended messages are usually
+/// newer than all the others, so only the messages after the oldest of the batch are moved.
+fn merge_chronologically(messages: &mut Vec<LogMessage>, batch: Vec<LogMessage>) {
+    let Some(oldest) = batch.first().map(LogMessage::chronological_key) else {
+        return;
+    };
+    let start = messages.partition_point(|m| m.chronological_key() < oldest);
+    let mut newer = messages.split_off(start).into_iter().peekable();
+    let mut batch = batch.into_iter().peekable();
+    messages.reserve(newer.len() + batch.len());
+    loop {
+        let next = match (newer.peek(), batch.peek()) {
+            (Some(a), Some(b)) if a.chronological_key() <= b.chronological_key() => newer.next(),
+            (_, Some(_)) => batch.next(),
+            (Some(_), None) => newer.next(),
+            (None, None) => break,
+        };
+        messages.extend(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(source: usize, sequence: usize, seconds: u32) -> LogMessage {
+        let timestamp = format!("2024-01-01T00:00:{seconds:02}Z");
+        LogMessage {
+            source,
+            sequence,
+            ..LogMessage::for_test(&timestamp, Level::Info, None, "")
+        }
+    }
+
+    #[test]
+    fn appended_messages_are_merged_in_chronological_order() {
+        let mut messages = vec![message(0, 1, 1), message(1, 1, 2), message(0, 2, 5)];
+        let batch = vec![message(1, 2, 3), message(0, 3, 5), message(1, 3, 6)];
+        merge_chronologically(&mut messages, batch);
+        let ids = messages.iter().map(LogMessage::id).collect::<Vec<_>>();
+        assert_eq!(ids, [(0, 1), (1, 1), (1, 2), (0, 2), (0, 3), (1, 3)]);
+
+        merge_chronologically(&mut messages, vec![]);
+        assert_eq!(messages.len(), 6);
+    }
+}
@@ -0,0 +1,163 @@
+use std::any::Any;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+
+/// Tasks that finish faster than this never show a progress bar.
+const PROGRESS_DELAY: Duration = Duration::from_secs(1);
+
+/// Progress shared between a background task and the UI. Doubles as the task's cancellation
+/// token.
+#[derive(Debug, Default)]
+pub struct Progress {
+    done: AtomicU64,
+    total: AtomicU64,
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+    failure: Mutex<Option<String>>,
+}
+
+impl Progress {
+    pub fn set_total(&self, total: u64) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, amount: u64) {
+        self.done.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    pub fn ratio(&self) -> f64 {
+        let total = self.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.0;
+        }
+        (self.done.load(Ordering::Relaxed) as f64 / total as f64).min(1.0)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn finish(&self, failure: Option<String>) {
+        *self.failure.lock() = failure;
+        self.finished.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    /// Returns the error or panic message if the task failed.
+    pub fn failure(&self) -> Option<String> {
+        self.failure.lock().clone()
+    }
+}
+
+pub struct BackgroundTask {
+    pub title: String,
+    pub progress: Arc<Progress>,
+    started_at: Instant,
+}
+
+impl BackgroundTask {
+    pub fn shows_progress(&self) -> bool {
+        !self.progress.is_finished() && self.started_at.elapsed() >= PROGRESS_DELAY
+    }
+}
+
+/// Owns all background work of the UI.
+///
+/// Each task runs on its own thread with its own cancellation token. Errors and panics are
+/// caught and kept as the task's failure, so a failing task never takes down the interface.
+#[derive(Clone, Default)]
+pub struct TaskManager {
+    tasks: Arc<Mutex<Vec<BackgroundTask>>>,
+}
+
+impl TaskManager {
+    /// Runs `work` on a separate thread. `work` should report its progress and check for
+    /// cancellation regularly.
+    pub fn spawn<F>(&self, title: impl Into<String>, work: F)
+    where
+        F: FnOnce(&Progress) -> Result<()> + Send + 'static,
+    {
+        let progress = Arc::new(Progress::default());
+
+        let task_progress = progress.clone();
+        std::thread::spawn(move || {
+            let failure = match catch_unwind(AssertUnwindSafe(|| work(&task_progress))) {
+                Ok(Ok(())) => None,
+                Ok(Err(err)) => Some(format!("{err:#}")),
+                Err(panic) => Some(panic_message(panic.as_ref())),
+            };
+            task_progress.finish(failure);
+        });
+
+        self.tasks.lock().push(BackgroundTask {
+            title: title.into(),
+            progress,
+            started_at: Instant::now(),
+        });
+    }
+
+    /// Returns true while tasks are running or have finished without being collected yet.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.lock().is_empty()
+    }
+
+    /// Whether a task titled `title` is still running.
+    pub fn is_running(&self, title: &str) -> bool {
+        self.tasks
+            .lock()
+            .iter()
+            .any(|t| t.title == title && !t.progress.is_finished())
+    }
+
+    pub fn cancel_all(&self) {
+        for task in self.tasks.lock().iter() {
+            task.progress.cancel()
+        }
+    }
+
+    /// Returns the tasks that have been running long enough to show their progress.
+    pub fn visible_tasks(&self) -> Vec<(String, f64)> {
+        self.tasks
+            .lock()
+            .iter()
+            .filter(|t| t.shows_progress())
+            .map(|t| (t.title.clone(), t.progress.ratio()))
+            .collect()
+    }
+
+    /// Removes finished tasks and returns the failures among them as `(title, message)`.
+    pub fn collect_finished(&self) -> Vec<(String, String)> {
+        let mut failures = vec![];
+        self.tasks.lock().retain(|t| {
+            if !t.progress.is_finished() {
+                return true;
+            }
+            if let Some(failure) = t.progress.failure() {
+                failures.push((t.title.clone(), failure));
+            }
+            false
+        });
+        failures
+    }
+}
+
+/// Returns the message a thread panicked with, from the payload the panic was caught with.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Task panicked".to_string())
+}
@@ -0,0 +1,91 @@
+use std::panic::catch_unwind;
+
+use clipboard::{ClipboardContext, ClipboardProvider};
+use ratatui::prelude::{Color, Line};
+
+/// Which optional facilities the environment provides. Probed once at startup, so that the
+/// features relying on a missing one are turned off or fall back instead of failing each time
+/// they're used.
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    /// Whether the system clipboard can be reached. Copying asks the terminal to set it with
+    /// OSC 52 otherwise.
+    pub clipboard: bool,
+    /// Whether the terminal shows 24-bit colors. Syntax highlighting is reduced to the
+    /// 256-color palette otherwise.
+    pub truecolor: bool,
+    /// Whether the terminal reports mouse clicks.
+    pub mouse: bool,
+    /// Whether all log files are watched for appended lines.
+    pub file_watching: bool,
+    /// What's missing and how the app copes, listed in the help overlay.
+    pub notes: Vec<String>,
+}
+
+impl Default for Capabilities {
+    /// Assumes everything is available, until detected otherwise.
+    fn default() -> Self {
+        Capabilities {
+            clipboard: true,
+            truecolor: true,
+            mouse: true,
+            file_watching: true,
+            notes: vec![],
+        }
+    }
+}
+
+impl Capabilities {
+    /// Probes the clipboard and the color support of the terminal. Mouse support and file
+    /// watching are only known once they're set up, and recorded with [`Capabilities::note`].
+    pub fn detect() -> Self {
+        let mut capabilities = Capabilities::default();
+
+        // Some clipboard backends panic instead of failing without a display server
+        let clipboard = catch_unwind(|| ClipboardContext::new().is_ok()).unwrap_or(false);
+        if !clipboard {
+            capabilities.clipboard = false;
+            capabilities.note("No system clipboard: copying asks the terminal to set it (OSC 52)");
+        }
+
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let truecolor = matches!(colorterm.as_str(), "truecolor" | "24bit")
+            || std::env::var_os("WT_SESSION").is_some();
+        if !truecolor {
+            capabilities.truecolor = false;
+            capabilities.note("No 24-bit color: syntax highlighting uses 256 colors");
+        }
+
+        capabilities
+    }
+
+    pub fn note(&mut self, note: impl Into<String>) {
+        self.notes.push(note.into());
+    }
+}
+
+/// Replaces the 24-bit colors of `lines` with the closest colors of the 256-color palette.
+pub fn reduce_to_256_colors(lines: &mut [Line<'static>]) {
+    for span in lines.iter_mut().flat_map(|line| line.spans.iter_mut()) {
+        if let Some(Color::Rgb(r, g, b)) = span.style.fg {
+            span.style.fg = Some(Color::Indexed(ansi_256(r, g, b)));
+        }
+        if let Some(Color::Rgb(r, g, b)) = span.style.bg {
+            span.style.bg = Some(Color::Indexed(ansi_256(r, g, b)));
+        }
+    }
+}
+
+/// Index of the closest color in the 6×6×6 cube of the 256-color palette, or in its grayscale
+/// ramp for grays.
+fn ansi_256(r: u8, g: u8, b: u8) -> u8 {
+    if r == g && g == b {
+        return match r {
+            0..=7 => 16,
+            249..=255 => 231,
+            gray => 232 + ((gray as u16 - 8) * 24 / 241) as u8,
+        };
+    }
+    let level = |c: u8| ((c as u16 * 5 + 127) / 255) as u8;
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
@@ -0,0 +1,54 @@
+use std::io::Write;
+
+use anyhow::{format_err, Result};
+use clipboard::{ClipboardContext, ClipboardProvider};
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Where [`copy_to_clipboard`] sent the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardTarget {
+    /// The system clipboard, which was set.
+    System,
+    /// The terminal, asked to set the clipboard with OSC 52. Terminals that don't support it
+    /// ignore the request, which can't be detected.
+    Terminal,
+}
+
+/// Copies `text` to the system clipboard if `native` is set. Where there's no clipboard the
+/// process can reach, e.g. over SSH or without an X server, falls back to asking the terminal
+/// to set it with an OSC 52 sequence, which Windows Terminal and most modern terminals support.
+pub fn copy_to_clipboard(text: &str, native: bool) -> Result<ClipboardTarget> {
+    if native {
+        let copied = ClipboardContext::new()
+            .and_then(|mut ctx: ClipboardContext| ctx.set_contents(text.to_string()));
+        if copied.is_ok() {
+            return Ok(ClipboardTarget::System);
+        }
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))
+        .and_then(|_| stdout.flush())
+        .map_err(|err| format_err!("Failed to copy to the clipboard: {err}"))?;
+    Ok(ClipboardTarget::Terminal)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
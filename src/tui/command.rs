@@ -0,0 +1,216 @@
+use std::path::Path;
+
+use anyhow::{format_err, Result};
+use chrono::{NaiveDateTime, NaiveTime};
+
+use crate::export::ExportFormat;
+use crate::log_message::{Level, StanzaDirection, StanzaKind};
+use crate::tui::filter::Filter;
+use crate::tui::search::SearchQuery;
+
+/// A command typed in the `:` command line, for operations that don't have their own key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `:filter level>=warn`, `:filter direction=in`, `:filter kind=iq`, `:filter hash=3fa2`
+    /// or `:filter clear`.
+    Filter(FilterCommand),
+    /// `:goto 14:32` or `:goto 2024-01-31 14:32:05`. Selects the first message at or after
+    /// the time.
+    Goto(GotoTarget),
+    /// `:export out.xml`. Exports the filtered view in the format matching the extension.
+    Export { path: String, format: ExportFormat },
+    /// `:range 14:30..14:32`, `:range 14:30..`, `:range ..14:32` or `:range clear`. Shows
+    /// only the messages within the range, bounds included.
+    Range {
+        since: Option<GotoTarget>,
+        until: Option<GotoTarget>,
+    },
+    /// `:theme solarized`. Switches to the first theme whose name starts with the text.
+    Theme(String),
+    /// `:pin errors level>=error`. Counts the messages matching the query in the bottom bar.
+    Pin { name: String, query: String },
+    /// `:unpin errors`.
+    Unpin(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterCommand {
+    /// Shows only the messages of these levels.
+    Levels(Vec<Level>),
+    Direction(Option<StanzaDirection>),
+    Kind(Option<StanzaKind>),
+    /// Shows only the stanzas whose hash starts with these hex digits.
+    Hash(Option<String>),
+    /// Resets the levels, direction, kind, hash, search and similar stanzas filters.
+    Clear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GotoTarget {
+    /// A time of day, on the day of the selected message.
+    Time(NaiveTime),
+    DateTime(NaiveDateTime),
+}
+
+const USAGE: &str = "Commands: filter, goto, range, export, theme, pin, unpin";
+
+/// Parses the text typed after `:`.
+pub fn parse_command(input: &str) -> Result<Command> {
+    let input = input.trim();
+    let (name, argument) = input.split_once(' ').unwrap_or((input, ""));
+    let argument = argument.trim();
+    if name.is_empty() {
+        return Err(format_err!("{USAGE}"));
+    }
+    if argument.is_empty() {
+        return Err(format_err!("`{name}` needs an argument"));
+    }
+
+    match name {
+        "filter" => parse_filter(argument).map(Command::Filter),
+        "goto" => parse_goto(argument).map(Command::Goto),
+        "range" if argument == "clear" => Ok(Command::Range {
+            since: None,
+            until: None,
+        }),
+        "range" => {
+            let (since, until) = argument
+                .split_once("..")
+                .ok_or_else(|| format_err!("Expected a range like `14:30..14:32`"))?;
+            let bound = |bound: &str| match bound.trim() {
+                "" => Ok(None),
+                bound => parse_goto(bound).map(Some),
+            };
+            let (since, until) = (bound(since)?, bound(until)?);
+            if since.is_none() && until.is_none() {
+                return Err(format_err!("Expected a start or an end, like `14:30..`"));
+            }
+            Ok(Command::Range { since, until })
+        }
+        "export" => {
+            let extension = Path::new(argument)
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let format = ExportFormat::ALL
+                .into_iter()
+                .find(|f| f.extension() == extension)
+                .ok_or_else(|| format_err!("Unknown extension, use .xml, .html or .ndjson"))?;
+            Ok(Command::Export {
+                path: argument.to_string(),
+                format,
+            })
+        }
+        "theme" => Ok(Command::Theme(argument.to_string())),
+        "pin" => {
+            let (name, query) = argument
+                .split_once(' ')
+                .ok_or_else(|| format_err!("Expected `pin NAME QUERY`"))?;
+            parse_query(query)?;
+            Ok(Command::Pin {
+                name: name.to_string(),
+                query: query.trim().to_string(),
+            })
+        }
+        "unpin" => Ok(Command::Unpin(argument.to_string())),
+        _ => Err(format_err!("Unknown command `{name}`. {USAGE}")),
+    }
+}
+
+/// Parses a query of `:filter` conditions and text to search for, separated by spaces, e.g.
+/// `level>=warn kind=iq timeout`. All conditions must hold, and the words that aren't
+/// conditions are searched for together as one text.
+pub fn parse_query(query: &str) -> Result<Filter> {
+    let mut filter = Filter::default();
+    let mut words = vec![];
+    for term in query.split_whitespace() {
+        let is_condition = ["level", "direction", "kind", "hash"]
+            .into_iter()
+            .any(|key| {
+                term.starts_with(key) && term[key.len()..].starts_with(['<', '>', '=', '!'])
+            });
+        match is_condition {
+            true => filter.apply(parse_filter(term)?),
+            false => words.push(term),
+        }
+    }
+    filter.search = SearchQuery::text(words.join(" "));
+    Ok(filter)
+}
+
+/// Parses `clear` or a `key<op>value` condition, where only levels support `<`, `<=`, `>`,
+/// `>=` and `!=` in addition to `=`.
+fn parse_filter(argument: &str) -> Result<FilterCommand> {
+    if argument == "clear" {
+        return Ok(FilterCommand::Clear);
+    }
+    let start = argument
+        .find(['<', '>', '=', '!'])
+        .ok_or_else(|| format_err!("Expected a condition like `level>=warn`"))?;
+    let rest = &argument[start..];
+    let operator = ["<=", ">=", "!=", "<", ">", "="]
+        .into_iter()
+        .find(|op| rest.starts_with(op))
+        .ok_or_else(|| format_err!("Unknown operator in `{argument}`"))?;
+    let key = argument[..start].trim();
+    let value = rest[operator.len()..].trim().to_lowercase();
+
+    if key == "level" {
+        let level = Level::ALL
+            .into_iter()
+            .find(|l| l.to_string().eq_ignore_ascii_case(&value))
+            .ok_or_else(|| format_err!("Unknown level `{value}`"))?;
+        let levels = Level::ALL
+            .into_iter()
+            .filter(|l| match operator {
+                "<=" => *l <= level,
+                ">=" => *l >= level,
+                "!=" => *l != level,
+                "<" => *l < level,
+                ">" => *l > level,
+                _ => *l == level,
+            })
+            .collect();
+        return Ok(FilterCommand::Levels(levels));
+    }
+
+    if operator != "=" {
+        return Err(format_err!("`{key}` can only be compared with `=`"));
+    }
+    match (key, value.as_str()) {
+        ("direction", "in") => Ok(FilterCommand::Direction(Some(StanzaDirection::In))),
+        ("direction", "out") => Ok(FilterCommand::Direction(Some(StanzaDirection::Out))),
+        ("direction", "all") => Ok(FilterCommand::Direction(None)),
+        ("direction", _) => Err(format_err!("Expected `in`, `out` or `all`")),
+        ("kind", "iq") => Ok(FilterCommand::Kind(Some(StanzaKind::Iq))),
+        ("kind", "message") => Ok(FilterCommand::Kind(Some(StanzaKind::Message))),
+        ("kind", "presence") => Ok(FilterCommand::Kind(Some(StanzaKind::Presence))),
+        ("kind", "all") => Ok(FilterCommand::Kind(None)),
+        ("kind", _) => Err(format_err!("Expected `iq`, `message`, `presence` or `all`")),
+        ("hash", "all") => Ok(FilterCommand::Hash(None)),
+        ("hash", hash) if !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Ok(FilterCommand::Hash(Some(hash.to_string())))
+        }
+        ("hash", _) => Err(format_err!("Expected hex digits of a stanza hash or `all`")),
+        _ => Err(format_err!(
+            "Unknown filter `{key}`, use level, direction, kind or hash"
+        )),
+    }
+}
+
+/// Parses `HH:MM`, `HH:MM:SS` or either preceded by a `YYYY-MM-DD` date.
+fn parse_goto(argument: &str) -> Result<GotoTarget> {
+    let argument = argument.replacen('T', " ", 1);
+    for format in ["%H:%M:%S%.f", "%H:%M"] {
+        if let Ok(time) = NaiveTime::parse_from_str(&argument, format) {
+            return Ok(GotoTarget::Time(time));
+        }
+        let format = format!("%Y-%m-%d {format}");
+        if let Ok(date_time) = NaiveDateTime::parse_from_str(&argument, &format) {
+            return Ok(GotoTarget::DateTime(date_time));
+        }
+    }
+    Err(format_err!(
+        "Expected a time like `14:32` or `2024-01-31 14:32:05`"
+    ))
+}
@@ -0,0 +1,137 @@
+use ratatui::prelude::{Color, Line, Span, Style};
+
+/// How a diff is laid out in the detail pane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffMode {
+    Unified,
+    SideBySide,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Renders the line diff from `old` to `new` in the given layout.
+pub fn render_diff(old: &str, new: &str, mode: DiffMode) -> Vec<Line<'static>> {
+    let diff = diff_lines(old, new);
+    match mode {
+        DiffMode::Unified => unified(&diff),
+        DiffMode::SideBySide => side_by_side(&diff),
+    }
+}
+
+/// Computes a line diff from the longest common subsequence of lines. Stanzas are short, so
+/// the quadratic table is fine.
+fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // lengths[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            diff.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().map(|line| DiffLine::Removed(line)));
+    diff.extend(new[j..].iter().map(|line| DiffLine::Added(line)));
+    diff
+}
+
+fn unified(diff: &[DiffLine]) -> Vec<Line<'static>> {
+    diff.iter()
+        .map(|line| match line {
+            DiffLine::Same(text) => Line::from(format!("  {text}")),
+            DiffLine::Removed(text) => {
+                Line::styled(format!("- {text}"), Style::default().fg(Color::Red))
+            }
+            DiffLine::Added(text) => {
+                Line::styled(format!("+ {text}"), Style::default().fg(Color::Green))
+            }
+        })
+        .collect()
+}
+
+/// Lays out the old lines on the left and the new lines on the right, pairing up runs of
+/// removed and added lines.
+fn side_by_side(diff: &[DiffLine]) -> Vec<Line<'static>> {
+    let width = diff
+        .iter()
+        .filter_map(|line| match line {
+            DiffLine::Same(text) | DiffLine::Removed(text) => Some(text.chars().count()),
+            DiffLine::Added(_) => None,
+        })
+        .max()
+        .unwrap_or_default();
+
+    let mut rows: Vec<(Option<DiffLine>, Option<DiffLine>)> = vec![];
+    let mut removed = vec![];
+    let mut added = vec![];
+    for line in diff {
+        match line {
+            DiffLine::Same(_) => {
+                pair_up(&mut rows, &mut removed, &mut added);
+                rows.push((Some(*line), Some(*line)));
+            }
+            DiffLine::Removed(_) => removed.push(*line),
+            DiffLine::Added(_) => added.push(*line),
+        }
+    }
+    pair_up(&mut rows, &mut removed, &mut added);
+
+    let cell = |line: Option<DiffLine>, width: usize| match line {
+        Some(DiffLine::Same(text)) => Span::raw(format!("{text:width$}")),
+        Some(DiffLine::Removed(text)) => {
+            Span::styled(format!("{text:width$}"), Style::default().fg(Color::Red))
+        }
+        Some(DiffLine::Added(text)) => {
+            Span::styled(format!("{text:width$}"), Style::default().fg(Color::Green))
+        }
+        None => Span::raw(" ".repeat(width)),
+    };
+    rows.into_iter()
+        .map(|(left, right)| {
+            Line::from(vec![
+                cell(left, width),
+                Span::styled(" │ ", Style::default().fg(Color::DarkGray)),
+                cell(right, 0),
+            ])
+        })
+        .collect()
+}
+
+/// Moves a run of removed and added lines into `rows`, side by side.
+fn pair_up<'a>(
+    rows: &mut Vec<(Option<DiffLine<'a>>, Option<DiffLine<'a>>)>,
+    removed: &mut Vec<DiffLine<'a>>,
+    added: &mut Vec<DiffLine<'a>>,
+) {
+    for i in 0..removed.len().max(added.len()) {
+        rows.push((removed.get(i).copied(), added.get(i).copied()));
+    }
+    removed.clear();
+    added.clear();
+}
@@ -0,0 +1,265 @@
+use std::path::{Path, PathBuf};
+
+use crossterm::event::KeyCode;
+use ratatui::backend::Backend;
+use ratatui::layout::Rect;
+use ratatui::prelude::{Color, Line, Modifier, Span, Style};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use ratatui::Frame;
+
+use crate::export::ExportFormat;
+use crate::tui::popup::centered_rect;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ExportScope {
+    Selection,
+    /// The messages tagged with space, in log order.
+    Tagged,
+    #[default]
+    Filter,
+    All,
+}
+
+impl ExportScope {
+    const ALL: [ExportScope; 4] = [
+        ExportScope::Selection,
+        ExportScope::Tagged,
+        ExportScope::Filter,
+        ExportScope::All,
+    ];
+
+    fn title(&self) -> &'static str {
+        match self {
+            ExportScope::Selection => "Selected message",
+            ExportScope::Tagged => "Tagged messages",
+            ExportScope::Filter => "Filtered view",
+            ExportScope::All => "All messages",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Format,
+    Scope,
+    Redact,
+    Path,
+}
+
+impl Field {
+    const ALL: [Field; 4] = [Field::Format, Field::Scope, Field::Redact, Field::Path];
+}
+
+pub enum ExportDialogEvent {
+    Pending,
+    Closed,
+    Submitted,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportDialog {
+    pub format: ExportFormat,
+    pub scope: ExportScope,
+    pub redact: bool,
+    pub path: String,
+    /// Outcome of the last export, either a success message or an error.
+    pub status: Option<Result<String, String>>,
+    field: Field,
+}
+
+impl ExportDialog {
+    /// Creates the dialog, exporting the tagged messages by default if there are any.
+    pub fn new(redact: bool, has_tagged: bool) -> Self {
+        let format = ExportFormat::default();
+        ExportDialog {
+            format,
+            scope: match has_tagged {
+                true => ExportScope::Tagged,
+                false => Default::default(),
+            },
+            redact,
+            path: format!("export.{}", format.extension()),
+            status: None,
+            field: Field::Path,
+        }
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> ExportDialogEvent {
+        match code {
+            KeyCode::Esc => return ExportDialogEvent::Closed,
+            KeyCode::Enter => return ExportDialogEvent::Submitted,
+            KeyCode::Up => self.field = cycle(&Field::ALL, self.field, false),
+            KeyCode::Down => self.field = cycle(&Field::ALL, self.field, true),
+            code => match self.field {
+                Field::Format => match code {
+                    KeyCode::Left | KeyCode::Right | KeyCode::Char(' ') => {
+                        let format = cycle(&ExportFormat::ALL, self.format, code != KeyCode::Left);
+                        self.set_format(format);
+                    }
+                    _ => (),
+                },
+                Field::Scope => match code {
+                    KeyCode::Left | KeyCode::Right | KeyCode::Char(' ') => {
+                        self.scope = cycle(&ExportScope::ALL, self.scope, code != KeyCode::Left)
+                    }
+                    _ => (),
+                },
+                Field::Redact => match code {
+                    KeyCode::Left | KeyCode::Right | KeyCode::Char(' ') => {
+                        self.redact = !self.redact
+                    }
+                    _ => (),
+                },
+                Field::Path => match code {
+                    KeyCode::Char(c) => self.path.push(c),
+                    KeyCode::Backspace => {
+                        self.path.pop();
+                    }
+                    KeyCode::Tab => self.path = complete_path(&self.path),
+                    _ => (),
+                },
+            },
+        }
+        ExportDialogEvent::Pending
+    }
+
+    fn set_format(&mut self, format: ExportFormat) {
+        // Keep the file extension in sync unless the user chose a custom one
+        let path = Path::new(&self.path);
+        if path
+            .extension()
+            .map(|ext| ext == self.format.extension())
+            .unwrap_or(false)
+        {
+            self.path = path
+                .with_extension(format.extension())
+                .to_string_lossy()
+                .into_owned();
+        }
+        self.format = format;
+    }
+
+    pub fn render<B: Backend>(&self, f: &mut Frame<B>, rect: Rect) {
+        let field_style = |field: Field| {
+            if self.field == field {
+                Style::default().fg(Color::Black).bg(Color::LightYellow)
+            } else {
+                Style::default()
+            }
+        };
+
+        let mut lines = vec![
+            Line::from(vec![
+                Span::raw(" Format:  "),
+                Span::styled(
+                    format!("< {} >", self.format.title()),
+                    field_style(Field::Format),
+                ),
+            ]),
+            Line::from(vec![
+                Span::raw(" Scope:   "),
+                Span::styled(
+                    format!("< {} >", self.scope.title()),
+                    field_style(Field::Scope),
+                ),
+            ]),
+            Line::from(vec![
+                Span::raw(" Redact:  "),
+                Span::styled(
+                    if self.redact { "[x]" } else { "[ ]" },
+                    field_style(Field::Redact),
+                ),
+            ]),
+            Line::from(vec![
+                Span::raw(" Path:    "),
+                Span::styled(format!("{} ", self.path), field_style(Field::Path)),
+            ]),
+            Line::from(""),
+            Line::styled(
+                " ↑/↓ Field  ←/→ Change  Tab Complete path  Enter Export  Esc Close",
+                Style::default().fg(Color::Gray),
+            ),
+        ];
+
+        match &self.status {
+            Some(Ok(message)) => {
+                lines.push(Line::styled(
+                    format!(" {message}"),
+                    Style::default().fg(Color::Green),
+                ));
+            }
+            Some(Err(message)) => {
+                lines.push(Line::styled(
+                    format!(" {message}"),
+                    Style::default().fg(Color::Red),
+                ));
+            }
+            None => (),
+        }
+
+        let rect = centered_rect(72, lines.len() as u16 + 2, rect);
+        let paragraph = Paragraph::new(lines)
+            .style(Style::default().fg(Color::White))
+            .block(Block::default().borders(Borders::ALL).title(Span::styled(
+                "Export (x)",
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+
+        f.render_widget(Clear, rect);
+        f.render_widget(paragraph, rect);
+    }
+}
+
+fn cycle<T: Copy + PartialEq>(values: &[T], current: T, forward: bool) -> T {
+    let idx = values.iter().position(|v| *v == current).unwrap_or(0);
+    let len = values.len();
+    if forward {
+        values[(idx + 1) % len]
+    } else {
+        values[(idx + len - 1) % len]
+    }
+}
+
+/// Completes `input` to the longest prefix shared by all matching directory entries,
+/// appending a `/` if the completion is an unambiguous directory.
+pub fn complete_path(input: &str) -> String {
+    let (dir, prefix) = match input.rfind('/') {
+        Some(idx) => (&input[..=idx], &input[idx + 1..]),
+        None => ("", input),
+    };
+    let search_dir = if dir.is_empty() {
+        PathBuf::from(".")
+    } else {
+        PathBuf::from(dir)
+    };
+
+    let Ok(entries) = std::fs::read_dir(&search_dir) else {
+        return input.to_string();
+    };
+
+    let candidates = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            name.starts_with(prefix).then(|| (name, e.path().is_dir()))
+        })
+        .collect::<Vec<_>>();
+
+    match candidates.as_slice() {
+        [] => input.to_string(),
+        [(name, is_dir)] => format!("{dir}{name}{}", if *is_dir { "/" } else { "" }),
+        [(first, _), rest @ ..] => {
+            let common = rest.iter().fold(first.as_str(), |common, (name, _)| {
+                let len = common
+                    .char_indices()
+                    .zip(name.chars())
+                    .take_while(|((_, a), b)| a == b)
+                    .last()
+                    .map(|((idx, c), _)| idx + c.len_utf8())
+                    .unwrap_or(0);
+                &common[..len]
+            });
+            format!("{dir}{common}")
+        }
+    }
+}
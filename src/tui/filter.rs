@@ -0,0 +1,285 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+
+use crate::log_message::{Level, LogMessage, StanzaDirection, StanzaKind};
+use crate::reconnect::Storm;
+use crate::tui::command::FilterCommand;
+use crate::tui::search::SearchQuery;
+use crate::tui::similar::SimilarStanzas;
+
+/// Everything narrowing down the message list. Each part is optional and they all apply at
+/// once, so filters stack instead of replacing each other.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Index and name of the account whose file messages must come from.
+    pub account: Option<(usize, String)>,
+    pub span: Option<String>,
+    /// Reconnect storm whose messages are shown, selected in place of a span.
+    pub storm: Option<Storm>,
+    pub target: Option<String>,
+    pub excluded_levels: HashSet<Level>,
+    pub direction: Option<StanzaDirection>,
+    pub kind: Option<StanzaKind>,
+    /// Leading hex digits of the hash of the stanzas shown.
+    pub hash: Option<String>,
+    /// Whether keepalive pings and the replies to them are hidden.
+    pub hide_keepalives: bool,
+    pub search: Option<SearchQuery>,
+    /// Stanzas similar to the one "find similar" was used on.
+    pub similar: Option<SimilarStanzas>,
+    /// Earliest timestamp of the messages shown.
+    pub since: Option<DateTime<Utc>>,
+    /// Latest timestamp of the messages shown, inclusive.
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Format of the bounds of the time range in descriptions.
+const TIME_FORMAT: &str = "%H:%M:%S%.3f";
+
+/// How a filter judged a message: the filter's description, the value of the message it
+/// looks at if it's a single one, and whether the message passes.
+pub type Verdict = (String, Option<String>, bool);
+
+impl Filter {
+    /// Applies a `:filter` command, replacing the part of the filter it sets.
+    pub fn apply(&mut self, command: FilterCommand) {
+        match command {
+            FilterCommand::Levels(levels) => {
+                self.excluded_levels = Level::ALL
+                    .into_iter()
+                    .filter(|l| !levels.contains(l))
+                    .collect();
+            }
+            FilterCommand::Direction(direction) => self.direction = direction,
+            FilterCommand::Kind(kind) => self.kind = kind,
+            FilterCommand::Hash(hash) => self.hash = hash,
+            FilterCommand::Clear => {
+                self.excluded_levels.clear();
+                self.direction = None;
+                self.kind = None;
+                self.hash = None;
+                self.search = None;
+                self.similar = None;
+                self.since = None;
+                self.until = None;
+            }
+        }
+    }
+
+    pub fn matches(&self, message: &LogMessage) -> bool {
+        self.account_matches(message)
+            && self.span_matches(message)
+            && self.storm.as_ref().is_none_or(|s| s.contains(message))
+            && self.target_matches(message)
+            && !self.excluded_levels.contains(&message.level)
+            && self.time_matches(message)
+            && self.direction_matches(message)
+            && self.kind_matches(message)
+            && self.hash_matches(message)
+            && !(self.hide_keepalives && message.keepalive)
+            && self.search.as_ref().is_none_or(|s| s.matches(message))
+            && self
+                .similar
+                .as_ref()
+                .is_none_or(|s| s.score(message).is_some())
+    }
+
+    /// Describes the active filters, e.g. "span: connect".
+    pub fn descriptions(&self) -> Vec<String> {
+        self.stages(None)
+            .into_iter()
+            .map(|(description, ..)| description)
+            .collect()
+    }
+
+    /// Returns the active filters in the order they're applied, with how they judge
+    /// `message`.
+    pub fn explain(&self, message: &LogMessage) -> Vec<Verdict> {
+        self.stages(Some(message))
+    }
+
+    /// Returns the active filters, judging `message` if given. Without a message, verdicts
+    /// hold no values and pass.
+    fn stages(&self, message: Option<&LogMessage>) -> Vec<Verdict> {
+        let mut stages = vec![];
+        let judge = |value: &dyn Fn(&LogMessage) -> String,
+                     passes: &dyn Fn(&LogMessage) -> bool| {
+            (message.map(value), message.is_none_or(passes))
+        };
+
+        if let Some((_, name)) = &self.account {
+            let (value, passes) = judge(&|m| format!("file #{}", m.source + 1), &|m| {
+                self.account_matches(m)
+            });
+            stages.push((format!("account: {name}"), value, passes));
+        }
+
+        if let Some(span) = &self.span {
+            let chain = |m: &LogMessage| {
+                let names = span_names(m).collect::<Vec<_>>();
+                match names.is_empty() {
+                    true => "no spans".to_string(),
+                    false => names.join(" › "),
+                }
+            };
+            let (value, passes) = judge(&chain, &|m| self.span_matches(m));
+            stages.push((format!("span: {span}"), value, passes));
+        }
+
+        if let Some(storm) = &self.storm {
+            let (value, passes) = judge(&|m| m.timestamp.to_string(), &|m| storm.contains(m));
+            let description = format!("reconnect storm: {} to {}", storm.start, storm.end);
+            stages.push((description, value, passes));
+        }
+
+        if let Some(target) = &self.target {
+            let (value, passes) = judge(&|m| m.target.clone(), &|m| self.target_matches(m));
+            stages.push((format!("target: {target}"), value, passes));
+        }
+
+        if !self.excluded_levels.is_empty() {
+            let levels = Level::ALL
+                .iter()
+                .filter(|l| !self.excluded_levels.contains(l))
+                .map(|l| l.to_string())
+                .collect::<Vec<_>>();
+            let (value, passes) = judge(&|m| m.level.to_string(), &|m| {
+                !self.excluded_levels.contains(&m.level)
+            });
+            stages.push((format!("level: {}", levels.join("|")), value, passes));
+        }
+
+        if let Some(time_range) = self.time_range_description() {
+            let (value, passes) = judge(&|m| m.timestamp.format(TIME_FORMAT).to_string(), &|m| {
+                self.time_matches(m)
+            });
+            stages.push((format!("time: {time_range}"), value, passes));
+        }
+
+        if let Some(direction) = &self.direction {
+            let (value, passes) = judge(
+                &|m| {
+                    m.fields
+                        .direction
+                        .as_ref()
+                        .map_or("none", StanzaDirection::as_str)
+                        .to_string()
+                },
+                &|m| self.direction_matches(m),
+            );
+            let description = format!("direction: {}", direction.as_str());
+            stages.push((description, value, passes));
+        }
+
+        if let Some(kind) = self.kind {
+            let (value, passes) = judge(
+                &|m| {
+                    m.stanza_kind()
+                        .map_or("not a stanza".to_string(), |k| k.to_string())
+                },
+                &|m| self.kind_matches(m),
+            );
+            stages.push((format!("kind: {kind}"), value, passes));
+        }
+
+        if let Some(hash) = &self.hash {
+            let (value, passes) = judge(
+                &|m| {
+                    m.stanza_hash_hex()
+                        .unwrap_or_else(|| "not a stanza".to_string())
+                },
+                &|m| self.hash_matches(m),
+            );
+            stages.push((format!("hash: {hash}…"), value, passes));
+        }
+
+        if self.hide_keepalives {
+            let (value, passes) = judge(
+                &|m| match m.keepalive {
+                    true => "keepalive".to_string(),
+                    false => "not a keepalive".to_string(),
+                },
+                &|m| !m.keepalive,
+            );
+            stages.push(("keepalives hidden".to_string(), value, passes));
+        }
+
+        if let Some(search) = &self.search {
+            let passes = message.is_none_or(|m| search.matches(m));
+            stages.push((search.description(), None, passes));
+        }
+
+        if let Some(similar) = &self.similar {
+            let (value, passes) = judge(
+                &|m| match similar.score(m) {
+                    Some(score) => format!("{score}% similar"),
+                    None => "not similar".to_string(),
+                },
+                &|m| similar.score(m).is_some(),
+            );
+            let description = format!(
+                "similar to <{}> at {}",
+                similar.root,
+                similar.timestamp.format("%H:%M:%S%.3f")
+            );
+            stages.push((description, value, passes));
+        }
+
+        stages
+    }
+
+    /// Describes the time range, e.g. `14:30:00.000 – 14:32:00.000`, if one is set.
+    pub fn time_range_description(&self) -> Option<String> {
+        if self.since.is_none() && self.until.is_none() {
+            return None;
+        }
+        let format = |time: Option<DateTime<Utc>>| {
+            time.map_or("…".to_string(), |t| t.format(TIME_FORMAT).to_string())
+        };
+        Some(format!("{} – {}", format(self.since), format(self.until)))
+    }
+
+    fn time_matches(&self, message: &LogMessage) -> bool {
+        self.since.is_none_or(|since| message.timestamp >= since)
+            && self.until.is_none_or(|until| message.timestamp <= until)
+    }
+
+    fn account_matches(&self, message: &LogMessage) -> bool {
+        self.account
+            .as_ref()
+            .is_none_or(|(source, _)| message.source == *source)
+    }
+
+    fn span_matches(&self, message: &LogMessage) -> bool {
+        self.span
+            .as_ref()
+            .is_none_or(|span| span_names(message).any(|name| name == span))
+    }
+
+    fn target_matches(&self, message: &LogMessage) -> bool {
+        self.target
+            .as_ref()
+            .is_none_or(|target| &message.target == target)
+    }
+
+    fn direction_matches(&self, message: &LogMessage) -> bool {
+        self.direction.is_none() || message.fields.direction == self.direction
+    }
+
+    fn kind_matches(&self, message: &LogMessage) -> bool {
+        self.kind.is_none() || message.stanza_kind() == self.kind
+    }
+
+    fn hash_matches(&self, message: &LogMessage) -> bool {
+        self.hash.as_ref().is_none_or(|hash| {
+            message
+                .stanza_hash_hex()
+                .is_some_and(|h| h.starts_with(hash.as_str()))
+        })
+    }
+}
+
+fn span_names(message: &LogMessage) -> impl Iterator<Item = &str> {
+    message.spans.iter().flatten().map(|s| s.name.as_str())
+}
@@ -0,0 +1,145 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use ratatui::prelude::{Color, Line, Style};
+
+use crate::log_message::LogMessage;
+
+/// Messages further apart than this are in different sessions.
+const SESSION_GAP_MINUTES: i64 = 5;
+
+/// How the message list is divided under header rows, when it's sorted by time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Grouping {
+    #[default]
+    None,
+    /// By the hour the messages were logged in.
+    Hour,
+    /// By session: runs of messages without a pause of more than a few minutes.
+    Session,
+}
+
+impl Grouping {
+    pub fn next(self) -> Self {
+        match self {
+            Grouping::None => Grouping::Hour,
+            Grouping::Hour => Grouping::Session,
+            Grouping::Session => Grouping::None,
+        }
+    }
+
+    /// Returns the start of the group of each of `timestamps`, which are chronological: the
+    /// hour it was logged in, or the first message of its session.
+    fn group_starts(self, timestamps: &[DateTime<Utc>]) -> Vec<DateTime<Utc>> {
+        let mut starts = Vec::with_capacity(timestamps.len());
+        let mut previous: Option<DateTime<Utc>> = None;
+        for &timestamp in timestamps {
+            let start = match (self, previous, starts.last()) {
+                (Grouping::Hour, ..) => timestamp
+                    .duration_trunc(Duration::hours(1))
+                    .unwrap_or(timestamp),
+                (Grouping::Session, Some(previous), Some(&start))
+                    if timestamp - previous <= Duration::minutes(SESSION_GAP_MINUTES) =>
+                {
+                    start
+                }
+                _ => timestamp,
+            };
+            starts.push(start);
+            previous = Some(timestamp);
+        }
+        starts
+    }
+}
+
+impl fmt::Display for Grouping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Grouping::None => "no grouping",
+            Grouping::Hour => "hour",
+            Grouping::Session => "session",
+        })
+    }
+}
+
+/// The header row above a group of messages.
+#[derive(Debug, Clone)]
+pub struct GroupHeader {
+    /// Start of the group, which identifies it: the hour, or the first message of the
+    /// session.
+    pub start: DateTime<Utc>,
+    /// Timestamp of the last message of the group.
+    pub end: DateTime<Utc>,
+    pub len: usize,
+    /// Whether only the header is shown, in place of the first message of the group.
+    pub collapsed: bool,
+}
+
+impl GroupHeader {
+    pub fn line(&self, grouping: Grouping, width: usize) -> Line<'static> {
+        let marker = if self.collapsed { "▸" } else { "▾" };
+        let span = match grouping {
+            Grouping::Session => format!(
+                "session {} – {}",
+                self.start.format("%F %H:%M:%S"),
+                self.end.format("%H:%M:%S")
+            ),
+            _ => self.start.format("%F %H:00").to_string(),
+        };
+        let label = format!("{marker} {span} · {} messages ", self.len);
+        let fill = width.saturating_sub(label.chars().count());
+        Line::styled(
+            format!("{label}{}", "─".repeat(fill)),
+            Style::default().fg(Color::LightBlue),
+        )
+    }
+}
+
+/// Divides `messages`, sorted by time in either order, into groups. Removes the messages of
+/// the groups whose start is in `collapsed`, except for their first row, which shows the
+/// header instead. Returns the header of each group by the index of its first row.
+pub fn group_messages(
+    messages: &mut Vec<LogMessage>,
+    grouping: Grouping,
+    newest_first: bool,
+    collapsed: &HashSet<DateTime<Utc>>,
+) -> HashMap<usize, GroupHeader> {
+    let mut headers = HashMap::new();
+    if grouping == Grouping::None {
+        return headers;
+    }
+
+    let mut timestamps = messages.iter().map(|m| m.timestamp).collect::<Vec<_>>();
+    if newest_first {
+        timestamps.reverse();
+    }
+    let mut starts = grouping.group_starts(&timestamps);
+    if newest_first {
+        starts.reverse();
+    }
+
+    let mut grouped = Vec::with_capacity(messages.len());
+    let mut rows = std::mem::take(messages).into_iter().zip(starts).peekable();
+    while let Some((first, start)) = rows.next() {
+        let is_collapsed = collapsed.contains(&start);
+        let mut header = GroupHeader {
+            start,
+            end: first.timestamp,
+            len: 1,
+            collapsed: is_collapsed,
+        };
+        let first_idx = grouped.len();
+        grouped.push(first);
+        while let Some((message, _)) = rows.next_if(|(_, s)| *s == start) {
+            header.len += 1;
+            header.end = header.end.max(message.timestamp);
+            if !is_collapsed {
+                grouped.push(message);
+            }
+        }
+        headers.insert(first_idx, header);
+    }
+    *messages = grouped;
+    headers
+}
@@ -0,0 +1,130 @@
+use ratatui::backend::Backend;
+use ratatui::layout::Rect;
+use ratatui::prelude::{Color, Line, Modifier, Span, Style};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use ratatui::Frame;
+
+use crate::tui::app::ListColors;
+use crate::tui::keymap::{Action, Keymap};
+use crate::tui::popup::centered_rect;
+
+/// Keybindings grouped by section, as listed in the help overlay.
+#[derive(Debug, Clone)]
+pub struct Help {
+    sections: Vec<(&'static str, Vec<(String, &'static str)>)>,
+    list_colors: ListColors,
+    /// Facilities the environment is missing, with how the app copes.
+    unavailable: Vec<String>,
+    /// Lines scrolled past, for terminals too short to show the whole overlay.
+    scroll: u16,
+}
+
+impl Help {
+    pub fn new(keymap: &Keymap, list_colors: ListColors, unavailable: Vec<String>) -> Self {
+        let mut sections = Vec::<(&'static str, Vec<(String, &'static str)>)>::new();
+        for action in Action::ALL {
+            let keys = keymap.keys_for(action);
+            if keys.is_empty() {
+                continue;
+            }
+            let binding = (keys.join(" / "), action.description());
+            match sections.iter_mut().find(|(s, _)| *s == action.section()) {
+                Some((_, bindings)) => bindings.push(binding),
+                None => sections.push((action.section(), vec![binding])),
+            }
+        }
+
+        if let Some((_, bindings)) = sections.first_mut() {
+            bindings.push(("1-9".to_string(), "Switch to file tab"));
+            bindings.push(("Click header".to_string(), "Sort messages by column"));
+        }
+
+        Help {
+            sections,
+            list_colors,
+            unavailable,
+            scroll: 0,
+        }
+    }
+
+    pub fn scroll_by(&mut self, delta: i16) {
+        self.scroll = self.scroll.saturating_add_signed(delta);
+    }
+
+    pub fn render<B: Backend>(&mut self, f: &mut Frame<B>, rect: Rect) {
+        let key_width = self
+            .sections
+            .iter()
+            .flat_map(|(_, keys)| keys.iter())
+            .map(|(key, _)| key.chars().count())
+            .max()
+            .unwrap_or_default();
+
+        let mut lines = vec![];
+        for (section, keys) in &self.sections {
+            lines.push(Line::from(Span::styled(
+                *section,
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+            for (key, description) in keys {
+                lines.push(Line::from(vec![
+                    Span::styled(
+                        format!("  {key:key_width$}  "),
+                        Style::default().fg(Color::LightYellow),
+                    ),
+                    Span::raw(*description),
+                ]));
+            }
+            lines.push(Line::from(""));
+        }
+
+        lines.push(Line::from(Span::styled(
+            "Legend",
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+        for (color, title) in self.list_colors.legend() {
+            lines.push(Line::from(vec![
+                Span::raw("  "),
+                Span::styled("  ", Style::default().bg(*color)),
+                Span::raw(format!(" {title}")),
+            ]));
+        }
+
+        if !self.unavailable.is_empty() {
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(
+                "Unavailable",
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+            for note in &self.unavailable {
+                lines.push(Line::styled(
+                    format!("  {note}"),
+                    Style::default().fg(Color::Gray),
+                ));
+            }
+        }
+
+        let width = lines.iter().map(Line::width).max().unwrap_or_default() as u16 + 4;
+        let rect = centered_rect(width, lines.len() as u16 + 2, rect);
+        let hidden_lines = (lines.len() as u16).saturating_sub(rect.height.saturating_sub(2));
+        let title = if hidden_lines > 0 {
+            "Help (?, Up/Down to scroll)"
+        } else {
+            "Help (?)"
+        };
+        self.scroll = self.scroll.min(hidden_lines);
+
+        let paragraph = Paragraph::new(lines).scroll((self.scroll, 0)).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title(Span::styled(
+                    title,
+                    Style::default().add_modifier(Modifier::BOLD),
+                )),
+        );
+
+        f.render_widget(Clear, rect);
+        f.render_widget(paragraph, rect);
+    }
+}
@@ -0,0 +1,736 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{format_err, Context, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use indexmap::IndexMap;
+use serde::Deserialize;
+
+use crate::tui::config::{config_dir, read_config, OneOrMany};
+
+type ActionInfo = (
+    &'static str,
+    &'static str,
+    &'static str,
+    &'static [&'static str],
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    SelectSpans,
+    SelectMessages,
+    SelectDetail,
+    NextArea,
+    PrevArea,
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+    First,
+    Last,
+    JumpToStart,
+    JumpToEnd,
+    PrevFile,
+    NextFile,
+    PrevTab,
+    NextTab,
+    ZoomDetail,
+    ZoomMessages,
+    NewestFirst,
+    TimeDisplay,
+    ListColors,
+    ShrinkSidebar,
+    GrowSidebar,
+    ShrinkSpans,
+    GrowSpans,
+    SplitDetail,
+    Grouping,
+    ToggleGroup,
+    ToggleAllGroups,
+    Levels,
+    Direction,
+    Kind,
+    Keepalives,
+    SidebarMode,
+    Search,
+    ExplainFilters,
+    TypeAhead,
+    RegexSearch,
+    Bookmark,
+    NextBookmark,
+    PrevBookmark,
+    Tag,
+    ClearTags,
+    Mark,
+    SetNamedMark,
+    JumpToNamedMark,
+    Diff,
+    ClearFilters,
+    FindSimilar,
+    RangeStart,
+    RangeEnd,
+    Copy,
+    Export,
+    SpanStats,
+    FilterStats,
+    MalformedLines,
+    Save,
+    OpenViewer,
+    OpenEditor,
+    Pipe,
+    ValidateSchemas,
+    Wrap,
+    RawDetail,
+    LineNumbers,
+    ToggleFold,
+    FollowErrors,
+    Reload,
+    Theme,
+    CommandLine,
+    Help,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Action; 76] = [
+        Action::SelectSpans,
+        Action::SelectMessages,
+        Action::SelectDetail,
+        Action::NextArea,
+        Action::PrevArea,
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::PageUp,
+        Action::PageDown,
+        Action::HalfPageUp,
+        Action::HalfPageDown,
+        Action::First,
+        Action::Last,
+        Action::JumpToStart,
+        Action::JumpToEnd,
+        Action::PrevFile,
+        Action::NextFile,
+        Action::PrevTab,
+        Action::NextTab,
+        Action::ZoomDetail,
+        Action::ZoomMessages,
+        Action::NewestFirst,
+        Action::TimeDisplay,
+        Action::ListColors,
+        Action::ShrinkSidebar,
+        Action::GrowSidebar,
+        Action::ShrinkSpans,
+        Action::GrowSpans,
+        Action::SplitDetail,
+        Action::Grouping,
+        Action::ToggleGroup,
+        Action::ToggleAllGroups,
+        Action::Levels,
+        Action::Direction,
+        Action::Kind,
+        Action::Keepalives,
+        Action::SidebarMode,
+        Action::Search,
+        Action::RegexSearch,
+        Action::ExplainFilters,
+        Action::TypeAhead,
+        Action::Bookmark,
+        Action::NextBookmark,
+        Action::PrevBookmark,
+        Action::Tag,
+        Action::ClearTags,
+        Action::Mark,
+        Action::SetNamedMark,
+        Action::JumpToNamedMark,
+        Action::Diff,
+        Action::ClearFilters,
+        Action::FindSimilar,
+        Action::RangeStart,
+        Action::RangeEnd,
+        Action::Copy,
+        Action::Export,
+        Action::SpanStats,
+        Action::FilterStats,
+        Action::MalformedLines,
+        Action::Save,
+        Action::OpenViewer,
+        Action::OpenEditor,
+        Action::Pipe,
+        Action::ValidateSchemas,
+        Action::Wrap,
+        Action::RawDetail,
+        Action::LineNumbers,
+        Action::ToggleFold,
+        Action::FollowErrors,
+        Action::Reload,
+        Action::Theme,
+        Action::CommandLine,
+        Action::Help,
+        Action::Quit,
+    ];
+
+    /// Name of the action in the keymap config.
+    pub fn name(&self) -> &'static str {
+        self.info().0
+    }
+
+    pub fn description(&self) -> &'static str {
+        self.info().1
+    }
+
+    /// Heading the action is listed under in the help overlay.
+    pub fn section(&self) -> &'static str {
+        self.info().2
+    }
+
+    fn default_keys(&self) -> &'static [&'static str] {
+        self.info().3
+    }
+
+    /// Returns the config name, description, help section and default keys of the action.
+    fn info(&self) -> ActionInfo {
+        const NAVIGATION: &str = "Navigation";
+        const LAYOUT: &str = "Layout";
+        const FILTERS: &str = "Filters";
+        const ACTIONS: &str = "Actions";
+        const GENERAL: &str = "General";
+
+        match self {
+            Action::SelectSpans => ("select-spans", "Focus spans", NAVIGATION, &["s"]),
+            Action::SelectMessages => ("select-messages", "Focus messages", NAVIGATION, &["a"]),
+            Action::SelectDetail => ("select-detail", "Focus message detail", NAVIGATION, &["m"]),
+            Action::NextArea => ("next-area", "Focus next area", NAVIGATION, &["tab"]),
+            Action::PrevArea => ("prev-area", "Focus previous area", NAVIGATION, &["backtab"]),
+            Action::Up => (
+                "up",
+                "Move selection up or scroll detail",
+                NAVIGATION,
+                &["up", "k"],
+            ),
+            Action::Down => (
+                "down",
+                "Move selection down or scroll detail",
+                NAVIGATION,
+                &["down", "j"],
+            ),
+            Action::Left => ("left", "Scroll detail left", NAVIGATION, &["left"]),
+            Action::Right => ("right", "Scroll detail right", NAVIGATION, &["right"]),
+            Action::PageUp => ("page-up", "Move up by one page", NAVIGATION, &["pageup"]),
+            Action::PageDown => (
+                "page-down",
+                "Move down by one page",
+                NAVIGATION,
+                &["pagedown"],
+            ),
+            Action::HalfPageUp => (
+                "half-page-up",
+                "Move up by half a page",
+                NAVIGATION,
+                &["ctrl-u"],
+            ),
+            Action::HalfPageDown => (
+                "half-page-down",
+                "Move down by half a page",
+                NAVIGATION,
+                &["ctrl-d"],
+            ),
+            Action::First => ("first", "Jump to first item", NAVIGATION, &["home"]),
+            Action::Last => ("last", "Jump to last item", NAVIGATION, &["end"]),
+            Action::JumpToStart => (
+                "jump-to-start",
+                "Jump to start of messages",
+                NAVIGATION,
+                &["g g", "B"],
+            ),
+            Action::JumpToEnd => (
+                "jump-to-end",
+                "Jump to end of messages",
+                NAVIGATION,
+                &["G", "e"],
+            ),
+            Action::PrevFile => ("prev-file", "Previous file tab", NAVIGATION, &["{"]),
+            Action::NextFile => ("next-file", "Next file tab", NAVIGATION, &["}"]),
+            Action::PrevTab => ("prev-tab", "Previous account tab", NAVIGATION, &["["]),
+            Action::NextTab => ("next-tab", "Next account tab", NAVIGATION, &["]"]),
+            Action::ZoomDetail => ("zoom-detail", "Zoom message detail", NAVIGATION, &["z"]),
+            Action::ZoomMessages => ("zoom-messages", "Zoom message list", NAVIGATION, &["Z"]),
+            Action::NewestFirst => (
+                "newest-first",
+                "Toggle newest messages first",
+                LAYOUT,
+                &["R"],
+            ),
+            Action::TimeDisplay => (
+                "time-display",
+                "Cycle absolute / relative / delta timestamps",
+                LAYOUT,
+                &["A"],
+            ),
+            Action::ListColors => (
+                "list-colors",
+                "Color messages by direction / level",
+                LAYOUT,
+                &["C"],
+            ),
+            Action::ShrinkSidebar => ("shrink-sidebar", "Narrow the sidebar", LAYOUT, &["<"]),
+            Action::GrowSidebar => ("grow-sidebar", "Widen the sidebar", LAYOUT, &[">"]),
+            Action::ShrinkSpans => ("shrink-spans", "Shorten the spans list", LAYOUT, &["-"]),
+            Action::GrowSpans => ("grow-spans", "Lengthen the spans list", LAYOUT, &["+", "="]),
+            Action::SplitDetail => (
+                "split-detail",
+                "Show metadata next to the XML on wide terminals",
+                LAYOUT,
+                &["_"],
+            ),
+            Action::Grouping => (
+                "grouping",
+                "Group messages by hour / session / not at all",
+                LAYOUT,
+                &["H"],
+            ),
+            Action::ToggleGroup => (
+                "toggle-group",
+                "Collapse or expand the group of the selected message",
+                LAYOUT,
+                &["F"],
+            ),
+            Action::ToggleAllGroups => (
+                "toggle-all-groups",
+                "Collapse or expand all groups",
+                LAYOUT,
+                &["ctrl-f"],
+            ),
+            Action::Levels => ("levels", "Choose levels", FILTERS, &["l"]),
+            Action::Direction => ("direction", "Cycle direction", FILTERS, &["d"]),
+            Action::Kind => ("kind", "Cycle stanza kind", FILTERS, &["K"]),
+            Action::Keepalives => ("keepalives", "Hide keepalive pings", FILTERS, &["p"]),
+            Action::SidebarMode => (
+                "sidebar-mode",
+                "Group sidebar by spans / targets",
+                FILTERS,
+                &["t"],
+            ),
+            Action::Search => (
+                "search",
+                "Search text, or within the focused detail",
+                FILTERS,
+                &["/"],
+            ),
+            Action::RegexSearch => ("regex-search", "Search regex", FILTERS, &["\\"]),
+            Action::ExplainFilters => (
+                "explain-filters",
+                "Explain which filters hide a message",
+                FILTERS,
+                &["W"],
+            ),
+            Action::TypeAhead => (
+                "type-ahead",
+                "Jump through the focused list by typing",
+                NAVIGATION,
+                &["f"],
+            ),
+            Action::Bookmark => ("bookmark", "Toggle bookmark", ACTIONS, &["b"]),
+            Action::NextBookmark => (
+                "next-bookmark",
+                "Next bookmark, or next match in the detail",
+                ACTIONS,
+                &["n"],
+            ),
+            Action::PrevBookmark => (
+                "prev-bookmark",
+                "Previous bookmark, or previous match in the detail",
+                ACTIONS,
+                &["N"],
+            ),
+            Action::Tag => (
+                "tag",
+                "Tag message to copy or export with others",
+                ACTIONS,
+                &["space"],
+            ),
+            Action::ClearTags => ("clear-tags", "Untag all messages", ACTIONS, &["U"]),
+            Action::Mark => ("mark", "Mark message to diff against", ACTIONS, &["M"]),
+            Action::SetNamedMark => (
+                "set-named-mark",
+                "Set a named mark, followed by its letter",
+                ACTIONS,
+                &["alt-m"],
+            ),
+            Action::JumpToNamedMark => (
+                "jump-to-named-mark",
+                "Jump to a named mark, followed by its letter",
+                ACTIONS,
+                &["'"],
+            ),
+            Action::Diff => (
+                "diff",
+                "Cycle unified / side-by-side diff against marked",
+                ACTIONS,
+                &["D"],
+            ),
+            Action::ClearFilters => (
+                "clear-filters",
+                "Clear all filters, back to [All Messages]",
+                FILTERS,
+                &["X"],
+            ),
+            Action::FindSimilar => (
+                "find-similar",
+                "Show stanzas structured like the selected one, or all again",
+                FILTERS,
+                &["*"],
+            ),
+            Action::RangeStart => (
+                "range-start",
+                "Hide messages before the selected one",
+                FILTERS,
+                &["("],
+            ),
+            Action::RangeEnd => (
+                "range-end",
+                "Hide messages after the selected one",
+                FILTERS,
+                &[")"],
+            ),
+            Action::Copy => (
+                "copy",
+                "Copy message, or the tagged messages",
+                ACTIONS,
+                &["c"],
+            ),
+            Action::Export => ("export", "Export", ACTIONS, &["x"]),
+            Action::SpanStats => (
+                "span-stats",
+                "Show statistics of the highlighted span or target",
+                ACTIONS,
+                &["i"],
+            ),
+            Action::FilterStats => (
+                "filter-stats",
+                "Show statistics of the messages passing the filters",
+                ACTIONS,
+                &["I"],
+            ),
+            Action::MalformedLines => (
+                "malformed-lines",
+                "List the lines that failed to parse",
+                ACTIONS,
+                &["!"],
+            ),
+            Action::Save => ("save", "Save selected stanza to a file", ACTIONS, &["S"]),
+            Action::OpenViewer => (
+                "open-viewer",
+                "Open selected stanza in external viewer",
+                ACTIONS,
+                &["o"],
+            ),
+            Action::OpenEditor => (
+                "open-editor",
+                "Open selected stanza and its metadata in $EDITOR",
+                ACTIONS,
+                &["O"],
+            ),
+            Action::Pipe => (
+                "pipe",
+                "Pipe selected stanza to a shell command",
+                ACTIONS,
+                &["|"],
+            ),
+            Action::ValidateSchemas => (
+                "validate-schemas",
+                "Validate payloads against registered schemas",
+                ACTIONS,
+                &["V"],
+            ),
+            Action::Wrap => ("wrap", "Wrap detail lines", ACTIONS, &["w"]),
+            Action::RawDetail => (
+                "raw-detail",
+                "Show the stanza as logged / pretty-printed",
+                ACTIONS,
+                &["v"],
+            ),
+            Action::LineNumbers => (
+                "line-numbers",
+                "Number the lines of the stanza",
+                ACTIONS,
+                &["#"],
+            ),
+            Action::ToggleFold => (
+                "toggle-fold",
+                "Fold or unfold the XML element at the top of the detail",
+                ACTIONS,
+                &["enter"],
+            ),
+            Action::FollowErrors => (
+                "follow-errors",
+                "Jump to new errors as the log grows",
+                ACTIONS,
+                &["E"],
+            ),
+            Action::Reload => ("reload", "Reload messages", ACTIONS, &["r"]),
+            Action::Theme => ("theme", "Cycle syntax highlighting theme", GENERAL, &["T"]),
+            Action::CommandLine => (
+                "command-line",
+                "Run a command: filter, goto, range, export, theme or pin",
+                GENERAL,
+                &[":"],
+            ),
+            Action::Help => ("help", "Toggle help", GENERAL, &["?"]),
+            Action::Quit => (
+                "quit",
+                "Cancel background tasks, or quit",
+                GENERAL,
+                &["esc"],
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        // Shift is already reflected in the character (or in `BackTab`)
+        let modifiers = match event.code {
+            KeyCode::Char(_) | KeyCode::BackTab => event.modifiers - KeyModifiers::SHIFT,
+            _ => event.modifiers,
+        };
+        Key {
+            code: event.code,
+            modifiers,
+        }
+    }
+}
+
+impl std::str::FromStr for Key {
+    type Err = anyhow::Error;
+
+    /// Parses keys like `q`, `G`, `ctrl-d`, `alt-enter`, `pagedown` or `f5`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        loop {
+            let lower = rest.to_ascii_lowercase();
+            if rest.len() > 1 && lower.starts_with("ctrl-") {
+                modifiers |= KeyModifiers::CONTROL;
+                rest = &rest[5..];
+            } else if rest.len() > 1 && lower.starts_with("alt-") {
+                modifiers |= KeyModifiers::ALT;
+                rest = &rest[4..];
+            } else {
+                break;
+            }
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "esc" => KeyCode::Esc,
+                "enter" => KeyCode::Enter,
+                "tab" => KeyCode::Tab,
+                "backtab" | "shift-tab" => KeyCode::BackTab,
+                "space" => KeyCode::Char(' '),
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n) => KeyCode::F(n),
+                    None => return Err(format_err!("Unknown key `{s}`")),
+                },
+            },
+        };
+        Ok(Key { code, modifiers })
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            f.write_str("Ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            f.write_str("Alt-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::Esc => f.write_str("Esc"),
+            KeyCode::Enter => f.write_str("Enter"),
+            KeyCode::Tab => f.write_str("Tab"),
+            KeyCode::BackTab => f.write_str("Shift-Tab"),
+            KeyCode::Backspace => f.write_str("Backspace"),
+            KeyCode::Delete => f.write_str("Delete"),
+            KeyCode::Up => f.write_str("Up"),
+            KeyCode::Down => f.write_str("Down"),
+            KeyCode::Left => f.write_str("Left"),
+            KeyCode::Right => f.write_str("Right"),
+            KeyCode::PageUp => f.write_str("PgUp"),
+            KeyCode::PageDown => f.write_str("PgDn"),
+            KeyCode::Home => f.write_str("Home"),
+            KeyCode::End => f.write_str("End"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+/// Maps key sequences to actions. Most bindings are a single key, but sequences like `g g`
+/// are supported too.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Vec<Key>, Action)>,
+    /// Keys typed so far that are a prefix of some binding.
+    pending: Vec<Key>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .flat_map(|action| {
+                action.default_keys().iter().map(|keys| {
+                    let sequence = parse_sequence(keys).expect("default keys are valid");
+                    (sequence, *action)
+                })
+            })
+            .collect();
+
+        Keymap {
+            bindings,
+            pending: vec![],
+        }
+    }
+}
+
+impl Keymap {
+    /// Returns the default location of the keymap config,
+    /// `$XDG_CONFIG_HOME/prose-log-parser/keys.toml` or `~/.config/prose-log-parser/keys.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("keys.toml"))
+    }
+
+    /// Loads the default keymap, overridden by the bindings in `path` if it exists.
+    ///
+    /// The config has a single `[keys]` table mapping action names to a key or a list of keys.
+    /// An action listed in the config loses its default keys:
+    ///
+    /// ```toml
+    /// [keys]
+    /// quit = ["esc", "q"]
+    /// copy = "y"
+    /// jump-to-start = "g g"
+    /// ```
+    pub fn load(path: &Path) -> Result<Self> {
+        let config: KeymapConfig = read_config(path)?;
+        let overrides = parse_config(config)
+            .with_context(|| format!("Invalid keymap config {}", path.display()))?;
+
+        let mut keymap = Keymap::default();
+        for (action, sequences) in overrides {
+            keymap.bindings.retain(|(_, a)| *a != action);
+            keymap
+                .bindings
+                .extend(sequences.into_iter().map(|keys| (keys, action)));
+        }
+        Ok(keymap)
+    }
+
+    /// Feeds a key press into the keymap, returning the bound action once a complete sequence
+    /// has been typed.
+    pub fn resolve(&mut self, key: KeyEvent) -> Option<Action> {
+        let key = Key::from(key);
+        self.pending.push(key);
+
+        if let Some((_, action)) = self.bindings.iter().find(|(keys, _)| *keys == self.pending) {
+            self.pending.clear();
+            return Some(*action);
+        }
+        if self
+            .bindings
+            .iter()
+            .any(|(keys, _)| keys.starts_with(&self.pending))
+        {
+            return None;
+        }
+
+        // Not part of any sequence; the key may still be bound on its own
+        let retry = self.pending.len() > 1;
+        self.pending.clear();
+        if retry {
+            self.pending.push(key);
+            if let Some((_, action)) = self.bindings.iter().find(|(keys, _)| *keys == self.pending)
+            {
+                self.pending.clear();
+                return Some(*action);
+            }
+            self.pending.clear();
+        }
+        None
+    }
+
+    /// Returns the key sequences bound to `action`, formatted for display.
+    pub fn keys_for(&self, action: Action) -> Vec<String> {
+        self.bindings
+            .iter()
+            .filter(|(_, a)| *a == action)
+            .map(|(keys, _)| keys.iter().map(Key::to_string).collect::<Vec<_>>().join(""))
+            .collect()
+    }
+}
+
+fn parse_sequence(s: &str) -> Result<Vec<Key>> {
+    let keys = s
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<Key>>>()?;
+    if keys.is_empty() {
+        return Err(format_err!("Empty key binding"));
+    }
+    Ok(keys)
+}
+
+/// The keymap config, a `[keys]` table mapping action names to key sequences.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeymapConfig {
+    #[serde(default)]
+    keys: IndexMap<String, OneOrMany>,
+}
+
+fn parse_config(config: KeymapConfig) -> Result<Vec<(Action, Vec<Vec<Key>>)>> {
+    config
+        .keys
+        .into_iter()
+        .map(|(name, keys)| {
+            let action = Action::ALL
+                .into_iter()
+                .find(|action| action.name() == name)
+                .ok_or_else(|| format_err!("unknown action `{name}`"))?;
+            let sequences = keys
+                .into_vec()
+                .iter()
+                .map(|value| parse_sequence(value))
+                .collect::<Result<_>>()
+                .with_context(|| format!("action `{name}`"))?;
+            Ok((action, sequences))
+        })
+        .collect()
+}
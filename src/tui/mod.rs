@@ -1,44 +1,192 @@
-use std::io;
-use std::path::Path;
+use std::io::{self, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::thread;
 use std::time::Duration;
 
-use anyhow::Result;
-use crossterm::event::{EventStream, KeyEvent};
+use anyhow::{Context, Result};
+use clap::Args;
+use crossterm::event::{
+    DisableMouseCapture, EnableMouseCapture, EventStream, KeyCode, KeyEvent, MouseButton,
+    MouseEvent, MouseEventKind,
+};
 use crossterm::{
-    event::{Event, KeyCode, KeyEventKind},
+    cursor,
+    event::{Event, KeyEventKind},
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, is_raw_mode_enabled, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
 };
 use futures::StreamExt;
-use notify::{Config, PollWatcher, RecursiveMode, Watcher};
+use notify::{Config, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::Mutex;
 use ratatui::prelude::*;
-use ratatui::widgets::Paragraph;
+use ratatui::widgets::{Paragraph, Tabs};
 use tokio::select;
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
 
-use crate::tui::app::{App, AppArea};
+use crate::format::parse_timestamp_format;
+use crate::log_source::ReadOptions;
+use crate::theme::DEFAULT_THEME;
+use crate::tui::app::{App, AppArea, MarkCommand, Movement};
+use crate::tui::background_task::panic_message;
+use crate::tui::capabilities::Capabilities;
+use crate::tui::keymap::{Action, Keymap};
+use crate::tui::pins::Pin;
+use crate::tui::prompt::PromptKind;
+use crate::tui::schema::SchemaRegistry;
+use crate::tui::timestamp::{TimeDisplay, DEFAULT_LIST_TIMESTAMP_FORMAT};
+use crate::tui::viewer::{open_in_editor, open_in_viewer, ViewerOptions};
 
 mod app;
+mod background_task;
+mod capabilities;
+mod clipboard;
+mod command;
+mod config;
+mod diff;
+mod export_dialog;
+mod filter;
+mod grouping;
+mod help;
+mod keymap;
+mod pins;
+mod pipe;
+mod popup;
+mod prompt;
+mod rate;
+mod schema;
+mod search;
 mod selected_log_message;
+mod similar;
+mod span_stats;
 mod stateful_list;
+mod timestamp;
+mod toast;
+mod viewer;
+
+#[derive(Args, Debug, Clone)]
+pub struct BrowseOptions {
+    /// Keymap config to load instead of `~/.config/prose-log-parser/keys.toml`.
+    #[arg(long)]
+    pub keymap: Option<PathBuf>,
+    /// Schema config to load instead of `~/.config/prose-log-parser/schemas.toml`.
+    #[arg(long)]
+    pub schemas: Option<PathBuf>,
+    /// Queries to count in the bottom bar to load instead of
+    /// `~/.config/prose-log-parser/pins.toml`. More can be pinned with `:pin`.
+    #[arg(long)]
+    pub pins: Option<PathBuf>,
+    /// Syntax highlighting theme, switchable at runtime with `T`.
+    #[arg(long, default_value = DEFAULT_THEME)]
+    pub theme: String,
+    /// Jump to new ERROR-level messages and error stanzas as the log file grows.
+    #[arg(long)]
+    pub follow_errors: bool,
+    #[command(flatten)]
+    pub read: ReadOptions,
+    /// Languages to show the bodies of `<message/>` stanzas in, in place of the stanzas in
+    /// the message list, most preferred first, e.g. `fr,en`. The first body is shown if none
+    /// is in these languages.
+    #[arg(long, value_delimiter = ',')]
+    pub body_lang: Vec<String>,
+    /// strftime-style format of the timestamp column in the message list. Pass an empty
+    /// string to hide the column.
+    #[arg(long, default_value = DEFAULT_LIST_TIMESTAMP_FORMAT, value_parser = parse_timestamp_format)]
+    pub timestamp_format: String,
+    /// How timestamps are shown, switchable at runtime with `A`.
+    #[arg(long, value_enum, default_value_t)]
+    pub time_display: TimeDisplay,
+    /// Rows of messages kept visible above and below the selected one while navigating, like
+    /// vim's `scrolloff`. Pass a large value such as 999 to keep the selection centered.
+    #[arg(long, default_value_t = 0)]
+    pub scrolloff: usize,
+    #[command(flatten)]
+    pub viewer: ViewerOptions,
+    /// Area focused on startup, so that scripted launches start where the user acts first.
+    #[arg(long, value_enum, env = "PROSE_LOG_FOCUS", default_value_t)]
+    pub focus: AppArea,
+    /// File to append how often the UI woke up and redrew to whenever it goes idle and on exit,
+    /// to check that nothing runs while idle.
+    #[arg(long)]
+    pub debug_log: Option<PathBuf>,
+}
+
+/// Browses the log files at `paths`, each in its own tab.
+pub async fn browse_log_files(paths: &[PathBuf], options: &BrowseOptions) -> Result<()> {
+    let keymap = match options.keymap.clone().or_else(Keymap::default_path) {
+        Some(path) => Keymap::load(&path)?,
+        None => Keymap::default(),
+    };
+    let schemas = match options
+        .schemas
+        .clone()
+        .or_else(SchemaRegistry::default_path)
+    {
+        Some(path) => SchemaRegistry::load(&path)?,
+        None => SchemaRegistry::default(),
+    };
+
+    let pins = match options.pins.clone().or_else(Pin::default_path) {
+        Some(path) => Pin::load(&path)?,
+        None => vec![],
+    };
 
-pub async fn browse_log_file(path: impl AsRef<Path>) -> Result<()> {
+    let mut files = vec![];
+    for path in paths {
+        let app = App::new(path, options.read.clone(), options.body_lang.clone())?;
+        app.set_follow_errors(options.follow_errors);
+        app.set_timestamp_format(&options.timestamp_format);
+        app.set_time_display(options.time_display);
+        app.set_scrolloff(options.scrolloff);
+        app.set_pins(pins.clone());
+        app.select_area(options.focus.clone());
+        app.set_theme(&options.theme)?;
+        let title = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.display().to_string());
+        files.push((title, app));
+    }
+
+    let mut capabilities = Capabilities::detect();
+
+    install_panic_hook();
     enable_raw_mode()?;
+    let _guard = TerminalGuard;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
+    if execute!(stdout, EnableMouseCapture).is_err() {
+        capabilities.mouse = false;
+        capabilities.note("No mouse support: use the keyboard to focus and sort");
+    }
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let path = path.as_ref();
-    let app = App::new(path)?;
-
-    let res = run_app(&mut terminal, app).await;
+    let res = run_app(
+        &mut terminal,
+        files,
+        keymap,
+        &schemas,
+        capabilities,
+        options,
+    )
+    .await;
 
     // restore terminal
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen,)?;
+    // Disabling mouse capture where it couldn't be enabled fails the same way
+    let _ = execute!(terminal.backend_mut(), DisableMouseCapture);
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
     terminal.show_cursor()?;
 
+    for panic in DEFERRED_PANICS.lock().drain(..) {
+        eprintln!("{panic}");
+    }
     if let Err(err) = res {
         println!("{err:?}");
     }
@@ -46,117 +194,673 @@ pub async fn browse_log_file(path: impl AsRef<Path>) -> Result<()> {
     Ok(())
 }
 
-async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: App) -> Result<()> {
-    let mut reader = EventStream::new();
+/// Leaves the alternate screen and raw mode, unless the terminal was already restored.
+fn restore_terminal() -> io::Result<()> {
+    if !is_raw_mode_enabled()? {
+        return Ok(());
+    }
+    disable_raw_mode()?;
+    let mut stdout = io::stdout();
+    let _ = execute!(stdout, DisableMouseCapture);
+    execute!(stdout, LeaveAlternateScreen, cursor::Show)
+}
+
+/// Restores the terminal when dropped, so that it's usable again when the app unwinds from a
+/// panic instead of returning.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = restore_terminal();
+    }
+}
+
+/// Panics of other threads than the UI's while the terminal was taken over, printed once it's
+/// restored.
+static DEFERRED_PANICS: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+/// Restores the terminal before a panic of the calling thread, the UI's, is printed, so that
+/// the message isn't lost with the alternate screen. Panics on other threads leave the terminal
+/// to the UI, which keeps running, and are printed on exit instead of over it. Background
+/// tasks and the event reader also report theirs in the UI.
+fn install_panic_hook() {
+    let ui_thread = thread::current().id();
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if thread::current().id() == ui_thread {
+            let _ = restore_terminal();
+        } else if is_raw_mode_enabled().unwrap_or(false) {
+            let name = thread::current().name().unwrap_or("<unnamed>").to_string();
+            DEFERRED_PANICS
+                .lock()
+                .push(format!("thread '{name}' {info}"));
+            return;
+        }
+        hook(info);
+    }));
+}
+
+async fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    files: Vec<(String, App)>,
+    mut keymap: Keymap,
+    schemas: &SchemaRegistry,
+    mut capabilities: Capabilities,
+    options: &BrowseOptions,
+) -> Result<()> {
     let (es_tx, mut es_rx) = mpsc::channel(1);
-    let (notify_tx, mut notify_rx) = mpsc::channel::<()>(1);
+    // Carries the index of the file whose log changed
+    let (notify_tx, mut notify_rx) = mpsc::channel::<usize>(1);
 
-    // Spawn an async task to listen for key events and send them through the channel
-    tokio::spawn(async move {
-        while let Some(Ok(event)) = reader.next().await {
-            if let Err(_) = es_tx.send(event).await {
-                // If we can't send the event, it means the receiver has been dropped, so we should end the loop
-                break;
+    let mut event_reader = spawn_event_reader(es_tx.clone());
+
+    let mut watchers = vec![];
+    for (index, (title, app)) in files.iter().enumerate() {
+        let notify_tx = notify_tx.clone();
+        let watcher = watch_files(app.paths(), move || {
+            // The receiver is gone when the app is shutting down
+            let _ = notify_tx.blocking_send(index);
+        });
+        match watcher {
+            Ok(watcher) => watchers.push(watcher),
+            Err(err) => {
+                capabilities.file_watching = false;
+                capabilities.note(format!(
+                    "Not watching {title} ({err}): reload to see new lines"
+                ));
             }
         }
-    });
+    }
+    for (_, app) in &files {
+        app.set_capabilities(capabilities.clone());
+    }
 
-    let mut watcher = PollWatcher::new(
-        move |result: Result<notify::Event, notify::Error>| {
-            let event = result.unwrap();
+    let titles = files
+        .iter()
+        .map(|(title, _)| title.clone())
+        .collect::<Vec<_>>();
+    let mut selected = 0;
 
-            if event.kind.is_modify() {
-                notify_tx.blocking_send(()).unwrap();
-            }
-        },
-        Config::default().with_poll_interval(Duration::from_millis(10)),
-    )?;
-    watcher.watch(app.path(), RecursiveMode::NonRecursive)?;
+    terminal.draw(|f| draw_ui(f, &titles, selected, &files[selected].1, &keymap))?;
+
+    // Redraws progress bars while background tasks are running
+    let mut progress_interval = tokio::time::interval(Duration::from_millis(100));
+    // Keeps relative timestamps and the rate of new messages current
+    let mut clock_interval = tokio::time::interval(Duration::from_secs(1));
+    // Ticks missed while a timer wasn't needed aren't worth catching up on
+    progress_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    clock_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
-    terminal.draw(|f| draw_ui(f, &app))?;
+    // Files watched by polling are scanned less often the longer nothing happens
+    let polled = watchers.iter().any(FileWatcher::is_polling);
+    let mut polling = PollBackoff::new();
 
+    // The timers above are only polled while they're needed, so that the loop only wakes up
+    // for input, file changes and backed off polling while idle
+    let mut wakeups = 0u64;
+    let mut redraws = 0u64;
+    let mut was_idle = false;
     loop {
-        select! {
+        let detail_deadline = files[selected].1.pending_detail_deadline();
+        let toast_deadline = files[selected].1.toast_deadline();
+        let clock_needed = files[selected].1.is_relative_time() || files[selected].1.is_live();
+        let background_tasks = files[selected].1.has_background_tasks();
+
+        let idle = !background_tasks
+            && !clock_needed
+            && detail_deadline.is_none()
+            && toast_deadline.is_none()
+            && (!polled || polling.is_backed_off());
+        if idle && !was_idle {
+            if let Some(path) = &options.debug_log {
+                write_debug_log(path, "idle", wakeups, redraws)?;
+            }
+        }
+        was_idle = idle;
+
+        let redraw = select! {
             Some(event) = es_rx.recv() => {
+                polling.reset();
+                let app = &files[selected].1;
+                if let Event::Key(_) = event {
+                    app.clear_status();
+                }
+                // Mouse moves and focus changes don't change anything on screen
+                let redraw = match &event {
+                    Event::Key(_) | Event::Resize(..) => true,
+                    Event::Mouse(mouse) => mouse.kind == MouseEventKind::Down(MouseButton::Left),
+                    _ => false,
+                };
                 match event {
                     Event::Key(KeyEvent {
                         code,
                         kind: KeyEventKind::Press,
                         ..
-                    }) => {
-                        match code {
-                            KeyCode::Char('s') => app.select_area(AppArea::Spans),
-                            KeyCode::Char('a') => app.select_area(AppArea::Messages),
-                            KeyCode::Char('m') => app.select_area(AppArea::MessageDetail),
-                            KeyCode::Char('c') => app.copy_selected_message_to_clipboard(),
-                            KeyCode::Char('r') => app.reload_messages(),
-                            KeyCode::Char('e') => app.jump_to_end(),
-                            KeyCode::Up => app.handle_key_up(),
-                            KeyCode::Down => app.handle_key_down(),
-                            KeyCode::Esc => {
-                                // Exit the loop on Esc
-                                break;
+                    }) if app.is_prompt_active() => app.handle_prompt_key(code),
+                    Event::Key(KeyEvent {
+                        code,
+                        kind: KeyEventKind::Press,
+                        ..
+                    }) if app.is_popup_active() => app.handle_popup_key(code),
+                    Event::Key(KeyEvent {
+                        code,
+                        kind: KeyEventKind::Press,
+                        ..
+                    }) if app.is_mark_pending() => app.handle_mark_key(code),
+                    Event::Key(key @ KeyEvent {
+                        kind: KeyEventKind::Press,
+                        ..
+                    }) => match keymap.resolve(key) {
+                        Some(Action::SelectSpans) => app.select_area(AppArea::Spans),
+                        Some(Action::SelectMessages) => app.select_area(AppArea::Messages),
+                        Some(Action::SelectDetail) => app.select_area(AppArea::MessageDetail),
+                        Some(Action::NextArea) => app.select_next_area(),
+                        Some(Action::PrevArea) => app.select_prev_area(),
+                        Some(Action::Up) => app.handle_key_up(),
+                        Some(Action::Down) => app.handle_key_down(),
+                        Some(Action::Left) => app.handle_key_left(),
+                        Some(Action::Right) => app.handle_key_right(),
+                        Some(Action::PageUp) => app.handle_movement(Movement::PageUp),
+                        Some(Action::PageDown) => app.handle_movement(Movement::PageDown),
+                        Some(Action::HalfPageUp) => app.handle_movement(Movement::HalfPageUp),
+                        Some(Action::HalfPageDown) => app.handle_movement(Movement::HalfPageDown),
+                        Some(Action::First) => app.handle_movement(Movement::First),
+                        Some(Action::Last) => app.handle_movement(Movement::Last),
+                        Some(Action::JumpToStart) => app.jump_to_start(),
+                        Some(Action::JumpToEnd) => app.jump_to_end(),
+                        Some(Action::NextFile) => selected = (selected + 1) % files.len(),
+                        Some(Action::PrevFile) => {
+                            selected = (selected + files.len() - 1) % files.len()
+                        }
+                        Some(Action::PrevTab) => app.select_prev_tab(),
+                        Some(Action::NextTab) => app.select_next_tab(),
+                        Some(Action::ZoomDetail) => app.toggle_zoom(AppArea::MessageDetail),
+                        Some(Action::ZoomMessages) => app.toggle_zoom(AppArea::Messages),
+                        Some(Action::NewestFirst) => app.toggle_newest_first(),
+                        Some(Action::TimeDisplay) => app.cycle_time_display(),
+                        Some(Action::ListColors) => app.toggle_list_colors(),
+                        Some(Action::ShrinkSidebar) => app.resize_sidebar(-5),
+                        Some(Action::GrowSidebar) => app.resize_sidebar(5),
+                        Some(Action::ShrinkSpans) => app.resize_spans(-5),
+                        Some(Action::GrowSpans) => app.resize_spans(5),
+                        Some(Action::Levels) => app.toggle_level_filter_popup(),
+                        Some(Action::Direction) => app.cycle_direction_filter(),
+                        Some(Action::Kind) => app.cycle_kind_filter(),
+                        Some(Action::Keepalives) => app.toggle_keepalive_filter(),
+                        Some(Action::SidebarMode) => app.toggle_sidebar_mode(),
+                        Some(Action::Search) => app.open_search_prompt(PromptKind::Search),
+                        Some(Action::RegexSearch) => app.open_search_prompt(PromptKind::RegexSearch),
+                        Some(Action::ExplainFilters) => app.open_explain_filters_prompt(),
+                        Some(Action::CommandLine) => app.open_command_prompt(),
+                        Some(Action::TypeAhead) => app.open_type_ahead(),
+                        Some(Action::Bookmark) => app.toggle_bookmark(),
+                        Some(Action::NextBookmark) => app.select_next_bookmark(),
+                        Some(Action::PrevBookmark) => app.select_prev_bookmark(),
+                        Some(Action::Tag) => app.toggle_tag(),
+                        Some(Action::ClearTags) => app.clear_tags(),
+                        Some(Action::Mark) => app.toggle_mark(),
+                        Some(Action::SetNamedMark) => app.start_named_mark(MarkCommand::Set),
+                        Some(Action::JumpToNamedMark) => app.start_named_mark(MarkCommand::Jump),
+                        Some(Action::Diff) => app.cycle_diff_mode(),
+                        Some(Action::ClearFilters) => app.clear_all_filters(),
+                        Some(Action::FindSimilar) => app.toggle_similar_stanzas(),
+                        Some(Action::RangeStart) => app.set_time_range_bound(true),
+                        Some(Action::RangeEnd) => app.set_time_range_bound(false),
+                        Some(Action::Copy) => app.copy_messages_to_clipboard(),
+                        Some(Action::Export) => app.open_export_dialog(),
+                        Some(Action::SpanStats) => app.open_span_stats(),
+                        Some(Action::FilterStats) => app.open_filter_stats(),
+                        Some(Action::MalformedLines) => app.show_malformed_lines(),
+                        Some(Action::Save) => app.open_save_prompt(),
+                        Some(Action::OpenViewer) => {
+                            // Stop reading terminal events so they reach the viewer instead
+                            event_reader.abort();
+                            if let Err(err) = open_in_viewer(terminal, app, &options.viewer) {
+                                app.set_status(Err(err.to_string()));
                             }
-                            _ => {}
+                            event_reader = spawn_event_reader(es_tx.clone());
                         }
-                    }
+                        Some(Action::OpenEditor) => {
+                            event_reader.abort();
+                            if let Err(err) = open_in_editor(terminal, app) {
+                                app.set_status(Err(err.to_string()));
+                            }
+                            event_reader = spawn_event_reader(es_tx.clone());
+                        }
+                        Some(Action::Pipe) => app.open_pipe_prompt(),
+                        Some(Action::ValidateSchemas) => app.validate_selected_message(schemas),
+                        Some(Action::Wrap) => app.toggle_detail_wrap(),
+                        Some(Action::SplitDetail) => app.toggle_detail_split(),
+                        Some(Action::Grouping) => app.cycle_grouping(),
+                        Some(Action::ToggleGroup) => app.toggle_group(),
+                        Some(Action::ToggleAllGroups) => app.toggle_all_groups(),
+                        Some(Action::RawDetail) => app.toggle_raw_detail(),
+                        Some(Action::LineNumbers) => app.toggle_line_numbers(),
+                        Some(Action::ToggleFold) => app.toggle_fold(),
+                        Some(Action::FollowErrors) => app.toggle_follow_errors(),
+                        Some(Action::Reload) => app.reload_messages(),
+                        Some(Action::Theme) => app.cycle_theme(),
+                        Some(Action::Help) => app.toggle_help_popup(&keymap),
+                        Some(Action::Quit) if app.has_background_tasks() => {
+                            app.cancel_background_tasks()
+                        }
+                        Some(Action::Quit) => break,
+                        None => {
+                            if let Some(index) = file_shortcut(key).filter(|i| *i < files.len()) {
+                                selected = index;
+                            }
+                        }
+                    },
+                    Event::Mouse(MouseEvent {
+                        kind: MouseEventKind::Down(MouseButton::Left),
+                        column,
+                        row,
+                        ..
+                    }) => app.handle_mouse_click(column, row),
                     _ => {}
                 }
-
-                terminal.draw(|f| draw_ui(f, &app))?;
+                redraw
+            },
+            result = &mut event_reader => {
+                // The reader only stops on its own if it panicked or the terminal is gone
+                let Some(panic) = result.err().and_then(|err| err.try_into_panic().ok()) else {
+                    break;
+                };
+                files[selected].1.show_toast(Err(format!(
+                    "Reading input failed and was restarted: {}",
+                    panic_message(panic.as_ref())
+                )));
+                event_reader = spawn_event_reader(es_tx.clone());
+                true
+            },
+            Some(index) = notify_rx.recv() => {
+                polling.reset();
+                files[index].1.append_messages();
+                // Other files are drawn once selected
+                index == selected
             },
-            Some(_) = notify_rx.recv() => {
-                app.reload_messages();
-                terminal.draw(|f| draw_ui(f, &app))?;
+            _ = progress_interval.tick(), if background_tasks => true,
+            _ = tokio::time::sleep_until(
+                detail_deadline.map_or_else(tokio::time::Instant::now, Into::into)
+            ), if detail_deadline.is_some() => {
+                files[selected].1.show_pending_detail();
+                true
+            },
+            _ = tokio::time::sleep_until(
+                toast_deadline.map_or_else(tokio::time::Instant::now, Into::into)
+            ), if toast_deadline.is_some() => {
+                files[selected].1.expire_toast();
+                true
+            },
+            _ = clock_interval.tick(), if clock_needed => true,
+            _ = tokio::time::sleep_until(polling.deadline()), if polled => {
+                watchers.iter().for_each(FileWatcher::poll);
+                polling.back_off();
+                // Changes found are sent through `notify_rx`
+                false
             },
             else => {
                 println!("All channels have been closed");
                 break;
             },
+        };
+
+        wakeups += 1;
+        if redraw {
+            terminal.draw(|f| draw_ui(f, &titles, selected, &files[selected].1, &keymap))?;
+            redraws += 1;
         }
     }
 
+    if let Some(path) = &options.debug_log {
+        write_debug_log(path, "exit", wakeups, redraws)?;
+    }
+    Ok(())
+}
+
+/// Appends the number of times the UI loop woke up and redrew so far to the debug log at
+/// `path`, labelled with the `event` that prompted it.
+fn write_debug_log(path: &Path, event: &str, wakeups: u64, redraws: u64) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(
+        file,
+        "{} browse {event}: {wakeups} wakeups, {redraws} redraws",
+        chrono::Utc::now().format("%F %T")
+    )?;
     Ok(())
 }
 
-fn draw_ui<B: Backend>(f: &mut Frame<B>, app: &App) {
-    // Create two chunks with equal horizontal screen space
+/// Returns the index of the file selected by pressing `1` to `9`.
+fn file_shortcut(key: KeyEvent) -> Option<usize> {
+    match key.code {
+        KeyCode::Char(c @ '1'..='9') if key.modifiers.is_empty() => Some(c as usize - '1' as usize),
+        _ => None,
+    }
+}
+
+/// Watches the log files of a tab for changes.
+enum FileWatcher {
+    /// The platform's file change notifications, which need nothing from the UI loop but
+    /// stop when the watcher is dropped.
+    Notify { _watcher: RecommendedWatcher },
+    /// A fallback that scans the files whenever [`FileWatcher::poll`] is called.
+    Poll(PollWatcher),
+}
+
+impl FileWatcher {
+    fn is_polling(&self) -> bool {
+        matches!(self, FileWatcher::Poll(_))
+    }
+
+    /// Scans the files for changes in the background if they're watched by polling.
+    fn poll(&self) {
+        if let FileWatcher::Poll(watcher) = self {
+            // Only fails if the polling thread is gone, which leaves nothing to do
+            let _ = watcher.poll();
+        }
+    }
+}
+
+/// Calls `on_change` whenever one of `paths` is modified. Uses the platform's file change
+/// notifications where they work, and falls back to polling where they don't, e.g. on network
+/// drives or in some Windows and container setups.
+fn watch_files<F: Fn() + Send + Clone + 'static>(
+    paths: &[PathBuf],
+    on_change: F,
+) -> Result<FileWatcher> {
+    let handler = |on_change: F| {
+        move |result: Result<notify::Event, notify::Error>| {
+            if result.is_ok_and(|event| event.kind.is_modify()) {
+                on_change();
+            }
+        }
+    };
+
+    let recommended =
+        notify::recommended_watcher(handler(on_change.clone())).and_then(|mut watcher| {
+            for path in paths {
+                watcher.watch(path, RecursiveMode::NonRecursive)?;
+            }
+            Ok(watcher)
+        });
+    if let Ok(watcher) = recommended {
+        return Ok(FileWatcher::Notify { _watcher: watcher });
+    }
+
+    // Scanned on the schedule of `PollBackoff` rather than at a fixed interval
+    let mut watcher =
+        PollWatcher::new(handler(on_change), Config::default().with_manual_polling())?;
+    for path in paths {
+        watcher.watch(path, RecursiveMode::NonRecursive)?;
+    }
+    Ok(FileWatcher::Poll(watcher))
+}
+
+/// When to next scan files watched by polling. Scans keep up with a person watching the log
+/// while there's input or the files change, and slow down while neither happens.
+struct PollBackoff {
+    interval: Duration,
+    deadline: Instant,
+}
+
+impl PollBackoff {
+    const MIN_INTERVAL: Duration = Duration::from_millis(250);
+    const MAX_INTERVAL: Duration = Duration::from_secs(5);
+
+    fn new() -> Self {
+        PollBackoff {
+            interval: Self::MIN_INTERVAL,
+            deadline: Instant::now() + Self::MIN_INTERVAL,
+        }
+    }
+
+    fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Whether scans have slowed down as far as they go.
+    fn is_backed_off(&self) -> bool {
+        self.interval == Self::MAX_INTERVAL
+    }
+
+    /// Schedules the next scan after a scan that may have found nothing.
+    fn back_off(&mut self) {
+        self.interval = (self.interval * 2).min(Self::MAX_INTERVAL);
+        self.deadline = Instant::now() + self.interval;
+    }
+
+    /// Goes back to frequent scans after input or a file change.
+    fn reset(&mut self) {
+        self.interval = Self::MIN_INTERVAL;
+        self.deadline = Instant::now() + Self::MIN_INTERVAL;
+    }
+}
+
+/// Spawns an async task to listen for terminal events and send them through the channel.
+fn spawn_event_reader(es_tx: mpsc::Sender<Event>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut reader = EventStream::new();
+        while let Some(Ok(event)) = reader.next().await {
+            // Windows reports key releases and repeats, while other platforms only report
+            // presses. Treat held keys as repeated presses everywhere.
+            let event = match event {
+                Event::Key(KeyEvent {
+                    kind: KeyEventKind::Release,
+                    ..
+                }) => continue,
+                Event::Key(key) => Event::Key(KeyEvent {
+                    kind: KeyEventKind::Press,
+                    ..key
+                }),
+                event => event,
+            };
+            if let Err(_) = es_tx.send(event).await {
+                // If we can't send the event, it means the receiver has been dropped, so we should end the loop
+                break;
+            }
+        }
+    })
+}
+
+fn draw_ui<B: Backend>(
+    f: &mut Frame<B>,
+    titles: &[String],
+    selected: usize,
+    app: &App,
+    keymap: &Keymap,
+) {
+    let rect = if titles.len() > 1 {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(f.size());
+        let titles = titles
+            .iter()
+            .enumerate()
+            .map(|(i, title)| format!("{} {title}", i + 1))
+            .collect();
+        let tabs = Tabs::new(titles)
+            .select(selected)
+            .style(Style::new().fg(Color::Gray))
+            .highlight_style(Style::new().fg(Color::Black).bg(Color::LightCyan));
+        f.render_widget(tabs, chunks[0]);
+        chunks[1]
+    } else {
+        f.size()
+    };
+
+    draw_panes(f, app, keymap, rect);
+    app.render_popup(f, rect);
+    app.render_toast(f, rect);
+    app.render_background_tasks(f, rect);
+}
+
+fn draw_panes<B: Backend>(f: &mut Frame<B>, app: &App, keymap: &Keymap, rect: Rect) {
+    let tab_titles = app.tab_titles();
+    let rect = if tab_titles.is_empty() {
+        rect
+    } else {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(rect);
+        let tabs = Tabs::new(tab_titles)
+            .select(app.selected_tab())
+            .style(Style::new().fg(Color::Gray))
+            .highlight_style(Style::new().fg(Color::Black).bg(Color::LightYellow));
+        f.render_widget(tabs, chunks[0]);
+        chunks[1]
+    };
+
+    match app.zoomed_area() {
+        Some(AppArea::MessageDetail) => {
+            draw_selected_message(f, app, keymap, rect);
+            return;
+        }
+        Some(AppArea::Messages) => {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(10), Constraint::Max(2)])
+                .split(rect);
+            app.render_messages_list(f, chunks[0]);
+            draw_bottom_bar(f, app, keymap, chunks[1]);
+            return;
+        }
+        Some(AppArea::Spans) | None => (),
+    }
+
+    // Split the screen between the sidebar and the message detail
+    let (sidebar_percent, _) = app.layout_percentages();
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
-        .split(f.size());
+        .constraints([
+            Constraint::Percentage(sidebar_percent),
+            Constraint::Percentage(100 - sidebar_percent),
+        ])
+        .split(rect);
 
     draw_sidebar(f, app, chunks[0]);
-    draw_selected_message(f, app, chunks[1]);
+    draw_selected_message(f, app, keymap, chunks[1]);
 }
 
-fn draw_selected_message<B: Backend>(f: &mut Frame<B>, app: &App, rect: Rect) {
+fn draw_selected_message<B: Backend>(f: &mut Frame<B>, app: &App, keymap: &Keymap, rect: Rect) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Min(10), Constraint::Max(2)])
         .split(rect);
 
     app.render_selected_message(f, chunks[0]);
+    draw_bottom_bar(f, app, keymap, chunks[1]);
+}
 
-    let legend = vec![(Color::Blue, "Sent"), (Color::Yellow, "Received")];
+fn draw_bottom_bar<B: Backend>(f: &mut Frame<B>, app: &App, keymap: &Keymap, rect: Rect) {
+    let rect = rect.inner(&Margin {
+        vertical: 0,
+        horizontal: 1,
+    });
 
-    let keys = vec![
-        ("Esc", "Quit"),
-        ("c", "Copy message"),
-        ("r", "Reload messages"),
-        ("e", "Jump to end"),
-    ];
+    if let Some((prompt, error)) = app.prompt_line() {
+        let mut spans = vec![
+            Span::raw(prompt),
+            Span::styled(" ", Style::new().bg(Color::Gray)),
+        ];
+        if let Some(error) = error {
+            spans.push(Span::styled(
+                format!("  {}", error.lines().last().unwrap_or_default()),
+                Style::new().fg(Color::Red),
+            ));
+        }
+        let prompt = Paragraph::new(Line::from(spans));
+        f.render_widget(prompt, rect);
+        return;
+    }
+
+    let keys = [
+        (Action::Help, "Help"),
+        (Action::Quit, "Quit"),
+        (Action::Search, "Search"),
+        (Action::Levels, "Levels"),
+        (Action::Export, "Export"),
+    ]
+    .into_iter()
+    .filter_map(|(action, title)| Some((keymap.keys_for(action).into_iter().next()?, title)))
+    .collect::<Vec<_>>();
 
     let mut spans = vec![];
 
-    spans.push(Span::raw(format!("{} messages", app.messages_len())));
+    let filters = app.active_filters();
+    if filters.is_empty() {
+        spans.push(Span::raw(format!("{} messages", app.messages_len())));
+    } else {
+        spans.push(Span::raw(format!(
+            "{} of {} messages",
+            app.messages_len(),
+            app.total_messages_len()
+        )));
+        spans.push(Span::raw(" |  "));
+        spans.push(Span::styled(
+            filters.join(", "),
+            Style::new().fg(Color::LightYellow),
+        ));
+    }
+    if let Some((incoming, outgoing, sparkline)) = app.live_rate() {
+        spans.push(Span::raw(" |  "));
+        spans.push(Span::styled(
+            format!("↓{incoming:.1}/s ↑{outgoing:.1}/s {sparkline}"),
+            Style::new().fg(Color::LightCyan),
+        ));
+    }
+    let malformed = app.malformed_count();
+    if malformed > 0 {
+        spans.push(Span::raw(" |  "));
+        let key = keymap
+            .keys_for(Action::MalformedLines)
+            .into_iter()
+            .next()
+            .map(|key| format!(" ({key})"))
+            .unwrap_or_default();
+        spans.push(Span::styled(
+            format!("⚠ {malformed} malformed lines{key}"),
+            Style::new().fg(Color::Yellow),
+        ));
+    }
+    let pins = app.pin_counts();
+    if !pins.is_empty() {
+        spans.push(Span::raw(" |  "));
+        for (index, (name, count)) in pins.into_iter().enumerate() {
+            if index > 0 {
+                spans.push(Span::raw("  "));
+            }
+            let color = if count > 0 {
+                Color::LightMagenta
+            } else {
+                Color::Gray
+            };
+            spans.push(Span::styled(
+                format!("{name}: {count}"),
+                Style::new().fg(color),
+            ));
+        }
+    }
     spans.push(Span::raw(" |  "));
+    if let Some(status) = app.status() {
+        spans.push(match status {
+            Ok(message) => Span::styled(message, Style::new().fg(Color::Green)),
+            Err(err) => Span::styled(err, Style::new().fg(Color::Red)),
+        });
+        spans.push(Span::raw(" |  "));
+    }
 
     spans.extend(
-        legend
-            .into_iter()
+        app.list_colors()
+            .legend()
+            .iter()
             .flat_map(|(color, title)| {
-                let key = Span::styled("  ", Style::new().fg(Color::Black).bg(color));
+                let key = Span::styled("  ", Style::new().fg(Color::Black).bg(*color));
                 let desc = Span::styled(format!(" {} ", title), Style::new().fg(Color::Gray));
                 [key, desc]
             })
@@ -179,19 +883,17 @@ fn draw_selected_message<B: Backend>(f: &mut Frame<B>, app: &App, rect: Rect) {
     );
 
     let bottom_bar = Paragraph::new(Line::from(spans));
-    f.render_widget(
-        bottom_bar,
-        chunks[1].inner(&Margin {
-            vertical: 0,
-            horizontal: 1,
-        }),
-    );
+    f.render_widget(bottom_bar, rect);
 }
 
 fn draw_sidebar<B: Backend>(f: &mut Frame<B>, app: &App, rect: Rect) {
+    let (_, spans_percent) = app.layout_percentages();
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
+        .constraints([
+            Constraint::Percentage(spans_percent),
+            Constraint::Percentage(100 - spans_percent),
+        ])
         .split(rect);
 
     app.render_spans_list(f, chunks[0]);
@@ -0,0 +1,66 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use serde::Deserialize;
+
+use crate::log_message::LogMessage;
+use crate::tui::command::parse_query;
+use crate::tui::config::{config_dir, read_config};
+use crate::tui::filter::Filter;
+
+/// A query pinned as a counter in the bottom bar, e.g. `errors: 3`, counting the messages it
+/// matches as the log grows.
+#[derive(Debug, Clone)]
+pub struct Pin {
+    pub name: String,
+    filter: Filter,
+}
+
+impl Pin {
+    /// Parses `query` with the syntax of [`parse_query`].
+    pub fn new(name: impl Into<String>, query: &str) -> Result<Self> {
+        Ok(Pin {
+            name: name.into(),
+            filter: parse_query(query)?,
+        })
+    }
+
+    pub fn matches(&self, message: &LogMessage) -> bool {
+        self.filter.matches(message)
+    }
+
+    /// Returns the default location of the pinned queries, `pins.toml` in the config
+    /// directory.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("pins.toml"))
+    }
+
+    /// Loads the queries pinned in `path`, if it exists, in the order they're listed. The
+    /// config has a single `[pins]` table mapping counter names to queries:
+    ///
+    /// ```toml
+    /// [pins]
+    /// errors = "level>=error"
+    /// "iq timeouts" = "kind=iq timeout"
+    /// ```
+    pub fn load(path: &Path) -> Result<Vec<Pin>> {
+        let config: PinsConfig = read_config(path)?;
+        config
+            .pins
+            .into_iter()
+            .map(|(name, query)| {
+                let pin = Pin::new(name.as_str(), &query);
+                pin.with_context(|| format!("Invalid pins config {}: pin `{name}`", path.display()))
+            })
+            .collect()
+    }
+}
+
+/// The pins config, a `[pins]` table mapping counter names to queries in display order.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PinsConfig {
+    #[serde(default)]
+    pins: IndexMap<String, String>,
+}
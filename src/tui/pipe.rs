@@ -0,0 +1,68 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{format_err, Result};
+use ratatui::prelude::{Color, Line, Style};
+
+/// Runs `command` with the system shell, writing `input` to its standard input. Returns the
+/// lines it printed, standard error in red after standard output, followed by its exit status
+/// if it failed.
+pub fn run_piped(command: &str, input: &str) -> Result<Vec<Line<'static>>> {
+    let mut child = shell_command(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format_err!("Failed to run {command}: {err}"))?;
+
+    // Write from another thread, as the command may fill its output pipes before reading all
+    // of its input
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_string();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    // Commands that don't read their input, like `date`, close the pipe early
+    let _ = writer.join();
+
+    let mut lines = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| Line::from(line.replace('\t', "    ")))
+        .collect::<Vec<_>>();
+    lines.extend(
+        String::from_utf8_lossy(&output.stderr)
+            .lines()
+            .map(|line| Line::styled(line.replace('\t', "    "), Style::default().fg(Color::Red))),
+    );
+    if lines.is_empty() {
+        lines.push(Line::styled(
+            "No output",
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    if !output.status.success() {
+        lines.push(Line::styled(
+            format!("{command} exited with {}", output.status),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    Ok(lines)
+}
+
+/// Returns a command running `command` with `sh -c`, or `cmd /C` on Windows.
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+/// Returns a command running `command` with `sh -c`, or `cmd /C` on Windows.
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+
+    // cmd doesn't unquote arguments the way Rust quotes them, so pass the command as is
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").raw_arg(command);
+    shell
+}
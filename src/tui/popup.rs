@@ -0,0 +1,96 @@
+use ratatui::backend::Backend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::prelude::{Color, Line, Modifier, Span, Style};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use ratatui::Frame;
+
+use crate::log_message::Level;
+use crate::tui::export_dialog::ExportDialog;
+use crate::tui::help::Help;
+use crate::tui::span_stats::SpanStats;
+use crate::tui::stateful_list::StatefulList;
+
+#[derive(Clone)]
+pub enum Popup {
+    LevelFilter(StatefulList<Level>),
+    Export(ExportDialog),
+    Help(Help),
+    SpanStats(SpanStats),
+    Text(TextPopup),
+}
+
+/// Read-only text shown over the app until Esc is pressed.
+#[derive(Debug, Clone)]
+pub struct TextPopup {
+    pub title: String,
+    pub lines: Vec<Line<'static>>,
+    /// Lines scrolled past, for text taller than the terminal.
+    scroll: u16,
+}
+
+impl TextPopup {
+    pub fn new(title: impl Into<String>, lines: Vec<Line<'static>>) -> Self {
+        TextPopup {
+            title: title.into(),
+            lines,
+            scroll: 0,
+        }
+    }
+
+    pub fn scroll_by(&mut self, delta: i16) {
+        self.scroll = self.scroll.saturating_add_signed(delta);
+    }
+
+    pub fn render<B: Backend>(&mut self, f: &mut Frame<B>, rect: Rect) {
+        let width = self
+            .lines
+            .iter()
+            .map(Line::width)
+            .chain([self.title.chars().count()])
+            .max()
+            .unwrap_or_default() as u16
+            + 4;
+        let rect = centered_rect(width, self.lines.len() as u16 + 2, rect);
+        let hidden_lines = (self.lines.len() as u16).saturating_sub(rect.height.saturating_sub(2));
+        self.scroll = self.scroll.min(hidden_lines);
+
+        let paragraph = Paragraph::new(self.lines.clone())
+            .scroll((self.scroll, 0))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .style(Style::default().fg(Color::White))
+                    .title(Span::styled(
+                        self.title.clone(),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    )),
+            );
+
+        f.render_widget(Clear, rect);
+        f.render_widget(paragraph, rect);
+    }
+}
+
+/// Returns a rect of `width` x `height` cells centered in `rect`, clamped to its size.
+pub fn centered_rect(width: u16, height: u16, rect: Rect) -> Rect {
+    let width = width.min(rect.width);
+    let height = height.min(rect.height);
+
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length((rect.height - height) / 2),
+            Constraint::Length(height),
+            Constraint::Min(0),
+        ])
+        .split(rect);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Length((rect.width - width) / 2),
+            Constraint::Length(width),
+            Constraint::Min(0),
+        ])
+        .split(vertical[1])[1]
+}
@@ -0,0 +1,76 @@
+use crossterm::event::KeyCode;
+
+use crate::tui::export_dialog::complete_path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PromptKind {
+    Search,
+    RegexSearch,
+    /// Search within the stanza shown in the detail pane.
+    DetailSearch,
+    /// Text of a message to explain the filtering of.
+    ExplainFilters,
+    SaveStanza,
+    /// A `:` command.
+    Command,
+    /// Text to jump to in the focused list as it's typed.
+    TypeAhead,
+    /// Shell command to pipe the selected stanza to.
+    Pipe,
+}
+
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: String,
+    pub error: Option<String>,
+}
+
+pub enum PromptEvent {
+    Pending,
+    Cancelled,
+    Submitted(String),
+}
+
+impl Prompt {
+    pub fn new(kind: PromptKind, input: impl Into<String>) -> Self {
+        Prompt {
+            kind,
+            input: input.into(),
+            error: None,
+        }
+    }
+
+    pub fn prefix(&self) -> &'static str {
+        match self.kind {
+            PromptKind::Search => "/",
+            PromptKind::RegexSearch => "\\",
+            PromptKind::DetailSearch => "Find in detail: ",
+            PromptKind::ExplainFilters => {
+                "Explain filters for message containing (empty for marked): "
+            }
+            PromptKind::SaveStanza => "Save to: ",
+            PromptKind::Command => ":",
+            PromptKind::TypeAhead => "Jump to: ",
+            PromptKind::Pipe => "| ",
+        }
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode) -> PromptEvent {
+        self.error = None;
+
+        match code {
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Tab if self.kind == PromptKind::SaveStanza => {
+                self.input = complete_path(&self.input)
+            }
+            KeyCode::Enter => return PromptEvent::Submitted(self.input.clone()),
+            KeyCode::Esc => return PromptEvent::Cancelled,
+            _ => (),
+        }
+        PromptEvent::Pending
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::log_message::{LogMessage, StanzaDirection};
+
+/// Seconds of history drawn in the sparkline.
+const HISTORY_SECS: u64 = 20;
+/// Seconds the rates are averaged over, so that they don't jump between 0 and the number of
+/// messages of the last batch.
+const RATE_WINDOW_SECS: u64 = 5;
+const SPARKLINE_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Messages appended to the log within one second.
+#[derive(Debug, Clone, Default)]
+struct Bucket {
+    /// Seconds since the meter started.
+    second: u64,
+    incoming: usize,
+    outgoing: usize,
+    total: usize,
+}
+
+/// Counts the messages appended to the log while it's tailed, by the second they arrived in,
+/// to show how busy it is.
+#[derive(Debug, Clone)]
+pub struct RateMeter {
+    started: Instant,
+    /// The seconds of the last [`HISTORY_SECS`] that had messages, oldest first.
+    buckets: VecDeque<Bucket>,
+}
+
+impl Default for RateMeter {
+    fn default() -> Self {
+        RateMeter {
+            started: Instant::now(),
+            buckets: VecDeque::new(),
+        }
+    }
+}
+
+impl RateMeter {
+    pub fn record(&mut self, messages: &[LogMessage]) {
+        let second = self.current_second();
+        if self.buckets.back().is_none_or(|b| b.second != second) {
+            self.buckets.push_back(Bucket {
+                second,
+                ..Bucket::default()
+            });
+        }
+        let bucket = self.buckets.back_mut().expect("bucket was added");
+        for message in messages {
+            match message.fields.direction {
+                Some(StanzaDirection::In) => bucket.incoming += 1,
+                Some(StanzaDirection::Out) => bucket.outgoing += 1,
+                None => (),
+            }
+            bucket.total += 1;
+        }
+        while self
+            .buckets
+            .front()
+            .is_some_and(|b| b.second + HISTORY_SECS <= second)
+        {
+            self.buckets.pop_front();
+        }
+    }
+
+    /// Whether messages arrived within the history shown.
+    pub fn is_live(&self) -> bool {
+        let second = self.current_second();
+        self.buckets
+            .back()
+            .is_some_and(|b| b.second + HISTORY_SECS > second)
+    }
+
+    /// Stanzas received and sent per second, over the last few seconds.
+    pub fn rates(&self) -> (f64, f64) {
+        let second = self.current_second();
+        let recent = self
+            .buckets
+            .iter()
+            .filter(|b| b.second + RATE_WINDOW_SECS > second);
+        let (incoming, outgoing) = recent.fold((0, 0), |(incoming, outgoing), b| {
+            (incoming + b.incoming, outgoing + b.outgoing)
+        });
+        let window = RATE_WINDOW_SECS as f64;
+        (incoming as f64 / window, outgoing as f64 / window)
+    }
+
+    /// Messages per second over the last [`HISTORY_SECS`], oldest first, scaled to the
+    /// busiest second.
+    pub fn sparkline(&self) -> String {
+        let second = self.current_second();
+        let counts = (0..HISTORY_SECS)
+            .rev()
+            .map(|ago| {
+                let bucket = self.buckets.iter().find(|b| b.second + ago == second);
+                bucket.map_or(0, |b| b.total)
+            })
+            .collect::<Vec<_>>();
+        let max = counts.iter().copied().max().unwrap_or_default().max(1);
+        counts
+            .into_iter()
+            .map(|count| SPARKLINE_LEVELS[count * (SPARKLINE_LEVELS.len() - 1) / max])
+            .collect()
+    }
+
+    fn current_second(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+}
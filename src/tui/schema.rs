@@ -0,0 +1,189 @@
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use anyhow::{format_err, Context, Result};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use xml::reader::XmlEvent;
+use xml::writer::EventWriter;
+use xml::{EmitterConfig, ParserConfig};
+
+use crate::tui::config::{config_dir, read_config};
+
+/// Outcome of validating one extension element against the schema of its namespace.
+pub struct SchemaReport {
+    pub namespace: String,
+    /// Violations reported by the validator. Empty if the element is valid.
+    pub errors: Vec<String>,
+}
+
+/// XML Schema (`.xsd`) or RelaxNG (`.rng`) files registered per namespace. Elements are
+/// validated with `xmllint`, which must be on the `PATH`.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    schemas: Vec<(String, PathBuf)>,
+}
+
+impl SchemaRegistry {
+    /// Returns the default location of the schema config, `schemas.toml` in the config
+    /// directory.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config_dir()?.join("schemas.toml"))
+    }
+
+    /// Loads the schemas registered in `path`, if it exists. The config has a single
+    /// `[schemas]` table mapping namespaces to schema files, relative to the config file:
+    ///
+    /// ```toml
+    /// [schemas]
+    /// "urn:example:payload:0" = "payload.xsd"
+    /// ```
+    pub fn load(path: &Path) -> Result<Self> {
+        let config: SchemaConfig = read_config(path)?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        let schemas = config
+            .schemas
+            .into_iter()
+            .map(|(namespace, schema)| (namespace, base.join(schema)))
+            .collect();
+        Ok(SchemaRegistry { schemas })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.schemas.is_empty()
+    }
+
+    /// Validates every element of `xml` whose namespace has a registered schema.
+    pub fn validate(&self, xml: &str) -> Result<Vec<SchemaReport>> {
+        extract_elements(xml, |namespace| self.schema_for(namespace).is_some())?
+            .into_iter()
+            .map(|(namespace, element)| {
+                let schema = self
+                    .schema_for(&namespace)
+                    .expect("namespace is registered");
+                Ok(SchemaReport {
+                    errors: run_xmllint(schema, &element)?,
+                    namespace,
+                })
+            })
+            .collect()
+    }
+
+    fn schema_for(&self, namespace: &str) -> Option<&Path> {
+        self.schemas
+            .iter()
+            .find(|(ns, _)| ns == namespace)
+            .map(|(_, schema)| schema.as_path())
+    }
+}
+
+/// The schema config, a `[schemas]` table mapping namespaces to schema files.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SchemaConfig {
+    #[serde(default)]
+    schemas: IndexMap<String, PathBuf>,
+}
+
+/// Returns the outermost elements of `xml` in a namespace accepted by `is_registered`, each
+/// serialized as a standalone document.
+fn extract_elements(
+    xml: &str,
+    is_registered: impl Fn(&str) -> bool,
+) -> Result<Vec<(String, String)>> {
+    let reader = ParserConfig::new()
+        .trim_whitespace(true)
+        .ignore_comments(true)
+        .create_reader(xml.as_bytes());
+
+    let mut elements = vec![];
+    // Namespace, writer and nesting depth of the element being extracted
+    let mut current: Option<(String, EventWriter<Vec<u8>>, usize)> = None;
+
+    for event in reader {
+        let event = event?;
+        if let (None, XmlEvent::StartElement { name, .. }) = (&current, &event) {
+            match &name.namespace {
+                Some(namespace) if is_registered(namespace) => {
+                    let writer = EmitterConfig::new()
+                        .write_document_declaration(false)
+                        .create_writer(vec![]);
+                    current = Some((namespace.clone(), writer, 0));
+                }
+                _ => continue,
+            }
+        }
+        let Some((_, writer, depth)) = &mut current else {
+            continue;
+        };
+
+        match &event {
+            XmlEvent::StartElement { .. } => *depth += 1,
+            XmlEvent::EndElement { .. } => *depth -= 1,
+            _ => (),
+        }
+        if let Some(event) = event.as_writer_event() {
+            writer.write(event)?;
+        }
+
+        if *depth == 0 {
+            let (namespace, writer, _) = current.take().expect("an element is being extracted");
+            elements.push((namespace, String::from_utf8(writer.into_inner())?));
+        }
+    }
+
+    Ok(elements)
+}
+
+/// Validates `xml` against `schema` with `xmllint`, returning the reported violations.
+fn run_xmllint(schema: &Path, xml: &str) -> Result<Vec<String>> {
+    let flag = match schema.extension().and_then(|ext| ext.to_str()) {
+        Some("rng") => "--relaxng",
+        _ => "--schema",
+    };
+
+    let mut child = Command::new("xmllint")
+        .arg("--noout")
+        .arg(flag)
+        .arg(schema)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run xmllint")?;
+    // Write from another thread while stderr is drained, so that neither side blocks on a
+    // full pipe
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = xml.to_string();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    // xmllint may exit before reading all of its input, e.g. for an unreadable schema
+    match writer.join().expect("xmllint writer panicked") {
+        Err(err) if err.kind() != ErrorKind::BrokenPipe => return Err(err.into()),
+        _ => (),
+    }
+
+    let messages = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter(|line| *line != "- validates" && *line != "- fails to validate")
+        // Line numbers refer to the extracted element rather than the log, so drop them
+        .map(|line| {
+            let message = line.strip_prefix("-:").and_then(|l| l.split_once(": "));
+            message.map_or(line, |(_, message)| message).to_string()
+        })
+        .collect::<Vec<_>>();
+
+    match output.status.code() {
+        Some(0) => Ok(vec![]),
+        // Validation failures, as opposed to unreadable schemas or input
+        Some(3) | Some(4) => Ok(messages),
+        _ => Err(format_err!(
+            "xmllint failed for {}: {}",
+            schema.display(),
+            messages.join(" ")
+        )),
+    }
+}
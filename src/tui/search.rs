@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use regex::Regex;
+
+use crate::log_message::{LogMessage, MessageId};
+
+#[derive(Debug, Clone)]
+pub enum SearchQuery {
+    Text(String),
+    Regex {
+        regex: Regex,
+        /// Whether each message matched. Matching pretty-prints stanzas, which is too slow to
+        /// redo each time the other filters change.
+        matched: Arc<Mutex<HashMap<MessageId, bool>>>,
+    },
+}
+
+impl SearchQuery {
+    pub fn text(input: impl Into<String>) -> Option<Self> {
+        let input = input.into();
+        if input.is_empty() {
+            return None;
+        }
+        Some(SearchQuery::Text(input))
+    }
+
+    pub fn regex(input: &str) -> Result<Option<Self>, regex::Error> {
+        if input.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(SearchQuery::Regex {
+            regex: Regex::new(input)?,
+            matched: Arc::default(),
+        }))
+    }
+
+    pub fn input(&self) -> &str {
+        match self {
+            SearchQuery::Text(text) => text,
+            SearchQuery::Regex { regex, .. } => regex.as_str(),
+        }
+    }
+
+    /// Text queries are matched against the raw message only, regular expressions
+    /// additionally against the pretty-printed stanza XML.
+    pub fn matches(&self, message: &LogMessage) -> bool {
+        match self {
+            SearchQuery::Text(text) => message.fields.message.contains(text.as_str()),
+            SearchQuery::Regex { regex, matched } => {
+                *matched.lock().entry(message.id()).or_insert_with(|| {
+                    regex.is_match(&message.fields.message)
+                        || message
+                            .pretty_printed_xml()
+                            .map(|xml| regex.is_match(&xml))
+                            .unwrap_or(false)
+                })
+            }
+        }
+    }
+
+    /// Forgets which messages matched, once they're replaced by reloading the log files.
+    pub fn clear_matches(&self) {
+        if let SearchQuery::Regex { matched, .. } = self {
+            matched.lock().clear();
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            SearchQuery::Text(text) => format!("search: \"{text}\""),
+            SearchQuery::Regex { regex, .. } => format!("regex: /{}/", regex.as_str()),
+        }
+    }
+}
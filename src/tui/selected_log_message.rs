@@ -1,10 +1,31 @@
-use ratatui::prelude::{Line, Text};
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use ratatui::prelude::{Color, Line, Modifier, Span, Style, Text};
 use ratatui::widgets::ScrollbarState;
 
 pub struct SelectedLogMessage {
+    /// The visible lines, with folded elements collapsed.
     pub message: Text<'static>,
     pub vertical_position: u16,
+    pub horizontal_position: u16,
     pub scroll_state: ScrollbarState,
+    /// Number of rows the message occupies on screen, accounting for wrapping.
+    line_count: usize,
+    /// All lines, including those hidden in folded elements.
+    lines: Vec<Line<'static>>,
+    /// Indexes into `lines` of the start tags of folded elements.
+    folded: BTreeSet<usize>,
+    /// Index into `lines` of each visible line.
+    visible: Vec<usize>,
+    wrap_width: Option<u16>,
+    /// Text searched for in the detail pane, highlighted wherever it occurs.
+    search: Option<String>,
+    /// Indexes into `lines` of the lines containing `search`.
+    matches: Vec<usize>,
+    /// Indexes into `lines` of the lines prefixed with line numbers, counting from 1 at the
+    /// first.
+    numbered: Option<Range<usize>>,
 }
 
 impl From<Vec<Line<'static>>> for SelectedLogMessage {
@@ -12,9 +33,18 @@ impl From<Vec<Line<'static>>> for SelectedLogMessage {
         let lines = value.len();
 
         SelectedLogMessage {
-            message: value.into(),
+            message: value.clone().into(),
             vertical_position: 0,
+            horizontal_position: 0,
             scroll_state: ScrollbarState::default().content_length(lines as u16),
+            line_count: lines,
+            visible: (0..lines).collect(),
+            lines: value,
+            folded: BTreeSet::new(),
+            wrap_width: None,
+            search: None,
+            matches: vec![],
+            numbered: None,
         }
     }
 }
@@ -30,7 +60,348 @@ impl SelectedLogMessage {
         self.scroll_state = self.scroll_state.position(self.vertical_position);
     }
 
+    /// Scrolls by `delta` lines, stopping at the first and last line.
+    pub fn scroll_by(&mut self, delta: i32) {
+        let max = self.line_count.saturating_sub(1) as i32;
+        self.vertical_position = (self.vertical_position as i32 + delta).clamp(0, max) as u16;
+        self.scroll_state = self.scroll_state.position(self.vertical_position);
+    }
+
+    pub fn scroll_to_top(&mut self) {
+        self.scroll_by(-(self.vertical_position as i32));
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll_by(self.line_count as i32);
+    }
+
+    pub fn scroll_left(&mut self) {
+        self.horizontal_position = self.horizontal_position.saturating_sub(1);
+    }
+
+    pub fn scroll_right(&mut self) {
+        let max = self.message.width().saturating_sub(1) as u16;
+        self.horizontal_position = self.horizontal_position.saturating_add(1).min(max);
+    }
+
+    /// Recomputes the scrollable line count for the message wrapped at `width` columns, or
+    /// unwrapped if `width` is `None`.
+    pub fn set_wrap_width(&mut self, width: Option<u16>) {
+        self.wrap_width = width;
+        self.line_count = match width {
+            Some(width) => self
+                .message
+                .lines
+                .iter()
+                .map(|line| wrapped_line_count(line, width as usize))
+                .sum(),
+            None => self.message.lines.len(),
+        };
+        if width.is_some() {
+            self.horizontal_position = 0;
+        }
+        self.scroll_state = self.scroll_state.content_length(self.line_count as u16);
+        self.scroll_by(0);
+    }
+
+    /// Inserts `lines` above the message, such as reports about it.
+    pub fn prepend(&mut self, lines: Vec<Line<'static>>) {
+        let count = lines.len();
+        self.lines.splice(0..0, lines);
+        self.folded = self.folded.iter().map(|start| start + count).collect();
+        self.matches = self.matches.iter().map(|line| line + count).collect();
+        self.numbered = self
+            .numbered
+            .take()
+            .map(|lines| lines.start + count..lines.end + count);
+        self.update_visible_lines();
+    }
+
+    /// Prefixes the lines at indexes `lines`, usually those of the stanza, with their line
+    /// numbers.
+    pub fn set_numbered_lines(&mut self, lines: Range<usize>) {
+        self.numbered = Some(lines);
+        self.update_visible_lines();
+    }
+
+    /// Highlights the occurrences of `query` and scrolls to the first line containing it, from
+    /// the top of the pane on. An empty query clears the search. Returns the number of lines
+    /// containing `query`.
+    pub fn search(&mut self, query: &str) -> usize {
+        self.search = Some(query.to_string()).filter(|q| !q.is_empty());
+        self.matches = match &self.search {
+            Some(query) => (0..self.lines.len())
+                .filter(|index| line_text(&self.lines[*index]).contains(query.as_str()))
+                .collect(),
+            None => vec![],
+        };
+        self.update_visible_lines();
+
+        let top = self.visible.get(self.current_line()).copied().unwrap_or(0);
+        if let Some(line) = self.matches.iter().find(|line| **line >= top).copied() {
+            self.scroll_to_line(line);
+        } else if let Some(line) = self.matches.first().copied() {
+            self.scroll_to_line(line);
+        }
+        self.matches.len()
+    }
+
+    pub fn search_query(&self) -> Option<&str> {
+        self.search.as_deref()
+    }
+
+    /// Scrolls to the next or previous line containing the search, wrapping around. Returns
+    /// the position of the match among all matches, counting from 1, and their number.
+    pub fn select_match(&mut self, forward: bool) -> Option<(usize, usize)> {
+        let top = self.visible.get(self.current_line()).copied().unwrap_or(0);
+        let position = if forward {
+            self.matches
+                .iter()
+                .position(|line| *line > top)
+                .unwrap_or(0)
+        } else {
+            self.matches
+                .iter()
+                .rposition(|line| *line < top)
+                .unwrap_or(self.matches.len().checked_sub(1)?)
+        };
+        self.scroll_to_line(*self.matches.get(position)?);
+        Some((position + 1, self.matches.len()))
+    }
+
+    /// Unfolds the elements hiding the line at index `line` into `lines` and scrolls it to the
+    /// top of the pane.
+    fn scroll_to_line(&mut self, line: usize) {
+        let hiding = self
+            .folded
+            .iter()
+            .copied()
+            .filter(|start| *start < line && self.fold_end(*start).is_some_and(|end| end >= line))
+            .collect::<Vec<_>>();
+        if !hiding.is_empty() {
+            for start in hiding {
+                self.folded.remove(&start);
+            }
+            self.update_visible_lines();
+        }
+        let row = self.row_of(line);
+        self.scroll_by(row as i32 - self.vertical_position as i32);
+    }
+
+    /// Index into the visible lines of the line at the top of the pane, which folding
+    /// applies to.
+    pub fn current_line(&self) -> usize {
+        let Some(width) = self.wrap_width else {
+            return (self.vertical_position as usize).min(self.visible.len().saturating_sub(1));
+        };
+        let mut row = 0;
+        for (index, line) in self.message.lines.iter().enumerate() {
+            row += wrapped_line_count(line, width as usize);
+            if row > self.vertical_position as usize {
+                return index;
+            }
+        }
+        self.visible.len().saturating_sub(1)
+    }
+
+    /// Returns the message with the current line highlighted.
+    pub fn highlighted_text(&self) -> Text<'static> {
+        let mut text = self.message.clone();
+        if let Some(line) = text.lines.get_mut(self.current_line()) {
+            for span in &mut line.spans {
+                span.style = span.style.add_modifier(Modifier::REVERSED);
+            }
+        }
+        text
+    }
+
+    /// Folds or unfolds the element starting on the current line. On any other line, folds
+    /// the innermost element containing it and scrolls to its start tag.
+    pub fn toggle_fold(&mut self) {
+        let Some(&current) = self.visible.get(self.current_line()) else {
+            return;
+        };
+        if self.folded.remove(&current) {
+            self.update_visible_lines();
+            return;
+        }
+        if self.fold_end(current).is_some() {
+            self.folded.insert(current);
+            self.update_visible_lines();
+            return;
+        }
+
+        let enclosing = (0..current)
+            .rev()
+            .find(|start| self.fold_end(*start).is_some_and(|end| end >= current));
+        if let Some(start) = enclosing {
+            self.folded.insert(start);
+            self.update_visible_lines();
+            let row = self.row_of(start);
+            self.scroll_by(row as i32 - self.vertical_position as i32);
+        }
+    }
+
+    /// Returns the index of the closing tag of the element whose start tag is on line
+    /// `start`, if it has children on their own lines. Relies on the indentation of
+    /// pretty-printed XML.
+    fn fold_end(&self, start: usize) -> Option<usize> {
+        let text = line_text(self.lines.get(start)?);
+        let indent = indentation(&text);
+        let tag = text.trim();
+        if !tag.starts_with('<') || tag.starts_with("</") || tag.ends_with("/>") {
+            return None;
+        }
+        if tag.contains("</") {
+            return None;
+        }
+
+        let end = (start + 1..self.lines.len())
+            .find(|index| indentation(&line_text(&self.lines[*index])) <= indent)?;
+        let closing = line_text(&self.lines[end]);
+        (end > start + 1 && indentation(&closing) == indent && closing.trim().starts_with("</"))
+            .then_some(end)
+    }
+
+    fn update_visible_lines(&mut self) {
+        let mut visible = vec![];
+        let mut message = vec![];
+        let mut index = 0;
+        while index < self.lines.len() {
+            let mut line = match &self.search {
+                Some(query) => highlight_occurrences(&self.lines[index], query),
+                None => self.lines[index].clone(),
+            };
+            if let Some(numbered) = self.numbered.as_ref().filter(|n| n.contains(&index)) {
+                let width = numbered.len().to_string().len();
+                line.spans.insert(
+                    0,
+                    Span::styled(
+                        format!("{:>width$} ", index - numbered.start + 1),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                );
+            }
+            visible.push(index);
+            match self.folded.contains(&index).then(|| self.fold_end(index)) {
+                Some(Some(end)) => {
+                    line.spans.push(Span::styled(
+                        format!(" ⋯ {} lines", end - index),
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                    index = end + 1;
+                }
+                _ => index += 1,
+            }
+            message.push(line);
+        }
+
+        self.visible = visible;
+        self.message = message.into();
+        self.set_wrap_width(self.wrap_width);
+    }
+
+    /// Returns the row at which the line at index `line` into `lines` starts.
+    fn row_of(&self, line: usize) -> usize {
+        self.visible
+            .iter()
+            .zip(&self.message.lines)
+            .take_while(|(index, _)| **index < line)
+            .map(|(_, line)| match self.wrap_width {
+                Some(width) => wrapped_line_count(line, width as usize),
+                None => 1,
+            })
+            .sum()
+    }
+
     pub fn scroll_position(&self) -> (u16, u16) {
-        (self.vertical_position, 0)
+        (self.vertical_position, self.horizontal_position)
+    }
+}
+
+/// Number of rows `line` takes up when word-wrapped at `width` columns, mirroring the greedy
+/// wrapping done by `Paragraph`.
+fn wrapped_line_count(line: &Line, width: usize) -> usize {
+    if width == 0 {
+        return 1;
+    }
+
+    let text = line_text(line);
+
+    let mut rows = 1;
+    let mut column = 0;
+    for word in text.split_inclusive(' ') {
+        let word_width = word.trim_end_matches('\n').chars().count();
+        if column + word.trim_end().chars().count() > width && column > 0 {
+            rows += 1;
+            column = 0;
+        }
+        if word_width > width {
+            rows += (column + word_width - 1) / width;
+            column = (column + word_width) % width;
+        } else {
+            column += word_width;
+        }
+    }
+    rows
+}
+
+/// Returns `line` with the occurrences of `query` highlighted, splitting spans where needed.
+fn highlight_occurrences(line: &Line<'static>, query: &str) -> Line<'static> {
+    let text = line_text(line);
+    let occurrences = text
+        .match_indices(query)
+        .map(|(start, _)| start..start + query.len())
+        .collect::<Vec<_>>();
+    if occurrences.is_empty() {
+        return line.clone();
     }
+
+    let highlight = Style::default().bg(Color::LightYellow).fg(Color::Black);
+    let mut spans = vec![];
+    let mut offset = 0;
+    for span in &line.spans {
+        let content = span.content.as_ref();
+        let end = offset + content.len();
+        // Split at the bounds of occurrences overlapping the span
+        let mut bounds = vec![offset, end];
+        for occurrence in &occurrences {
+            bounds.extend(
+                [occurrence.start, occurrence.end]
+                    .into_iter()
+                    .filter(|bound| (offset..end).contains(bound)),
+            );
+        }
+        bounds.sort();
+        bounds.dedup();
+
+        for part in bounds.windows(2) {
+            let (start, part_end) = (part[0], part[1]);
+            let is_match = occurrences
+                .iter()
+                .any(|occurrence| occurrence.start <= start && part_end <= occurrence.end);
+            let style = if is_match {
+                span.style.patch(highlight)
+            } else {
+                span.style
+            };
+            spans.push(Span::styled(
+                content[start - offset..part_end - offset].to_string(),
+                style,
+            ));
+        }
+        offset = end;
+    }
+    Line::from(spans)
+}
+
+fn line_text(line: &Line) -> String {
+    line.spans
+        .iter()
+        .map(|span| span.content.as_ref())
+        .collect()
+}
+
+fn indentation(text: &str) -> usize {
+    text.len() - text.trim_start().len()
 }
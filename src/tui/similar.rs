@@ -0,0 +1,126 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use xml::reader::XmlEvent;
+use xml::ParserConfig;
+
+use crate::log_message::{LogMessage, MessageId};
+
+/// Stanzas less similar than this, in percent, aren't shown.
+const MIN_SIMILARITY: u8 = 60;
+
+/// The structure of a stanza, regardless of its text and attribute values.
+#[derive(Debug, Clone)]
+struct StanzaShape {
+    /// `{namespace}name` of the root element.
+    root: String,
+    /// `{namespace}name` of every element, the root included.
+    elements: HashSet<String>,
+    /// `{namespace}name@attribute` of every attribute of every element.
+    attributes: HashSet<String>,
+}
+
+impl StanzaShape {
+    /// Returns the shape of `message`, or `None` if it isn't a stanza or doesn't parse.
+    fn of(message: &LogMessage) -> Option<Self> {
+        message.stanza_kind()?;
+        let reader = ParserConfig::new()
+            .trim_whitespace(true)
+            .ignore_comments(true)
+            .create_reader(message.fields.message.as_bytes());
+
+        let mut root = None;
+        let mut elements = HashSet::new();
+        let mut attributes = HashSet::new();
+        for event in reader {
+            let XmlEvent::StartElement {
+                name,
+                attributes: element_attributes,
+                ..
+            } = event.ok()?
+            else {
+                continue;
+            };
+            let element = format!(
+                "{{{}}}{}",
+                name.namespace.unwrap_or_default(),
+                name.local_name
+            );
+            for attribute in element_attributes {
+                attributes.insert(format!("{element}@{}", attribute.name.local_name));
+            }
+            root.get_or_insert_with(|| element.clone());
+            elements.insert(element);
+        }
+
+        Some(StanzaShape {
+            root: root?,
+            elements,
+            attributes,
+        })
+    }
+
+    /// Similarity to `other` in percent: 0 for different root elements, otherwise how much
+    /// their elements and attributes overlap.
+    fn similarity(&self, other: &StanzaShape) -> u8 {
+        if self.root != other.root {
+            return 0;
+        }
+        let overlap = |a: &HashSet<String>, b: &HashSet<String>| match a.union(b).count() {
+            0 => 1.0,
+            union => a.intersection(b).count() as f64 / union as f64,
+        };
+        let similarity = (overlap(&self.elements, &other.elements)
+            + overlap(&self.attributes, &other.attributes))
+            / 2.0;
+        (similarity * 100.0).round() as u8
+    }
+}
+
+/// The stanzas structurally similar to a given one: same root element, and mostly the same
+/// child elements, namespaces and attributes.
+#[derive(Debug, Clone)]
+pub struct SimilarStanzas {
+    /// Root element and timestamp of the stanza the others were compared with.
+    pub root: String,
+    pub timestamp: DateTime<Utc>,
+    /// Similarity in percent of each similar message.
+    scores: HashMap<MessageId, u8>,
+}
+
+impl SimilarStanzas {
+    /// Compares `messages`, which may include `stanza` itself, with `stanza`. Returns `None`
+    /// if `stanza` isn't a stanza.
+    pub fn find<'a>(
+        stanza: &LogMessage,
+        messages: impl IntoIterator<Item = &'a LogMessage>,
+    ) -> Option<Self> {
+        let shape = StanzaShape::of(stanza)?;
+        let scores = messages
+            .into_iter()
+            .filter_map(|message| {
+                let score = shape.similarity(&StanzaShape::of(message)?);
+                (score >= MIN_SIMILARITY).then_some((message.id(), score))
+            })
+            .collect();
+        Some(SimilarStanzas {
+            root: shape
+                .root
+                .rsplit('}')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            timestamp: stanza.timestamp,
+            scores,
+        })
+    }
+
+    /// Similarity of `message` in percent, if it's similar.
+    pub fn score(&self, message: &LogMessage) -> Option<u8> {
+        self.scores.get(&message.id()).copied()
+    }
+
+    pub fn count(&self) -> usize {
+        self.scores.len()
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use ratatui::backend::Backend;
+use ratatui::layout::Rect;
+use ratatui::prelude::{Color, Line, Modifier, Span, Style};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use ratatui::Frame;
+
+use crate::log_message::{Level, LogMessage, StanzaDirection, StanzaKind};
+use crate::tui::popup::centered_rect;
+use crate::tui::timestamp::{format_delta, format_duration};
+
+/// Number of stanza types listed, most frequent first.
+const TOP_STANZA_TYPES: usize = 5;
+
+/// Summary of the messages of a span or target, to triage it before filtering into it, or of
+/// the messages passing the current filters.
+#[derive(Debug, Clone)]
+pub struct SpanStats {
+    title: String,
+    count: usize,
+    time_range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    levels: Vec<(Level, usize)>,
+    /// Number of stanzas received and sent.
+    directions: [usize; 2],
+    kinds: Vec<(StanzaKind, usize)>,
+    /// Stanza kinds with their `type` attribute, e.g. `iq get`, and their number.
+    stanza_types: Vec<(String, usize)>,
+    average_gap: Option<Duration>,
+}
+
+impl SpanStats {
+    pub fn new<'a>(title: String, messages: impl IntoIterator<Item = &'a LogMessage>) -> Self {
+        let mut count = 0;
+        let mut timestamps = vec![];
+        let mut levels = HashMap::<Level, usize>::new();
+        let mut directions = [0; 2];
+        let mut kinds = HashMap::<StanzaKind, usize>::new();
+        let mut stanza_types = HashMap::<String, usize>::new();
+
+        for message in messages {
+            count += 1;
+            timestamps.push(message.timestamp);
+            *levels.entry(message.level).or_default() += 1;
+            match message.fields.direction {
+                Some(StanzaDirection::In) => directions[0] += 1,
+                Some(StanzaDirection::Out) => directions[1] += 1,
+                None => (),
+            }
+            if let Some(kind) = message.stanza_kind() {
+                *kinds.entry(kind).or_default() += 1;
+                let stanza_type = match message.stanza_attribute("type") {
+                    Some(t) => format!("{kind} {t}"),
+                    None => kind.to_string(),
+                };
+                *stanza_types.entry(stanza_type).or_default() += 1;
+            }
+        }
+
+        timestamps.sort();
+        let time_range = timestamps.first().zip(timestamps.last());
+        let average_gap = time_range
+            .filter(|_| count > 1)
+            .map(|(first, last)| (*last - *first) / (count as i32 - 1));
+
+        let mut stanza_types = stanza_types.into_iter().collect::<Vec<_>>();
+        stanza_types.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        stanza_types.truncate(TOP_STANZA_TYPES);
+
+        SpanStats {
+            title,
+            count,
+            time_range: time_range.map(|(first, last)| (*first, *last)),
+            levels: Level::ALL
+                .iter()
+                .filter_map(|level| Some((*level, *levels.get(level)?)))
+                .collect(),
+            directions,
+            kinds: [
+                StanzaKind::Iq,
+                StanzaKind::Message,
+                StanzaKind::Presence,
+                StanzaKind::Other,
+            ]
+            .into_iter()
+            .filter_map(|kind| Some((kind, *kinds.get(&kind)?)))
+            .collect(),
+            stanza_types,
+            average_gap,
+        }
+    }
+
+    pub fn render<B: Backend>(&self, f: &mut Frame<B>, rect: Rect) {
+        let heading = |text: &'static str| {
+            Line::from(Span::styled(
+                text,
+                Style::default().add_modifier(Modifier::BOLD),
+            ))
+        };
+        let row = |label: String, value: String| {
+            Line::from(vec![
+                Span::styled(
+                    format!("  {label:14}"),
+                    Style::default().fg(Color::LightYellow),
+                ),
+                Span::raw(value),
+            ])
+        };
+
+        let mut lines = vec![row("messages".into(), self.count.to_string())];
+        if let Some((first, last)) = self.time_range {
+            lines.push(row("first".into(), first.format("%F %T%.3f").to_string()));
+            lines.push(row("last".into(), last.format("%F %T%.3f").to_string()));
+            let duration = format_duration(last - first);
+            lines.push(row("duration".into(), duration));
+            let minutes = (last - first).num_milliseconds() as f64 / 60_000.0;
+            if minutes > 0.0 {
+                let rate = format!("{:.1}", self.count as f64 / minutes);
+                lines.push(row("per minute".into(), rate));
+            }
+        }
+        if let Some(gap) = self.average_gap {
+            lines.push(row("average gap".into(), format_delta(gap)));
+        }
+
+        lines.push(Line::from(""));
+        lines.push(heading("Levels"));
+        for (level, count) in &self.levels {
+            lines.push(row(level.to_string(), count.to_string()));
+        }
+
+        if self.directions != [0, 0] {
+            lines.push(Line::from(""));
+            lines.push(heading("Directions"));
+            lines.push(row("in".into(), self.directions[0].to_string()));
+            lines.push(row("out".into(), self.directions[1].to_string()));
+        }
+
+        if !self.kinds.is_empty() {
+            lines.push(Line::from(""));
+            lines.push(heading("Stanza kinds"));
+            for (kind, count) in &self.kinds {
+                lines.push(row(kind.to_string(), count.to_string()));
+            }
+        }
+
+        if !self.stanza_types.is_empty() {
+            lines.push(Line::from(""));
+            lines.push(heading("Top stanza types"));
+            for (stanza_type, count) in &self.stanza_types {
+                lines.push(row(stanza_type.clone(), count.to_string()));
+            }
+        }
+
+        let title = &self.title;
+        let width = lines
+            .iter()
+            .map(Line::width)
+            .chain([title.chars().count()])
+            .max()
+            .unwrap_or_default() as u16
+            + 4;
+        let rect = centered_rect(width, lines.len() as u16 + 2, rect);
+
+        let paragraph = Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title(Span::styled(
+                    title,
+                    Style::default().add_modifier(Modifier::BOLD),
+                )),
+        );
+
+        f.render_widget(Clear, rect);
+        f.render_widget(paragraph, rect);
+    }
+}
@@ -1,5 +1,7 @@
 use ratatui::widgets::ListState;
 
+use crate::log_message::{LogMessage, MessageId};
+
 #[derive(Debug, Clone)]
 pub struct StatefulList<T> {
     pub state: ListState,
@@ -60,7 +62,29 @@ impl<T> StatefulList<T> {
         Some(&self.items[i])
     }
 
+    /// Moves the selection by `delta` items, stopping at the first and last item.
+    pub fn select_relative(&mut self, delta: isize) {
+        if self.items.is_empty() {
+            return;
+        }
+
+        let idx = self.state.selected().unwrap_or(0) as isize + delta;
+        self.state
+            .select(Some(idx.clamp(0, self.items.len() as isize - 1) as usize));
+    }
+
+    pub fn select_first(&mut self) {
+        self.state.select(Some(0));
+    }
+
     pub fn select_last(&mut self) {
         self.state.select(Some(self.items.len().saturating_sub(1)));
     }
 }
+
+impl StatefulList<LogMessage> {
+    /// Returns the index of the message identified by `id`, if it's in the list.
+    pub fn position_of(&self, id: MessageId) -> Option<usize> {
+        self.items.iter().position(|m| m.id() == id)
+    }
+}
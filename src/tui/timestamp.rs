@@ -0,0 +1,76 @@
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+
+/// Format of the timestamp in the detail pane title, with as many fractional digits as the
+/// log has, up to nanoseconds.
+pub const DETAIL_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+/// Default format of the timestamp column in the message list.
+pub const DEFAULT_LIST_TIMESTAMP_FORMAT: &str = "%H:%M:%S%.3f";
+
+/// How message timestamps are shown.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeDisplay {
+    #[default]
+    Absolute,
+    /// Age of the message relative to now, e.g. "3s ago".
+    Relative,
+    /// Time elapsed since the previous message, e.g. "+120ms".
+    Delta,
+}
+
+impl TimeDisplay {
+    pub fn next(self) -> Self {
+        match self {
+            TimeDisplay::Absolute => TimeDisplay::Relative,
+            TimeDisplay::Relative => TimeDisplay::Delta,
+            TimeDisplay::Delta => TimeDisplay::Absolute,
+        }
+    }
+
+    /// Formats `timestamp` with the strftime-style `format`, or as its age at `now`. Deltas
+    /// need the previous message, so they're formatted as absolute times here and with
+    /// `format_delta` where the previous message is known.
+    pub fn format(self, timestamp: DateTime<Utc>, now: DateTime<Utc>, format: &str) -> String {
+        match self {
+            TimeDisplay::Absolute | TimeDisplay::Delta => timestamp.format(format).to_string(),
+            TimeDisplay::Relative if timestamp > now => "in the future".to_string(),
+            TimeDisplay::Relative => format!("{} ago", format_duration(now - timestamp)),
+        }
+    }
+}
+
+/// Formats the time elapsed between two messages, e.g. "+120ms" or "+1.25s".
+pub fn format_delta(delta: Duration) -> String {
+    let sign = if delta < Duration::zero() { "-" } else { "+" };
+    let delta = delta.abs();
+    if delta < Duration::seconds(1) {
+        format!("{sign}{}ms", delta.num_milliseconds())
+    } else if delta < Duration::minutes(1) {
+        format!("{sign}{:.2}s", delta.num_milliseconds() as f64 / 1000.0)
+    } else {
+        format!("{sign}{}", format_duration(delta))
+    }
+}
+
+/// Formats a non-negative `duration` with its two most significant units, e.g. "2m 5s".
+pub fn format_duration(duration: Duration) -> String {
+    if duration < Duration::seconds(1) {
+        return format!("{}ms", duration.num_milliseconds());
+    }
+
+    let units = [
+        (duration.num_days(), "d"),
+        (duration.num_hours() % 24, "h"),
+        (duration.num_minutes() % 60, "m"),
+        (duration.num_seconds() % 60, "s"),
+    ];
+    let parts = units
+        .iter()
+        .skip_while(|(value, _)| *value == 0)
+        .take(2)
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{value}{unit}"))
+        .collect::<Vec<_>>();
+    parts.join(" ")
+}
@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+use ratatui::backend::Backend;
+use ratatui::layout::Rect;
+use ratatui::prelude::{Color, Style};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
+use ratatui::Frame;
+
+/// How long a toast stays on screen.
+const TOAST_DURATION: Duration = Duration::from_secs(3);
+
+/// The outcome of an operation, shown in the bottom right corner for a few seconds, without
+/// taking the keys away from the rest of the app.
+#[derive(Debug, Clone)]
+pub struct Toast {
+    message: Result<String, String>,
+    shown_at: Instant,
+}
+
+impl Toast {
+    pub fn new(message: Result<String, String>) -> Self {
+        Toast {
+            message,
+            shown_at: Instant::now(),
+        }
+    }
+
+    /// When the toast disappears.
+    pub fn deadline(&self) -> Instant {
+        self.shown_at + TOAST_DURATION
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.deadline()
+    }
+
+    pub fn render<B: Backend>(&self, f: &mut Frame<B>, rect: Rect) {
+        let (text, color) = match &self.message {
+            Ok(message) => (message, Color::Green),
+            Err(err) => (err, Color::Red),
+        };
+        let width = (text.chars().count() as u16 + 4).min(rect.width);
+        // Above the bottom bar
+        let toast = Rect {
+            x: rect.right().saturating_sub(width + 1).max(rect.x),
+            y: rect.bottom().saturating_sub(4).max(rect.y),
+            width,
+            height: 3.min(rect.height),
+        };
+
+        let paragraph = Paragraph::new(text.as_str())
+            .style(Style::default().fg(color))
+            .block(Block::default().borders(Borders::ALL));
+        f.render_widget(Clear, toast);
+        f.render_widget(paragraph, toast);
+    }
+}
@@ -0,0 +1,152 @@
+use std::io;
+use std::iter::once;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{format_err, Result};
+use clap::Args;
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::Backend;
+use ratatui::Terminal;
+
+use crate::export::{export_messages, ExportFormat};
+use crate::log_message::{LogMessage, StanzaDirection};
+use crate::tui::app::App;
+
+#[derive(Args, Debug, Clone)]
+pub struct ViewerOptions {
+    /// Command used to open the selected stanza with `o`, e.g. `bat` or `firefox`. The path of
+    /// a temporary file is appended to it. Defaults to `$PAGER`, or `less`.
+    #[arg(long)]
+    pub viewer: Option<String>,
+    /// Format of the file passed to `--viewer`.
+    #[arg(long, value_enum, default_value_t)]
+    pub viewer_format: ExportFormat,
+}
+
+impl ViewerOptions {
+    fn command(&self) -> String {
+        self.viewer
+            .clone()
+            .or_else(|| std::env::var("PAGER").ok())
+            .unwrap_or_else(|| "less".to_string())
+    }
+}
+
+/// Writes the selected message to a temporary file and opens it with the configured viewer,
+/// handing the terminal over to it until it exits.
+pub fn open_in_viewer<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &App,
+    options: &ViewerOptions,
+) -> Result<()> {
+    let Some(message) = app.selected_message() else {
+        return Ok(());
+    };
+
+    let path = std::env::temp_dir().join(format!(
+        "log-parser-{}.{}",
+        std::process::id(),
+        options.viewer_format.extension()
+    ));
+    export_messages(
+        once(&message),
+        options.viewer_format,
+        app.redact(),
+        &app.theme(),
+        &path,
+    )?;
+
+    run_suspended(terminal, &options.command(), &path)
+}
+
+/// Writes the selected stanza, pretty-printed below a comment with its metadata, to a
+/// temporary file and opens it with `$VISUAL` or `$EDITOR`, or `vi`, handing the terminal over
+/// to it until it exits. The file is kept so that notes saved in it aren't lost.
+pub fn open_in_editor<B: Backend>(terminal: &mut Terminal<B>, app: &App) -> Result<()> {
+    let Some(message) = app.selected_message() else {
+        return Ok(());
+    };
+    let message = match app.redact() {
+        true => message.redacted()?,
+        false => message,
+    };
+
+    let path = std::env::temp_dir().join(format!(
+        "log-parser-{}-{}.xml",
+        std::process::id(),
+        message.timestamp.format("%H%M%S%.f"),
+    ));
+    let contents = format!(
+        "{}\n{}\n",
+        metadata_comment(&message),
+        message.pretty_printed_xml()?
+    );
+    std::fs::write(&path, contents)?;
+
+    let command = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    run_suspended(terminal, &command, &path)
+}
+
+/// Describes where and when `message` was logged in an XML comment.
+fn metadata_comment(message: &LogMessage) -> String {
+    let direction = match message.fields.direction {
+        Some(StanzaDirection::In) => "received",
+        Some(StanzaDirection::Out) => "sent",
+        None => "not a stanza",
+    };
+    let spans = match (&message.spans, &message.span) {
+        (Some(spans), _) if !spans.is_empty() => spans
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>()
+            .join(" > "),
+        (_, Some(span)) => span.name.clone(),
+        _ => "none".to_string(),
+    };
+    let fields = [
+        ("time", message.timestamp.format("%F %T%.f UTC").to_string()),
+        ("level", message.level.to_string()),
+        ("target", message.target.clone()),
+        ("direction", direction.to_string()),
+        ("spans", spans),
+    ];
+    let lines = fields
+        .iter()
+        .map(|(name, value)| format!("  {name:<10} {value}\n"))
+        .collect::<String>();
+    format!("<!--\n{lines}-->")
+}
+
+/// Runs `command` with `path` appended after leaving the alternate screen, and restores the
+/// terminal once it exits.
+fn run_suspended<B: Backend>(terminal: &mut Terminal<B>, command: &str, path: &Path) -> Result<()> {
+    let mut args = command.split_whitespace();
+    let program = args
+        .next()
+        .ok_or_else(|| format_err!("No command configured to open {}", path.display()))?;
+
+    disable_raw_mode()?;
+    // Mouse capture fails where the terminal doesn't support it, which isn't worth stopping for
+    let _ = execute!(io::stdout(), DisableMouseCapture);
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+
+    let status = Command::new(program).args(args).arg(path).status();
+
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let _ = execute!(io::stdout(), EnableMouseCapture);
+    terminal.clear()?;
+
+    let status = status.map_err(|err| format_err!("Failed to run {program}: {err}"))?;
+    if !status.success() {
+        return Err(format_err!("{program} exited with {status}"));
+    }
+    Ok(())
+}